@@ -0,0 +1,90 @@
+//
+// the Internet checksum (RFC 1071): the one's complement
+// of the one's complement sum of 16-bit words, used by
+// IP, UDP and ICMP.
+//
+// the sum comes out the same whichever order the bytes of
+// each word are in (RFC 1071 section 2(B)), so words are
+// added in host order and the result can be stored as is.
+//
+
+#include "types.h"
+#include "param.h"
+#include "riscv.h"
+#include "spinlock.h"
+#include "defs.h"
+
+// add len bytes at addr to a running one's complement sum,
+// for checksums over data that isn't contiguous, such as a
+// pseudo-header and a packet. every piece but the last
+// must have an even length.
+uint32
+cksum_partial(uint32 sum, const void *addr, int len)
+{
+  const uint16 *w = addr;
+
+  for(; len > 1; len -= 2)
+    sum += *w++;
+  if(len == 1){
+    // pad the odd byte with a zero, without reading past it.
+    uint16 last = 0;
+    *(uint8 *)&last = *(const uint8 *)w;
+    sum += last;
+  }
+  // keep the carries from overflowing the accumulator.
+  return (sum & 0xffff) + (sum >> 16);
+}
+
+// fold a running sum into a 16-bit one's complement checksum.
+uint16
+cksum_fold(uint32 sum)
+{
+  sum = (sum & 0xffff) + (sum >> 16);
+  sum += (sum >> 16);
+  return ~sum;
+}
+
+// the checksum of len contiguous bytes at addr. over a header
+// that includes its own (correct) checksum field, it's 0.
+uint16
+in_cksum(const void *addr, int len)
+{
+  return cksum_fold(cksum_partial(0, addr, len));
+}
+
+// the new checksum after one 16-bit word covered by sum
+// changes from old to new, without summing everything
+// again (RFC 1624, equation 3: HC' = ~(~HC + ~m + m')).
+uint16
+cksum_update(uint16 sum, uint16 old, uint16 new)
+{
+  uint32 s = (uint16)~sum + (uint16)~old + new;
+  return cksum_fold(s);
+}
+
+// check the routines above against the example in
+// RFC 1071 section 3. returns 0, or -1 on a mismatch.
+int
+cksum_selftest(void)
+{
+  uint8 data[8] = { 0x00, 0x01, 0xf2, 0x03, 0xf4, 0xf5, 0xf6, 0xf7 };
+  uint16 sum = in_cksum(data, sizeof(data));
+  uint8 *b = (uint8 *)&sum;
+
+  // the sum is ddf2, so the checksum is 220d in network order.
+  if(b[0] != 0x22 || b[1] != 0x0d)
+    return -1;
+
+  // odd lengths pad with a zero byte.
+  if(in_cksum(data, 7) != in_cksum((uint8[8]){ 0x00, 0x01, 0xf2, 0x03, 0xf4, 0xf5, 0xf6, 0x00 }, 8))
+    return -1;
+
+  // changing a word incrementally agrees with starting over.
+  uint16 *w = (uint16 *)data;
+  uint16 old = w[1];
+  w[1] = 0x1234;
+  if(cksum_update(sum, old, w[1]) != in_cksum(data, sizeof(data)))
+    return -1;
+
+  return 0;
+}
@@ -2,16 +2,27 @@
 typedef unsigned long size_t;
 typedef long int off_t;
 #endif
+struct bind_ring_buffer;
+struct tcpcb;
+struct rawsock;
+struct pktfilter;
+struct route;
+struct rxinfo;
+struct netstat;
 struct buf;
 struct context;
 struct file;
 struct inode;
+struct ip;
+struct nicstats;
 struct pipe;
 struct proc;
+struct rwlock;
 struct spinlock;
 struct sleeplock;
 struct stat;
 struct superblock;
+struct udp;
 
 // bio.c
 void            binit(void);
@@ -21,6 +32,13 @@ void            bwrite(struct buf*);
 void            bpin(struct buf*);
 void            bunpin(struct buf*);
 
+// cksum.c
+uint32          cksum_partial(uint32, const void*, int);
+uint16          cksum_fold(uint32);
+uint16          in_cksum(const void*, int);
+uint16          cksum_update(uint16, uint16, uint16);
+int             cksum_selftest(void);
+
 // console.c
 void            consoleinit(void);
 void            consoleintr(int);
@@ -65,7 +83,10 @@ void            ramdiskrw(struct buf*);
 
 // kalloc.c
 void*           kalloc(void);
+void*           kzalloc(void);
+void*           kclone(const void*, int);
 void            kfree(void *);
+void            kdup(void *);
 void            kinit(void);
 
 // log.c
@@ -84,6 +105,14 @@ int             pipewrite(struct pipe*, uint64, int);
 int            printf(char*, ...) __attribute__ ((format (printf, 1, 2)));
 void            panic(char*) __attribute__((noreturn));
 void            printfinit(void);
+void            hexdump(const void*, int);
+void            klog(int, char*, ...) __attribute__ ((format (printf, 2, 3)));
+int             setloglevel(int);
+int             klogging(int);
+#define errorf(...)     klog(LOG_ERROR, __VA_ARGS__)
+#define warnf(...)      klog(LOG_WARN, __VA_ARGS__)
+#define infof(...)      klog(LOG_INFO, __VA_ARGS__)
+#define debugf(...)     klog(LOG_DEBUG, __VA_ARGS__)
 
 // proc.c
 int             cpuid(void);
@@ -103,6 +132,9 @@ void            procinit(void);
 void            scheduler(void) __attribute__((noreturn));
 void            sched(void);
 void            sleep(void*, struct spinlock*);
+int             sleeptimeout(void*, struct spinlock*, uint);
+int             sleeptimeout_selftest(void);
+void            timeoutwakeup(void);
 void            userinit(void);
 int             wait(uint64);
 void            wakeup(void*);
@@ -114,8 +146,17 @@ void            procdump(void);
 // swtch.S
 void            swtch(struct context*, struct context*);
 
+// rwlock.c
+void            acquireread(struct rwlock*);
+void            acquirewrite(struct rwlock*);
+void            initrwlock(struct rwlock*, char*);
+void            releaseread(struct rwlock*);
+void            releasewrite(struct rwlock*);
+int             rwlock_selftest(void);
+
 // spinlock.c
 void            acquire(struct spinlock*);
+int             tryacquire(struct spinlock*);
 int             holding(struct spinlock*);
 void            initlock(struct spinlock*, char*);
 void            release(struct spinlock*);
@@ -230,10 +271,66 @@ void            pci_init();
 // e1000.c
 void            e1000_init(uint32 *);
 void            e1000_intr(void);
+int             e1000_macaddr(uint8 *);
 int             e1000_transmit(char *, int);
+int             e1000_transmit_batch(char **, int *, int);
+int             e1000_transmit_copy(const char *, int);
+int             e1000_poll_tx(void);
+int             e1000_tx_wait(int);
+void            e1000_stats(struct nicstats*);
+int             e1000_link_status(void);
+int             e1000_rx_selftest(void);
+int             e1000_tx_copy_selftest(void);
+void            e1000_join_multicast(uint8 *);
 
 // net.c
 void            netinit(void);
+uint32          local_ip(void);
 void            net_rx(char *buf, int len);
+void            eth_register(int, int, int (*)(char*, int, struct rxinfo*));
+void            ip_register(int, int (*)(char*, int, struct rxinfo*));
+void            net_rx_flush(void);
+char*           netstrerror(int);
+void            arp_sweep(void);
+int             arp_resolve(uint32, uint8*);
+void            net_set_rx_filter(int (*)(struct ip *, struct udp *));
+void            net_exit(int);
+int             sockalloc(struct file**, int);
+void            sockclose(struct bind_ring_buffer*);
+int             sockread(struct bind_ring_buffer*, uint64, int);
+int             sockwrite(struct bind_ring_buffer*, uint64, int);
+int             rawalloc(struct file**, int, uint32);
+void            rawclose(struct rawsock*);
+int             rawread(struct rawsock*, uint64, int);
+int             rawwrite(struct rawsock*, uint64, int);
+int             raw_setfilter(struct rawsock*, struct pktfilter*);
+int             sock_setfilter(struct bind_ring_buffer*, struct pktfilter*);
+uint32          ip_pseudo_sum(uint32, uint32, int, int);
+uint16          ip_next_id(void);
+void*           eth_build(char*, uint8*, int);
+void*           ip_build(void*, uint32, int, int, uint16, int, int);
+int             route(uint32, struct route*);
+int             route_output(struct route*, char*, int);
+
+// filter.c
+int             pf_run(struct pktfilter*, const char*, int);
+int             setfilter(struct file*, uint64, int);
+
+// pcap.c
+void            pcapinit(void);
+void            pcap_tap(const char*, int, int);
+int             pcap_setfilter(struct pktfilter*);
+
+// tcp.c
+void            tcpinit(void);
+void            tcp_timer(void);
+int             tcplisten(struct file**, int);
+int             tcpaccept(struct file*, struct file**);
+int             tcpconnect(struct file**, uint32, int);
+void            tcpclose(struct tcpcb*);
+int             tcpread(struct tcpcb*, uint64, int);
+int             tcpwrite(struct tcpcb*, uint64, int);
+int             tcp_netstat(struct netstat*, int);
+int             tcp_selftest(void);
 
 #endif
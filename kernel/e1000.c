@@ -6,21 +6,58 @@
 #include "proc.h"
 #include "defs.h"
 #include "e1000_dev.h"
+#include "net.h"
 
 #define TX_RING_SIZE 16
 static struct tx_desc tx_ring[TX_RING_SIZE] __attribute__((aligned(16)));
 static char *tx_bufs[TX_RING_SIZE];
+static int tx_reap; // next descriptor for e1000_reap_tx() to check
 
 #define RX_RING_SIZE 16
 static struct rx_desc rx_ring[RX_RING_SIZE] __attribute__((aligned(16)));
 static char *rx_bufs[RX_RING_SIZE];
+static int rx_next; // next descriptor for e1000_recv() to check
+#define RX_BUF_SIZE 2048 // must match E1000_RCTL_SZ_* below
 
 // remember where the e1000's registers live.
 static volatile uint32 *regs;
 
+// the e1000's ethernet address.
+static uint8 mac[ETHADDR_LEN];
+
 struct spinlock e1000_lock_tx;
 struct spinlock e1000_lock_rx;
 
+// tx_* counters are protected by e1000_lock_tx, rx_* by e1000_lock_rx.
+static struct nicstats stats;
+
+static void e1000_recv(void);
+
+// release e1000_lock_rx, for holders other than e1000_recv().
+// an interrupt that came in meanwhile gave up on the lock, its
+// ICR already acknowledged, so drain whatever it left behind.
+static void
+e1000_unlock_rx(void)
+{
+  release(&e1000_lock_rx);
+  if (rx_ring[rx_next].status & E1000_RXD_STAT_DD)
+    e1000_recv();
+}
+
+// read a 16-bit word from the e1000's EEPROM.
+// returns the word, or -1 if the read doesn't complete.
+static int
+e1000_read_eeprom(uint8 word)
+{
+  regs[E1000_EERD] = (word << E1000_EERD_ADDR_SHIFT) | E1000_EERD_START;
+  for (int i = 0; i < 100000; i++) {
+    uint32 eerd = regs[E1000_EERD];
+    if (eerd & E1000_EERD_DONE)
+      return (eerd >> E1000_EERD_DATA_SHIFT) & 0xffff;
+  }
+  return -1;
+}
+
 // called by pci_init().
 // xregs is the memory address at which the
 // e1000's registers are mapped.
@@ -65,12 +102,32 @@ e1000_init(uint32 *xregs)
     panic("e1000");
   regs[E1000_RDH] = 0;
   regs[E1000_RDT] = RX_RING_SIZE - 1;
+  rx_next = 0;
   regs[E1000_RDLEN] = sizeof(rx_ring);
 
-  // filter by qemu's MAC address, 52:54:00:12:34:56
+  // filter by our MAC address (qemu's default is 52:54:00:12:34:56).
   // 3.2.1 packet filtering.
-  regs[E1000_RA] = 0x12005452; // low
-  regs[E1000_RA+1] = 0x5634 | (1<<31); // high
+  // qemu usually loads RAL0/RAH0 from the EEPROM at reset;
+  // if it didn't, read the address out of the EEPROM ourselves.
+  uint32 ral = regs[E1000_RA];
+  uint32 rah = regs[E1000_RA+1];
+  if ((rah & E1000_RAH_AV) == 0) {
+    int w0 = e1000_read_eeprom(0);
+    int w1 = e1000_read_eeprom(1);
+    int w2 = e1000_read_eeprom(2);
+    if (w0 < 0 || w1 < 0 || w2 < 0) {
+      printf("e1000: can't read MAC address from EEPROM, using qemu's default\n");
+      w0 = 0x5452; w1 = 0x1200; w2 = 0x5634;
+    }
+    ral = w0 | (w1 << 16);
+    rah = w2;
+  }
+  for (i = 0; i < 4; i++)
+    mac[i] = ral >> (8*i);
+  mac[4] = rah;
+  mac[5] = rah >> 8;
+  regs[E1000_RA] = ral; // low
+  regs[E1000_RA+1] = (rah & 0xffff) | E1000_RAH_AV; // high
   // multicast table
   for (int i = 0; i < 4096/32; i++)
     regs[E1000_MTA + i] = 0;
@@ -88,33 +145,73 @@ e1000_init(uint32 *xregs)
     E1000_RCTL_SZ_2048 |             // 2048-byte rx buffers
     E1000_RCTL_SECRC;                // strip CRC
   
-  // ask e1000 for receive interrupts.
+  // ask e1000 for receive interrupts, and for transmit ones,
+  // which e1000_tx_wait() sleeps for.
   regs[E1000_RDTR] = 0; // interrupt after every received packet (no timer)
   regs[E1000_RADV] = 0; // interrupt after every packet (no timer)
-  regs[E1000_IMS] = (1 << 7); // RXDW -- Receiver Descriptor Write Back
+  regs[E1000_IMS] = (1 << 7) | // RXDW -- Receiver Descriptor Write Back
+    (1 << 6) |                  // RXO -- Receiver Overrun, e.g. out of descriptors
+    (1 << 0);                   // TXDW -- Transmit Descriptor Written Back
 }
 
-// Node: ownership of `buf` is transferred to us
+// copy the e1000's ethernet address into m.
+// returns 0, or -1 if there's no e1000.
 int
-e1000_transmit(char *buf, int len)
+e1000_macaddr(uint8 *m)
 {
-  int rc = 0;
-
-  acquire(&e1000_lock_tx);
+  if (regs == 0)
+    return -1;
+  memmove(m, mac, ETHADDR_LEN);
+  return 0;
+}
 
-  int next_idx = regs[E1000_TDT]; // ring position
-  struct tx_desc *desc = &tx_ring[next_idx];
-  //printf("---\ne1000 transmit; \nlen=%d\ntx_idx=%d\nstatus=0x%x\n\n", len,next_idx, desc->status); 
-  if ((desc->status & E1000_TXD_STAT_DD) != E1000_TXD_STAT_DD) {
-    // a previous transition is alreay in flight.
-    // TODO: from what i understand in 2.8, this means this descriptor is now owned by the hardware,
-    // shouldn't I try the next descriptor to see if it's available?
-    printf("warning: a previous transition is already in flight\nidx=%d\n", next_idx);
+// free the buffers of transmitted descriptors, oldest first.
+// only descriptors with DD set are done with; the e1000 may
+// still be reading the buffer of any other.
+// caller holds e1000_lock_tx.
+static int
+e1000_reap_tx(void)
+{
+  int n = 0;
 
-    rc = 1;
-    goto out;
+  while (tx_reap != regs[E1000_TDT]) {
+    struct tx_desc *desc = &tx_ring[tx_reap];
+    if ((desc->status & E1000_TXD_STAT_DD) == 0)
+      break; // still owned by the e1000, and so is everything after it.
+    __sync_synchronize();
+    if (desc->addr != 0) {
+      kfree((void*)desc->addr);
+      desc->addr = 0;
+      n++;
+    }
+    tx_reap = (tx_reap + 1) % TX_RING_SIZE;
   }
-  
+  stats.tx_reclaimed += n;
+
+  return n;
+}
+
+// is descriptor idx free for a new frame?
+// caller holds e1000_lock_tx.
+static int
+e1000_tx_free(int idx)
+{
+  if ((tx_ring[idx].status & E1000_TXD_STAT_DD) != E1000_TXD_STAT_DD)
+    return 0;
+  // the e1000 writes DD after it's done with the descriptor's buffer;
+  // don't let reads of desc->addr move before the status check.
+  __sync_synchronize();
+  return 1;
+}
+
+// point descriptor idx at buf, freeing whatever it sent last.
+// the e1000 doesn't see it until TDT moves past it.
+// caller holds e1000_lock_tx.
+static void
+e1000_tx_fill(int idx, char *buf, int len)
+{
+  struct tx_desc *desc = &tx_ring[idx];
+
   if (desc->addr != 0) {
     //printf("previous descriptor was set, freeing the buffer...\n");
     kfree((void*)desc->addr);
@@ -123,27 +220,358 @@ e1000_transmit(char *buf, int len)
   desc->addr = (uint64)buf;
   desc->length = len;
   desc->cmd = E1000_TXD_CMD_RS | E1000_TXD_CMD_EOP;
-  __sync_synchronize();
-  regs[E1000_TDT] = (regs[E1000_TDT] + 1) % TX_RING_SIZE;
-  
-  
-  out:
-    release(&e1000_lock_tx);
+  // clear DD so e1000_tx_free() sees this descriptor as in flight
+  // until the e1000 reports it done.
+  desc->status = 0;
+  stats.tx_packets++;
+}
+
+// Node: ownership of `buf` is transferred to us on success.
+// returns 0, or -NET_ERINGBUSY if the next descriptor is
+// still in flight, in which case the caller keeps `buf`.
+int
+e1000_transmit(char *buf, int len)
+{
+  return e1000_transmit_batch(&buf, &len, 1) == 1 ? 0 : -NET_ERINGBUSY;
+}
+
+// queue the n frames bufs[i] (of lens[i] bytes) on consecutive
+// descriptors, then tell the e1000 about all of them with a
+// single write to TDT. stops early at a descriptor that's still
+// in flight.
+// returns the number of frames queued; ownership of those
+// buffers is transferred to us, and the caller keeps the rest.
+int
+e1000_transmit_batch(char **bufs, int *lens, int n)
+{
+  int i;
+
+  acquire(&e1000_lock_tx);
+
+  // release finished buffers now rather than when their
+  // descriptors next come round.
+  e1000_reap_tx();
+
+  int tail = regs[E1000_TDT]; // ring position
+  for (i = 0; i < n; i++) {
+    int idx = (tail + i) % TX_RING_SIZE;
+    //printf("---\ne1000 transmit; \nlen=%d\ntx_idx=%d\nstatus=0x%x\n\n", lens[i], idx, tx_ring[idx].status); 
+    // leave one descriptor unused: TDT == TDH means the ring is
+    // empty, so the tail must never catch up with the oldest
+    // descriptor the e1000 may still be working on.
+    if ((idx + 1) % TX_RING_SIZE == tx_reap || !e1000_tx_free(idx)) {
+      // a previous transition is alreay in flight.
+      // TODO: from what i understand in 2.8, this means this descriptor is now owned by the hardware,
+      // shouldn't I try the next descriptor to see if it's available?
+      warnf("a previous transition is already in flight\nidx=%d\n", idx);
+      stats.tx_busy++;
+      break;
+    }
+    e1000_tx_fill(idx, bufs[i], lens[i]);
+    pcap_tap(bufs[i], lens[i], PCAP_OUT);
+  }
+
+  if (i > 0) {
+    // the descriptors must be in memory before the e1000 sees the new tail.
+    __sync_synchronize();
+    regs[E1000_TDT] = (tail + i) % TX_RING_SIZE;
+  }
+
+  release(&e1000_lock_tx);
+  return i;
+}
+
+// like e1000_transmit(), but the caller keeps ownership of `buf`:
+// the bytes are copied into a freshly allocated page which the
+// ring frees once the descriptor is reused.
+int
+e1000_transmit_copy(const char *buf, int len)
+{
+  int rc;
+
+  if (len <= 0 || len > PGSIZE)
+    return -NET_EINVAL;
+
+  char *copy = kclone(buf, len);
+  if (copy == 0) {
+    warnf("e1000_transmit_copy: kalloc failed\n");
+    return -NET_ENOMEM;
+  }
+
+  if ((rc = e1000_transmit(copy, len)) != 0) {
+    // the ring didn't take the copy, so it's still ours to free.
+    kfree(copy);
     return rc;
+  }
+
+  return 0;
+}
+
+// check e1000_transmit_copy() without putting a frame on the
+// wire: it refuses lengths that don't fit a page before taking
+// a descriptor, and what it hands the ring is a copy in a page
+// of its own, so the caller's buffer stays the caller's to free.
+// returns 0, or -1 on failure.
+int
+e1000_tx_copy_selftest(void)
+{
+  int len = sizeof(struct eth) + 46; // the shortest ethernet payload
+  int rc = 0;
+
+  char *buf = kzalloc();
+  if (buf == 0)
+    return -1;
+  for (int i = 0; i < len; i++)
+    buf[i] = i;
+
+  if (e1000_transmit_copy(buf, 0) != -NET_EINVAL ||
+      e1000_transmit_copy(buf, PGSIZE + 1) != -NET_EINVAL)
+    rc = -1;
+  acquire(&e1000_lock_tx);
+  for (int i = 0; i < TX_RING_SIZE; i++)
+    if (tx_ring[i].addr == (uint64)buf)
+      rc = -1;
+  release(&e1000_lock_tx);
+
+  char *copy = kclone(buf, len);
+  if (copy == 0 || copy == buf || memcmp(copy, buf, len) != 0)
+    rc = -1;
+  for (int i = 0; i < len; i++)
+    if (buf[i] != (char)i)
+      rc = -1;
+
+  if (copy)
+    kfree(copy);
+  kfree(buf);
+  return rc;
+}
+
+// free the buffers of transmitted descriptors
+// without waiting for e1000_transmit() to reuse them.
+// needs no interrupt, so it can be called from any periodic context.
+// returns the number of descriptors reclaimed.
+int
+e1000_poll_tx(void)
+{
+  int n;
+
+  acquire(&e1000_lock_tx);
+  n = e1000_reap_tx();
+  if (n > 0)
+    wakeup(&tx_reap);
+  release(&e1000_lock_tx);
+
+  return n;
+}
+
+// wait up to n ticks for the e1000 to finish sending a frame,
+// so that a full ring has a descriptor free again.
+// returns 0, or -1 if none came free in time or the caller
+// was killed.
+int
+e1000_tx_wait(int n)
+{
+  int rc = 0;
+
+  acquire(&e1000_lock_tx);
+  if (e1000_reap_tx() == 0 && sleeptimeout(&tx_reap, &e1000_lock_tx, n))
+    rc = e1000_reap_tx() > 0 ? 0 : -1;
+  release(&e1000_lock_tx);
+
+  if (killed(myproc()))
+    rc = -1;
+  return rc;
+}
+
+// hand the descriptors processed since tail back to the e1000,
+// oldest first, giving any whose buffer went up to net_rx() a
+// new page from alloc. stops at the first one alloc can't back,
+// so the e1000 is never handed address 0; the rest wait for the
+// next call. the last descriptor processed, just before next, is
+// always kept back, since the e1000 takes head == tail to mean
+// it has no descriptors, not all of them.
+// caller holds e1000_lock_rx.
+//
+// returns the new tail, for RDT.
+static int
+e1000_rx_refill(struct rx_desc *ring, int tail, int next, void *(*alloc)(void))
+{
+  for (; (tail + 1) % RX_RING_SIZE != next; tail = (tail + 1) % RX_RING_SIZE) {
+    struct rx_desc *desc = &ring[tail];
+    if (desc->addr == 0) {
+      char *buf = alloc();
+      if (buf == 0)
+        break;
+      // the e1000 would DMA to whatever address it's given,
+      // so never hand it a stray pointer.
+      if ((uint64)buf % PGSIZE != 0)
+        panic("e1000_rx_refill");
+      desc->addr = (uint64)buf;
+    }
+    desc->status = 0;
+  }
+  return tail;
 }
 
+// refill rx_ring and move the tail past what's been refilled.
+// caller holds e1000_lock_rx.
 static void
-e1000_recv(void)
+e1000_rx_refill_ring(void)
 {
-  // TODO: 3.2.3 Software must read multiple descriptors to determine the complete
-  // length for packets that span multiple receive buffers
-  
-  // loop because multiple packets could be ready and not just one.
+  int tail = e1000_rx_refill(rx_ring, regs[E1000_RDT], rx_next, kalloc);
+  if ((tail + 1) % RX_RING_SIZE != rx_next)
+    stats.rx_alloc_failures++;
+
+  // the new buffers and cleared status must be visible before
+  // the tail write hands the descriptors back to the e1000.
+  __sync_synchronize();
+  regs[E1000_RDT] = tail;
+}
+
+static int rx_selftest_pages; // how many more pages rx_selftest_alloc() gives out
+
+static void *
+rx_selftest_alloc(void)
+{
+  if (rx_selftest_pages == 0)
+    return 0;
+  rx_selftest_pages--;
+  return kalloc();
+}
+
+// run e1000_rx_refill() over a pretend ring whose allocator runs
+// dry partway, and check that it stops at the first descriptor it
+// can't back, and that descriptors which kept their buffer don't
+// use up pages. returns 0, or -1 on failure.
+int
+e1000_rx_selftest(void)
+{
+  struct rx_desc ring[RX_RING_SIZE];
+  int rc = -1;
+
+  // serializes use of rx_selftest_pages.
   acquire(&e1000_lock_rx);
 
+  // pretend descriptors 0..9 have been processed, and all but 5
+  // had their buffers passed up.
+  memset(ring, 0, sizeof(ring));
+  if ((ring[5].addr = (uint64)kalloc()) == 0)
+    goto out;
+
+  rx_selftest_pages = 3;
+  int tail = e1000_rx_refill(ring, 0, 10, rx_selftest_alloc);
+  if (tail != 3 || ring[3].addr != 0)
+    goto out;
+
+  rx_selftest_pages = 0;
+  if (e1000_rx_refill(ring, tail, 10, rx_selftest_alloc) != 3)
+    goto out;
+
+  // 3, 4, 6, 7, and 8; 9 is kept back.
+  rx_selftest_pages = 5;
+  tail = e1000_rx_refill(ring, tail, 10, rx_selftest_alloc);
+  if (tail != 9 || rx_selftest_pages != 0)
+    goto out;
+
+  rc = 0;
+  for (int i = 0; i < tail; i++)
+    if (ring[i].addr == 0)
+      rc = -1;
+
+out:
+  for (int i = 0; i < RX_RING_SIZE; i++)
+    if (ring[i].addr)
+      kfree((char*)ring[i].addr);
+  e1000_unlock_rx();
+  return rc;
+}
+
+// a packet bigger than one receive buffer is spread over
+// consecutive descriptors, and only the last has EOP set
+// (3.2.3). copy the pieces, starting at descriptor idx, into
+// one fresh page for net_rx(). the descriptors keep their
+// buffers, since their contents have been copied out, and
+// e1000_rx_refill_ring() hands them back.
+// caller holds e1000_lock_rx.
+//
+// returns the number of descriptors consumed, or 0 if the e1000
+// hasn't finished writing the whole packet yet.
+static int
+e1000_recv_multi(int idx)
+{
+  int n, len = 0, noeop = 0;
+
+  // find the descriptor with EOP. the chain may wrap around the ring.
+  for (n = 1; ; n++) {
+    if (n > RX_RING_SIZE) {
+      // can't happen unless the e1000 is confused; drop the lot,
+      // so the next call doesn't scan the same chain again.
+      printf("e1000_recv: no EOP in the whole ring\n");
+      n = RX_RING_SIZE;
+      noeop = 1;
+      break;
+    }
+    struct rx_desc *desc = &rx_ring[(idx + n - 1) % RX_RING_SIZE];
+    if ((desc->status & E1000_RXD_STAT_DD) == 0)
+      return 0;
+    __sync_synchronize();
+    int l = desc->length;
+    if (l > RX_BUF_SIZE)
+      l = RX_BUF_SIZE;
+    len += l;
+    if (desc->status & E1000_RXD_STAT_EOP)
+      break;
+  }
+
+  char *buf = 0;
+  if (noeop) {
+    stats.rx_dropped++;
+  } else if (len > PGSIZE) {
+    printf("e1000_recv: dropping %d byte packet, larger than a page\n", len);
+    stats.rx_dropped++;
+  } else if ((buf = kalloc()) == 0) {
+    printf("e1000_recv: out of memory, dropping %d byte packet\n", len);
+    stats.rx_alloc_failures++;
+  }
+
+  int off = 0;
+  for (int i = 0; i < n; i++) {
+    int j = (idx + i) % RX_RING_SIZE;
+    int l = rx_ring[j].length;
+    if (l > RX_BUF_SIZE)
+      l = RX_BUF_SIZE;
+    if (buf)
+      memmove(buf + off, (char*)rx_ring[j].addr, l);
+    off += l;
+    rx_ring[j].status = 0;
+  }
+  rx_next = (idx + n) % RX_RING_SIZE;
+
+  if (buf) {
+    stats.rx_packets++;
+    net_rx(buf, len);
+  }
+
+  return n;
+}
+
+static void
+e1000_recv(void)
+{
   int i;
+
+again:
+  // if another cpu is already in here, it will drain the ring
+  // for us (it checks again after releasing the lock, below);
+  // rather than spin in the interrupt handler, leave.
+  if (!tryacquire(&e1000_lock_rx))
+    return;
+
+  // loop because multiple packets could be ready and not just one.
+  // each one's buffer goes up to net_rx(); the descriptors get new
+  // ones all together afterwards, in e1000_rx_refill_ring().
   for (i = 0; i < RX_RING_SIZE; ++i) {
-    int idx = (regs[E1000_RDT] + 1) % RX_RING_SIZE;
+    int idx = rx_next;
     struct rx_desc *desc = &rx_ring[idx];
     if ((desc->status & E1000_RXD_STAT_DD) != E1000_RXD_STAT_DD) {
       // if the descriptor is not done, stop.
@@ -153,26 +581,98 @@ e1000_recv(void)
       break;
     }
     
+    // DD is written last by the e1000; read the rest of the
+    // descriptor (length, EOP) and the packet only after it.
+    __sync_synchronize();
+
     if ((desc->status & E1000_RXD_STAT_EOP) == 0) {
-      panic("multi-buffer packets are not supported yet.");
-      break;
+      int n = e1000_recv_multi(idx);
+      if (n == 0)
+        break; // the rest of the packet isn't in yet; try again next interrupt.
+      i += n - 1;
+      continue;
+    }
+
+    // don't trust the e1000 to stay within the buffer it was given.
+    int len = desc->length;
+    if (len > RX_BUF_SIZE) {
+      printf("e1000_recv: descriptor[%d] length %d exceeds buffer size, clamping\n", idx, len);
+      len = RX_BUF_SIZE;
     }
 
     //printf("* e1000_recv: processing descriptor[%d]\n", idx);
-    net_rx((char*)desc->addr, desc->length);
-  
-    // not sure if i need to update rx_buf array for this index or not. not sure why we need rx_buf at all.
-    desc->addr = (uint64)kalloc();
+    char *buf = (char*)desc->addr;
+    desc->addr = 0;
     desc->status = 0;
-    __sync_synchronize();
-
-    // we have processed this packet, increment the tail to transfer ownership of the descriptor back to the hardware.
-    regs[E1000_RDT] = idx;
+    rx_next = (idx + 1) % RX_RING_SIZE;
+    stats.rx_packets++;
+    net_rx(buf, len);
   }
+
+  // also retries descriptors an earlier call couldn't back.
+  e1000_rx_refill_ring();
   
   release(&e1000_lock_rx);
+
+  // one wakeup per port for the whole burst, not one per packet.
+  net_rx_flush();
   if (i > 0)
-    printf("*** e1000_recv: processed %d packets\n", i);
+    debugf("e1000_recv: processed %d packets\n", i);
+
+  // a packet may have arrived while we held the lock, and
+  // its interrupt given up on the tryacquire() above; the
+  // lock's other holders check too, in e1000_unlock_rx().
+  if (rx_ring[rx_next].status & E1000_RXD_STAT_DD)
+    goto again;
+}
+
+// accept frames sent to multicast address m, by setting its bit
+// in the multicast table array. with RCTL.MO 0, the
+// bit is picked by bits 47:36 of the address. bits are never
+// cleared, since several groups can share one; net.c decides
+// what's wanted.
+void
+e1000_join_multicast(uint8 *m)
+{
+  uint32 hash = ((m[4] >> 4) | (m[5] << 4)) & 0xfff;
+
+  acquire(&e1000_lock_rx);
+  regs[E1000_MTA + (hash >> 5)] |= 1 << (hash & 31);
+  e1000_unlock_rx();
+}
+
+// the link speed in Mb/s, or 0 if the link is down.
+// a register reading all ones means the device isn't
+// really there, so that counts as down too.
+int
+e1000_link_status(void)
+{
+  uint32 status = regs[E1000_STATUS];
+
+  if (status == 0xffffffff || (status & E1000_STATUS_LU) == 0)
+    return 0;
+
+  switch (status & E1000_STATUS_SPEED_MASK) {
+  case E1000_STATUS_SPEED_10:
+    return 10;
+  case E1000_STATUS_SPEED_100:
+    return 100;
+  default:
+    return 1000;
+  }
+}
+
+// copy out a snapshot of the counters. holding both locks
+// keeps an interrupt from updating them halfway through.
+// rx before tx, as in e1000_recv() -> net_rx() -> e1000_transmit().
+void
+e1000_stats(struct nicstats *out)
+{
+  acquire(&e1000_lock_rx);
+  acquire(&e1000_lock_tx);
+  *out = stats;
+  release(&e1000_lock_tx);
+  e1000_unlock_rx();
 }
 
 void
@@ -186,4 +686,6 @@ e1000_intr(void)
   //printf("e1000 interrupt\n");
 
   e1000_recv();
+  e1000_poll_tx();
+  arp_sweep();
 }
@@ -5,6 +5,8 @@
 
 /* Registers */
 #define E1000_CTL      (0x00000/4)  /* Device Control Register - RW */
+#define E1000_STATUS   (0x00008/4)  /* Device Status - R */
+#define E1000_EERD     (0x00014/4)  /* EEPROM Read - RW */
 #define E1000_ICR      (0x000C0/4)  /* Interrupt Cause Read - R */
 #define E1000_IMS      (0x000D0/4)  /* Interrupt Mask Set - RW */
 #define E1000_RCTL     (0x00100/4)  /* RX Control - RW */
@@ -30,6 +32,22 @@
 #define E1000_CTL_FRCDPLX 0x00001000    /* force duplex */
 #define E1000_CTL_RST     0x04000000    /* full reset */
 
+/* Device Status */
+#define E1000_STATUS_LU          0x00000002    /* link up */
+#define E1000_STATUS_SPEED_MASK  0x000000C0
+#define E1000_STATUS_SPEED_10    0x00000000
+#define E1000_STATUS_SPEED_100   0x00000040
+#define E1000_STATUS_SPEED_1000  0x00000080    /* 0x000000C0 is 1000 too */
+
+/* EEPROM Read */
+#define E1000_EERD_START      0x00000001 /* start read */
+#define E1000_EERD_DONE       0x00000010 /* read done */
+#define E1000_EERD_ADDR_SHIFT 8
+#define E1000_EERD_DATA_SHIFT 16
+
+/* Receive Address High */
+#define E1000_RAH_AV      0x80000000    /* address valid */
+
 /* Transmit Control */
 #define E1000_TCTL_RST    0x00000001    /* software reset */
 #define E1000_TCTL_EN     0x00000002    /* enable tx */
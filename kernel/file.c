@@ -75,6 +75,14 @@ fileclose(struct file *f)
 
   if(ff.type == FD_PIPE){
     pipeclose(ff.pipe, ff.writable);
+#ifdef LAB_NET
+  } else if(ff.type == FD_SOCK){
+    sockclose(ff.ring);
+  } else if(ff.type == FD_TCP){
+    tcpclose(ff.tcp);
+  } else if(ff.type == FD_RAW){
+    rawclose(ff.raw);
+#endif
   } else if(ff.type == FD_INODE || ff.type == FD_DEVICE){
     begin_op();
     iput(ff.ip);
@@ -113,6 +121,14 @@ fileread(struct file *f, uint64 addr, int n)
 
   if(f->type == FD_PIPE){
     r = piperead(f->pipe, addr, n);
+#ifdef LAB_NET
+  } else if(f->type == FD_SOCK){
+    r = sockread(f->ring, addr, n);
+  } else if(f->type == FD_TCP){
+    r = tcpread(f->tcp, addr, n);
+  } else if(f->type == FD_RAW){
+    r = rawread(f->raw, addr, n);
+#endif
   } else if(f->type == FD_DEVICE){
     if(f->major < 0 || f->major >= NDEV || !devsw[f->major].read)
       return -1;
@@ -141,6 +157,14 @@ filewrite(struct file *f, uint64 addr, int n)
 
   if(f->type == FD_PIPE){
     ret = pipewrite(f->pipe, addr, n);
+#ifdef LAB_NET
+  } else if(f->type == FD_SOCK){
+    ret = sockwrite(f->ring, addr, n);
+  } else if(f->type == FD_TCP){
+    ret = tcpwrite(f->tcp, addr, n);
+  } else if(f->type == FD_RAW){
+    ret = rawwrite(f->raw, addr, n);
+#endif
   } else if(f->type == FD_DEVICE){
     if(f->major < 0 || f->major >= NDEV || !devsw[f->major].write)
       return -1;
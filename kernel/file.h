@@ -1,5 +1,5 @@
 struct file {
-  enum { FD_NONE, FD_PIPE, FD_INODE, FD_DEVICE } type;
+  enum { FD_NONE, FD_PIPE, FD_INODE, FD_DEVICE, FD_SOCK, FD_TCP, FD_RAW } type;
   int ref; // reference count
   char readable;
   char writable;
@@ -7,6 +7,9 @@ struct file {
   struct inode *ip;  // FD_INODE and FD_DEVICE
   uint off;          // FD_INODE
   short major;       // FD_DEVICE
+  struct bind_ring_buffer *ring; // FD_SOCK
+  struct tcpcb *tcp; // FD_TCP
+  struct rawsock *raw; // FD_RAW
 };
 
 #define major(dev)  ((dev) >> 16 & 0xFFFF)
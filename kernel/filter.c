@@ -0,0 +1,127 @@
+//
+// packet filters, in the style of BPF: small programs, attached
+// with setfilter() to a socket or to the capture, that decide
+// from its headers whether a packet gets in. they drop what a
+// reader doesn't want before it can fill a queue. jumps only go
+// forward, so every program ends.
+//
+
+#include "types.h"
+#include "param.h"
+#include "memlayout.h"
+#include "riscv.h"
+#include "spinlock.h"
+#include "proc.h"
+#include "defs.h"
+#include "fs.h"
+#include "sleeplock.h"
+#include "file.h"
+#include "net.h"
+
+// the field op names in the len-byte frame, in *v.
+// returns 0, or -1 if the frame hasn't got one.
+static int
+pf_field(int op, const char *frame, int len, uint32 *v)
+{
+  struct eth *eth = (struct eth *)frame;
+  struct ip *ip = (struct ip *)(eth + 1);
+
+  if(len < sizeof(*eth))
+    return -1;
+  if(op == PF_ETHTYPE){
+    *v = ntohs(eth->type);
+    return 0;
+  }
+
+  if(ntohs(eth->type) != ETHTYPE_IP || len < sizeof(*eth) + sizeof(*ip))
+    return -1;
+  switch(op){
+  case PF_PROTO:
+    *v = ip->ip_p;
+    return 0;
+  case PF_SRC:
+    *v = ntohl(ip->ip_src);
+    return 0;
+  case PF_DST:
+    *v = ntohl(ip->ip_dst);
+    return 0;
+  }
+
+  // UDP and TCP both start with the two ports, which only the
+  // first fragment carries.
+  int ihl = (ip->ip_vhl & 0xf) * 4;
+  uint16 *ports = (uint16 *)((char *)ip + ihl);
+  if((ip->ip_p != IPPROTO_UDP && ip->ip_p != IPPROTO_TCP) ||
+     (ntohs(ip->ip_off) & IP_OFFMASK) != 0 ||
+     len < sizeof(*eth) + ihl + 4)
+    return -1;
+  if(op == PF_SPORT){
+    *v = ntohs(ports[0]);
+    return 0;
+  }
+  if(op == PF_DPORT){
+    *v = ntohs(ports[1]);
+    return 0;
+  }
+  return -1;
+}
+
+// whether f accepts the len-byte frame. running off the end
+// of a program drops the frame.
+int
+pf_run(struct pktfilter *f, const char *frame, int len)
+{
+  if(f->n == 0)
+    return 1;
+
+  for(int pc = 0; pc < f->n; ){
+    struct pf_insn *in = &f->prog[pc];
+    if(in->op == PF_RET)
+      return in->k != 0;
+    uint32 v;
+    int eq = pf_field(in->op, frame, len, &v) == 0 && (v & in->mask) == in->k;
+    pc += 1 + (eq ? in->jt : in->jf);
+  }
+  return 0;
+}
+
+// copy in the n-instruction program at user address addr,
+// and check it: known ops, and jumps that stay inside.
+// returns 0, or -NET_EINVAL.
+static int
+pf_load(struct pktfilter *f, uint64 addr, int n)
+{
+  if(n < 0 || n > PF_MAXINSNS)
+    return -NET_EINVAL;
+  f->n = n;
+  if(copyin(myproc()->pagetable, (char *)f->prog, addr, n * sizeof(struct pf_insn)) < 0)
+    return -NET_EINVAL;
+  for(int i = 0; i < n; i++){
+    struct pf_insn *in = &f->prog[i];
+    if(in->op > PF_DPORT)
+      return -NET_EINVAL;
+    if(in->op != PF_RET && (i + 1 + in->jt > n || i + 1 + in->jf > n))
+      return -NET_EINVAL;
+  }
+  return 0;
+}
+
+// attach the n-instruction program at user address addr to
+// the socket f, or to the capture if f is 0; n == 0 takes
+// the filter off. returns 0, or -NET_E*.
+int
+setfilter(struct file *f, uint64 addr, int n)
+{
+  struct pktfilter pf;
+  int rc;
+
+  if((rc = pf_load(&pf, addr, n)) != 0)
+    return rc;
+  if(f == 0)
+    return pcap_setfilter(&pf);
+  if(f->type == FD_SOCK)
+    return sock_setfilter(f->ring, &pf);
+  if(f->type == FD_RAW)
+    return raw_setfilter(f->raw, &pf);
+  return -NET_EINVAL;
+}
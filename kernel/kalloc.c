@@ -23,6 +23,14 @@ struct {
   struct run *freelist;
 } kmem;
 
+// reference counts, so that a page can be shared by several
+// owners (e.g. one received packet queued on several ports).
+// kalloc() sets a page's count to 1, kdup() adds a reference,
+// and kfree() only frees the page when the last one is dropped.
+// protected by kmem.lock.
+static uint8 refcnt[(PHYSTOP - KERNBASE) / PGSIZE];
+#define PA2REF(pa) (((uint64)(pa) - KERNBASE) / PGSIZE)
+
 void
 kinit()
 {
@@ -35,14 +43,16 @@ freerange(void *pa_start, void *pa_end)
 {
   char *p;
   p = (char*)PGROUNDUP((uint64)pa_start);
-  for(; p + PGSIZE <= (char*)pa_end; p += PGSIZE)
+  for(; p + PGSIZE <= (char*)pa_end; p += PGSIZE){
+    refcnt[PA2REF(p)] = 1;
     kfree(p);
+  }
 }
 
-// Free the page of physical memory pointed at by pa,
-// which normally should have been returned by a
-// call to kalloc().  (The exception is when
-// initializing the allocator; see kinit above.)
+// Drop a reference to the page of physical memory pointed
+// at by pa, freeing it if that was the last one. pa normally
+// should have been returned by a call to kalloc().  (The
+// exception is when initializing the allocator; see kinit above.)
 void
 kfree(void *pa)
 {
@@ -51,6 +61,15 @@ kfree(void *pa)
   if(((uint64)pa % PGSIZE) != 0 || (char*)pa < end || (uint64)pa >= PHYSTOP)
     panic("kfree");
 
+  acquire(&kmem.lock);
+  if(refcnt[PA2REF(pa)] == 0)
+    panic("kfree: not allocated");
+  if(--refcnt[PA2REF(pa)] > 0){
+    release(&kmem.lock);
+    return;
+  }
+  release(&kmem.lock);
+
   // Fill with junk to catch dangling refs.
   memset(pa, 1, PGSIZE);
 
@@ -72,11 +91,55 @@ kalloc(void)
 
   acquire(&kmem.lock);
   r = kmem.freelist;
-  if(r)
+  if(r){
     kmem.freelist = r->next;
+    refcnt[PA2REF(r)] = 1;
+  }
   release(&kmem.lock);
 
   if(r)
     memset((char*)r, 5, PGSIZE); // fill with junk
   return (void*)r;
 }
+
+// Like kalloc(), but the page is zeroed rather than
+// filled with junk, for buffers that leave the machine.
+void *
+kzalloc(void)
+{
+  void *pa = kalloc();
+
+  if(pa)
+    memset(pa, 0, PGSIZE);
+  return pa;
+}
+
+// Allocate a page holding a copy of the first len bytes at src,
+// for a private copy of a buffer someone else owns.
+// Returns 0 if the memory cannot be allocated.
+void *
+kclone(const void *src, int len)
+{
+  if(len < 0 || len > PGSIZE)
+    panic("kclone");
+
+  void *pa = kalloc();
+  if(pa)
+    memmove(pa, src, len);
+  return pa;
+}
+
+// Add a reference to the page pa, which must already be
+// allocated; it will take one more kfree() to free it.
+void
+kdup(void *pa)
+{
+  if(((uint64)pa % PGSIZE) != 0 || (char*)pa < end || (uint64)pa >= PHYSTOP)
+    panic("kdup");
+
+  acquire(&kmem.lock);
+  if(refcnt[PA2REF(pa)] == 0 || refcnt[PA2REF(pa)] == 255)
+    panic("kdup: refcnt");
+  refcnt[PA2REF(pa)]++;
+  release(&kmem.lock);
+}
@@ -33,6 +33,7 @@ main()
     virtio_disk_init(); // emulated hard disk
 #ifdef LAB_NET
     pci_init();
+    netinit();
 #endif    
     userinit();      // first user process
 #ifdef KCSAN
@@ -7,26 +7,161 @@
 #include "defs.h"
 #include "fs.h"
 #include "sleeplock.h"
+#include "rwlock.h"
 #include "file.h"
 #include "net.h"
 
-// xv6's ethernet and IP addresses
+// xv6's ethernet address
 static uint8 local_mac[ETHADDR_LEN] = { 0x52, 0x54, 0x00, 0x12, 0x34, 0x56 };
-static uint32 local_ip = MAKE_IP_ADDR(10, 0, 2, 15);
 
 // qemu host's ethernet address.
 static uint8 host_mac[ETHADDR_LEN] = { 0x52, 0x55, 0x0a, 0x00, 0x02, 0x02 };
 
+// xv6's IP address, the gateway that forwards anything off its
+// network, and the name server resolve() asks. leased by
+// dhcp(), which init runs at boot; until then, and if that fails,
+// what qemu's DHCP server would hand out, and google's name server.
+// netconf() can read and change it.
+// the mac field is unused; local_mac is the real one.
+// protected by cfglock, which is taken last: don't acquire
+// any other lock while holding it.
+static struct netconf netcfg = {
+  .ip = MAKE_IP_ADDR(10, 0, 2, 15),
+  .mask = 0xffffff00,
+  .gateway = MAKE_IP_ADDR(10, 0, 2, 2),
+  .dns = MAKE_IP_ADDR(8, 8, 8, 8),
+  .mtu = ETH_MTU,
+};
+static struct spinlock cfglock;
+
 static struct spinlock netlock;
+// protects the ARP cache, which is read on every send
+// but only written by ARP traffic and arp_sweep().
+static struct rwlock arplock;
 
+void rings_init(void);
+static void raws_init(void);
+static int arp_rx(char *, int, struct rxinfo *);
+static int ip_input(char *, int, struct rxinfo *);
+static int icmp_rx(char *, int, struct rxinfo *);
+static int udp_rx(char *, int, struct rxinfo *);
+static int arp_selftest(void);
+static struct spinlock polllock;
+static void netpoll_wakeup(void);
+
+// called by main() after pci_init().
 void
 netinit(void)
 {
   initlock(&netlock, "netlock");
+  initlock(&cfglock, "netcfg");
+  initlock(&polllock, "netpoll");
+  initrwlock(&arplock, "arplock");
+  rings_init();
+  raws_init();
+  eth_register(ETHTYPE_ARP, sizeof(struct eth) + sizeof(struct arp), arp_rx);
+  eth_register(ETHTYPE_IP, sizeof(struct eth) + sizeof(struct ip), ip_input);
+  ip_register(IPPROTO_ICMP, icmp_rx);
+  ip_register(IPPROTO_UDP, udp_rx);
+  tcpinit();
+  pcapinit();
+  // use the address the e1000 is filtering on, if there is one.
+  e1000_macaddr(local_mac);
+}
+
+// xv6's IP address, host byte order.
+uint32
+local_ip(void)
+{
+  acquire(&cfglock);
+  uint32 ip = netcfg.ip;
+  release(&cfglock);
+  return ip;
+}
+
+static void
+get_netconfig(struct netconf *c)
+{
+  acquire(&cfglock);
+  *c = netcfg;
+  release(&cfglock);
+}
+
+static void
+set_netconfig(struct netconf *c)
+{
+  acquire(&cfglock);
+  netcfg = *c;
+  release(&cfglock);
+}
+
+// 224.0.0.0/4
+static int
+is_multicast(uint32 ip)
+{
+  return (ip >> 28) == 0xe;
+}
+
+// 255.255.255.255, or the broadcast address of our network.
+static int
+is_broadcast(uint32 ip)
+{
+  struct netconf c;
+
+  if(ip == 0xffffffff)
+    return 1;
+  get_netconfig(&c);
+  return c.mask != 0xffffffff && c.ip != 0 && ip == (c.ip | ~c.mask);
+}
+
+// the ethernet address that multicast group ip maps to:
+// 01:00:5e followed by the group's low 23 bits (RFC 1112).
+static void
+multicast_mac(uint32 ip, uint8 *mac)
+{
+  mac[0] = 0x01;
+  mac[1] = 0x00;
+  mac[2] = 0x5e;
+  mac[3] = (ip >> 16) & 0x7f;
+  mac[4] = ip >> 8;
+  mac[5] = ip;
+}
+
+static char *neterrs[NET_NERR] = {
+[0]             "ok",
+[NET_ETRUNC]    "truncated packet",
+[NET_EBADSUM]   "bad checksum",
+[NET_EQFULL]    "queue full",
+[NET_ENOROUTE]  "no route",
+[NET_ERINGBUSY] "tx ring busy",
+[NET_ENOMEM]    "out of memory",
+[NET_EINVAL]    "invalid argument",
+[NET_ENOTBOUND] "port not bound",
+[NET_EPROTO]    "unsupported protocol",
+[NET_ETIMEDOUT] "timed out",
+[NET_EFILTERED] "filtered",
+[NET_EADDRINUSE] "port already bound",
+[NET_EAGAIN]    "would block",
+[NET_ECONNREFUSED] "connection refused",
+[NET_ECONNRESET] "connection reset",
+[NET_EPERM]     "not permitted",
+};
+
+// describe a (possibly negated) NET_E* error code, for printf.
+char *
+netstrerror(int err)
+{
+  if(err < 0)
+    err = -err;
+  if(err >= NET_NERR || neterrs[err] == 0)
+    return "unknown error";
+  return neterrs[err];
 }
 
-// must be power of 2, for the uint32 overflow in the ring buffer trick to work properly.
+// packets a ring holds unless queuelen() says otherwise.
+// any size works; see ring_add().
 #define MAX_QUEUE_LEN 16
+_Static_assert(MAX_QUEUE_LEN > 0, "MAX_QUEUE_LEN must be positive");
 
 struct packet {
   char *buf;
@@ -39,105 +174,723 @@ struct packet {
 // inspiration: https://www.snellman.net/blog/archive/2016-12-13-ring-buffers/
 struct bind_ring_buffer {
   struct spinlock lock;
-  struct packet queue[MAX_QUEUE_LEN];
+  struct packet *queue; // cap slots: small, or a page from queuelen()
+  int cap;
+  struct packet small[MAX_QUEUE_LEN];
   uint32 read;
   uint32 write;
   int dropped;
+  uint64 pushed; // packets ever enqueued, for rate calculations
+  uint64 popped; // packets ever dequeued
   int dport;
+  int reuseport; // bound with BIND_REUSEPORT
+  int broadcast; // bound with BIND_BROADCAST
+  int pid;       // the process that bound it
+  int autobound; // bound by send() from port 0; see auto_ring()
+  int sock;      // owned by a socket file; see sockalloc()
+  uint rr;       // round-robin counter, if this is the port's first ring
+  uint64 wakeups; // times a receiver was woken for a new packet
+  uint32 peer_ip;   // if non-zero, only queue packets from this address,
+  uint16 peer_port; // and this port, if it's non-zero too; see connect()
+  uint32 group;     // multicast group joined, or 0; see joinmulticast()
+  int dirty;        // packets queued since the last wakeup; see net_rx_flush()
+  struct bind_ring_buffer *hnext; // next in its ringtable bucket
+  uint64 badsum;    // datagrams for the port with a bad UDP checksum
+  uint64 bytes;     // UDP payload bytes queued; see ring_deliver()
+  uint64 dropbytes; // UDP payload bytes dropped because the ring was full
+  struct pktfilter filter; // packets to queue; see setfilter()
 };
+// the longest queue queuelen() allows: a page of packets.
+#define RING_MAXCAP (PGSIZE / sizeof(struct packet))
+
+// go back to the ring's own MAX_QUEUE_LEN slots,
+// freeing any page queuelen() gave it.
+// the caller must hold ring->lock, and the ring must be empty.
+void ring_unsize(struct bind_ring_buffer *ring) {
+  if (ring->queue != 0 && ring->queue != ring->small)
+    kfree((char *)ring->queue);
+  ring->queue = ring->small;
+  ring->cap = MAX_QUEUE_LEN;
+}
+// reset a ring for a new binding.
+// the ring's lock is initialized once, by rings_init().
 void ring_init(struct bind_ring_buffer *ring) {
-  memset(ring->queue, 0, sizeof(struct packet) * MAX_QUEUE_LEN);
+  acquire(&ring->lock);
+  ring_unsize(ring);
+  memset(ring->queue, 0, sizeof(struct packet) * ring->cap);
   ring->read = 0;
   ring->write = 0;
   ring->dropped = 0;
-  initlock(&ring->lock, "ring");
+  ring->pushed = 0;
+  ring->popped = 0;
+  ring->rr = 0;
+  ring->wakeups = 0;
+  ring->peer_ip = 0;
+  ring->peer_port = 0;
+  ring->group = 0;
+  ring->dirty = 0;
+  ring->badsum = 0;
+  ring->bytes = 0;
+  ring->dropbytes = 0;
+  ring->filter.n = 0;
+  release(&ring->lock);
 }
-int ring_mod(int num) { return num % MAX_QUEUE_LEN; }
+// read and write count modulo twice the capacity, rather than
+// letting a uint32 wrap: that keeps full (write - read == cap)
+// apart from empty (write == read) for any capacity, where
+// wrapping at 2^32 only works for powers of 2. when cap is a
+// constant power of 2 these are all masks, as cheap as the
+// overflow was; a ring's own cap costs a divide.
+uint32 ring_add(uint32 i, uint32 n, uint32 cap) { return (i + n) % (2 * cap); }
+uint32 ring_count(uint32 read, uint32 write, uint32 cap) { return (write + 2 * cap - read) % (2 * cap); }
+// the queue slot for index i.
+int ring_slot(struct bind_ring_buffer *ring, uint32 i) { return i % ring->cap; }
 int ring_empty(struct bind_ring_buffer *ring) { return ring->write == ring->read; }
 // return the ring buffer size; the number of packets that haven't been consumed yet.
-int ring_size(struct bind_ring_buffer *ring) { return ring->write - ring->read; }
-int ring_full(struct bind_ring_buffer *ring) { return ring_size(ring) == MAX_QUEUE_LEN; }
+int ring_size(struct bind_ring_buffer *ring) { return ring_count(ring->read, ring->write, ring->cap); }
+int ring_full(struct bind_ring_buffer *ring) { return ring_size(ring) == ring->cap; }
 int ring_enqueue(struct bind_ring_buffer *ring, struct packet packet) {
   if (ring_full(ring)) {
     ring->dropped += 1;
     return 1;
   }
-  ring->queue[ring_mod(ring->write++)] = packet;
+  ring->queue[ring_slot(ring, ring->write)] = packet;
+  ring->write = ring_add(ring->write, 1, ring->cap);
+  ring->pushed++;
   return 0;
 }
+// like ring_enqueue(), but when the ring is full, evict the oldest
+// packet into *evicted to make room instead of dropping the new one.
+// returns 1 if a packet was evicted (the caller now owns its buf),
+// 0 if there was room. nothing is lost silently, so dropped isn't counted.
+int ring_enqueue_overwrite(struct bind_ring_buffer *ring, struct packet packet, struct packet *evicted) {
+  int full = ring_full(ring);
+  if (full) {
+    *evicted = ring->queue[ring_slot(ring, ring->read)];
+    ring->read = ring_add(ring->read, 1, ring->cap);
+    ring->popped++;
+  }
+  ring->queue[ring_slot(ring, ring->write)] = packet;
+  ring->write = ring_add(ring->write, 1, ring->cap);
+  ring->pushed++;
+  return full;
+}
+// pop the head packet into *packet without sleeping;
+// returns 1 if the ring is empty.
+// the caller must hold ring->lock, and decides whether to sleep.
 int ring_dequeue(struct bind_ring_buffer *ring, struct packet *packet) {
   if (ring_empty(ring)) {
     return 1;
   }
-  *packet = ring->queue[ring_mod(ring->read++)];
+  *packet = ring->queue[ring_slot(ring, ring->read)];
+  ring->read = ring_add(ring->read, 1, ring->cap);
+  ring->popped++;
   return 0;
 }
+// pop up to n packets from the head into out[], oldest first.
+// returns the number of packets moved.
+// the caller must hold ring->lock.
+int ring_dequeue_many(struct bind_ring_buffer *ring, struct packet *out, int n) {
+  if (n <= 0)
+    return 0;
+  if (n > ring_size(ring))
+    n = ring_size(ring);
 
+  // the packets may wrap around the end of the queue array.
+  int start = ring_slot(ring, ring->read);
+  int first = n < ring->cap - start ? n : ring->cap - start;
+  memmove(out, &ring->queue[start], first * sizeof(struct packet));
+  memmove(out + first, &ring->queue[0], (n - first) * sizeof(struct packet));
+
+  ring->read = ring_add(ring->read, n, ring->cap);
+  ring->popped += n;
+  return n;
+}
+// dequeue and free every queued packet, leaving the ring empty
+// and ready for reuse. returns the number of packets freed.
+// the caller must hold ring->lock.
+int ring_drain(struct bind_ring_buffer *ring) {
+  struct packet packet;
+  int n = 0;
+  while (ring_dequeue(ring, &packet) == 0) {
+    kfree(packet.buf);
+    n++;
+  }
+  return n;
+}
+// return the i'th queued packet, counting from the head, without
+// consuming anything; 0 if fewer than i+1 packets are queued.
+// for walking the queue in order: for (i = 0; (p = ring_at(ring, i)); i++).
+// the caller must hold ring->lock.
+struct packet *ring_at(struct bind_ring_buffer *ring, int i) {
+  if (i < 0 || i >= ring_size(ring)) {
+    return 0;
+  }
+  return &ring->queue[ring_slot(ring, ring_add(ring->read, i, ring->cap))];
+}
+// return the head packet without consuming it, or 0 if the ring is empty.
+// the packet may be modified in place.
+// the caller must hold ring->lock.
+struct packet *ring_peek(struct bind_ring_buffer *ring) {
+  return ring_at(ring, 0);
+}
 
-// store a fixed size of ring buffers for now, use a btree later or a hashamp;
+// a fixed number of ring buffers; ringtable, below, indexes the bound ones.
 #define RINGS_NUM 100
 static struct bind_ring_buffer rings[RINGS_NUM] = {0}; 
 
-// find the next unsed ring buffer and update
-// its dport to the port requested.
+// the bound rings, hashed by port, so that finding a packet's
+// ring doesn't mean scanning all of them. within a bucket, rings
+// sharing a port are in the order they were bound.
+// lookups take lock for reading, and can run in interrupts;
+// bind_ring() and ring_unbind() take it for writing.
+// lock order: netlock, then ringtable.lock, then a ring's lock.
+#define RING_BUCKETS 32
+static struct ringtable {
+  struct rwlock lock; // protects buckets and every ring's hnext
+  struct bind_ring_buffer *buckets[RING_BUCKETS];
+} ringtable;
+
+static struct bind_ring_buffer **
+ring_bucket(int port)
+{
+  return &ringtable.buckets[port % RING_BUCKETS];
+}
+
+struct bind_ring_buffer *find_ring(int port);
+struct bind_ring_buffer *own_ring(int port);
+
+#define EPHEMERAL_MIN 49152
+#define EPHEMERAL_MAX 65535
+#define EPHEMERAL_NUM (EPHEMERAL_MAX - EPHEMERAL_MIN + 1)
+
+// which ephemeral ports are bound, one bit each, whether
+// bind(0) picked them or they were asked for by number.
+// protected by netlock.
+static uint8 ephemeral_map[EPHEMERAL_NUM / 8];
+
+static int is_ephemeral(int port) {
+  return port >= EPHEMERAL_MIN && port <= EPHEMERAL_MAX;
+}
+
+// mark port bound (used = 1) or free (used = 0) in ephemeral_map;
+// ports outside the ephemeral range are ignored.
+// the caller must hold netlock.
+static void ephemeral_mark(int port, int used) {
+  if (!is_ephemeral(port))
+    return;
+  int i = port - EPHEMERAL_MIN;
+  if (used)
+    ephemeral_map[i / 8] |= 1 << (i % 8);
+  else
+    ephemeral_map[i / 8] &= ~(1 << (i % 8));
+}
+
+// pick an ephemeral port that no ring is bound to,
+// or return 0 if they're all taken.
+// the caller must hold netlock.
+static int ephemeral_port(void) {
+  static int next = EPHEMERAL_MIN;
+
+  for (int n = 0; n < EPHEMERAL_NUM; n++) {
+    int port = next;
+    int i = port - EPHEMERAL_MIN;
+    next = next == EPHEMERAL_MAX ? EPHEMERAL_MIN : next + 1;
+    if ((ephemeral_map[i / 8] & (1 << (i % 8))) == 0)
+      return port;
+  }
+
+  return 0;
+}
+
+void rings_init(void) {
+  initrwlock(&ringtable.lock, "ringtable");
+  for (int i = 0; i < RINGS_NUM; i++) {
+    initlock(&rings[i].lock, "ring");
+    rings[i].queue = rings[i].small;
+    rings[i].cap = MAX_QUEUE_LEN;
+  }
+}
+
+// find the next unsed ring buffer and bind it to the port
+// requested, or to a free ephemeral port if port is 0.
+// a port that's already bound can only be shared if every
+// binding, this one included, asks for BIND_REUSEPORT, and
+// at most MAXREUSE bindings can share it. netlock makes the
+// check and the claim atomic, so two binds can't both get a
+// port to themselves.
 //
 // # Return value
-// This will always return a pointer to a ring buffer or panic if no ring buffer is free.
-struct bind_ring_buffer *next_free_ring(int port) {
-  for (int i =0; i < RINGS_NUM; i++) {
+// 0 with *ringp set, or -NET_EADDRINUSE or -NET_ENOMEM.
+int bind_ring(int port, int flags, struct bind_ring_buffer **ringp) {
+  int rc = -NET_ENOMEM;
+
+  acquire(&netlock);
+  if (port == 0) {
+    port = ephemeral_port();
+    flags &= ~BIND_REUSEPORT;
+  }
+  // only binds change the table, and we hold netlock,
+  // so it can be read here without ringtable.lock.
+  int shared = 0;
+  for (struct bind_ring_buffer *r = *ring_bucket(port); port != 0 && r; r = r->hnext) {
+    if (r->dport == port &&
+        ((flags & BIND_REUSEPORT) == 0 || !r->reuseport || ++shared == MAXREUSE)) {
+      rc = -NET_EADDRINUSE;
+      goto out;
+    }
+  }
+  for (int i =0; port != 0 && i < RINGS_NUM; i++) {
     if (rings[i].dport == 0) {
+      ring_init(&rings[i]);
+      rings[i].reuseport = (flags & BIND_REUSEPORT) != 0;
+      rings[i].broadcast = (flags & BIND_BROADCAST) != 0;
+      rings[i].pid = myproc() ? myproc()->pid : 0;
       rings[i].dport = port;
-      return &rings[i]; 
+      rings[i].autobound = 0;
+      rings[i].sock = 0;
+      rings[i].hnext = 0;
+      ephemeral_mark(port, 1);
+      acquirewrite(&ringtable.lock);
+      struct bind_ring_buffer **pp = ring_bucket(port);
+      while (*pp)
+        pp = &(*pp)->hnext;
+      *pp = &rings[i];
+      releasewrite(&ringtable.lock);
+      *ringp = &rings[i];
+      rc = 0;
+      break;
     }
   }
 
-  panic("all ring buffers are used, time for a serious implementation?");
-
-  return 0;
+out:
+  release(&netlock);
+  return rc;
 }
 
+// find the ring bound to port, preferring one bound by the
+// calling process when the port is shared (BIND_REUSEPORT).
 struct bind_ring_buffer *find_ring(int port) {
-  for (int i =0; i < RINGS_NUM; i++) {
-    if (rings[i].dport == port) {
-      return &rings[i]; 
+  struct bind_ring_buffer *ring = 0;
+  struct proc *p = myproc();
+
+  if (port == 0)
+    return 0; // dport 0 marks a free ring
+
+  acquireread(&ringtable.lock);
+  for (struct bind_ring_buffer *r = *ring_bucket(port); r; r = r->hnext) {
+    if (r->dport == port) {
+      if (p == 0 || r->pid == p->pid) {
+        ring = r;
+        break;
+      }
+      if (ring == 0)
+        ring = r;
     }
   }
+  releaseread(&ringtable.lock);
 
-  return 0;
+  return ring;
+}
+
+// find the calling process's ring bound to port, or 0 if it
+// hasn't bound port. unlike find_ring(), this never falls back
+// to another process's binding, so it's for the calls that
+// change or release a binding: one process mustn't touch
+// another's.
+struct bind_ring_buffer *own_ring(int port) {
+  struct bind_ring_buffer *ring = 0;
+  struct proc *p = myproc();
+
+  if (port == 0)
+    return 0;
+
+  acquireread(&ringtable.lock);
+  for (struct bind_ring_buffer *r = *ring_bucket(port); r; r = r->hnext) {
+    if (r->dport == port && r->pid == p->pid) {
+      ring = r;
+      break;
+    }
+  }
+  releaseread(&ringtable.lock);
+
+  return ring;
+}
+
+// own_ring(), then lock the ring. it may have been unbound, and
+// maybe rebound by someone else, between the lookup and the
+// lock, so check again once the lock is held.
+// returns the ring with its lock held, or 0.
+static struct bind_ring_buffer *lock_own_ring(int port) {
+  struct bind_ring_buffer *ring = own_ring(port);
+
+  if (ring == 0)
+    return 0;
+  acquire(&ring->lock);
+  if (ring->dport != port || ring->pid != myproc()->pid) {
+    release(&ring->lock);
+    return 0;
+  }
+  return ring;
+}
+
+// fill out[] with up to max rings bound to port, first bound first.
+// returns how many there are.
+int find_rings(int port, struct bind_ring_buffer **out, int max) {
+  int n = 0;
+
+  if (port == 0)
+    return 0;
+
+  acquireread(&ringtable.lock);
+  for (struct bind_ring_buffer *r = *ring_bucket(port); r && n < max; r = r->hnext) {
+    if (r->dport == port)
+      out[n++] = r;
+  }
+  releaseread(&ringtable.lock);
+
+  return n;
+}
+
+// count a datagram with a bad UDP checksum against
+// every ring bound to port.
+void count_badsum(int port) {
+  acquireread(&ringtable.lock);
+  for (struct bind_ring_buffer *r = *ring_bucket(port); r; r = r->hnext) {
+    if (r->dport == port)
+      __sync_fetch_and_add(&r->badsum, 1);
+  }
+  releaseread(&ringtable.lock);
+}
+
+// bind a ring to some ephemeral port nobody is using,
+// for the kernel's own use. returns 0 if none is free.
+struct bind_ring_buffer *bind_any_ring(void) {
+  struct bind_ring_buffer *ring;
+  if (bind_ring(0, 0, &ring) != 0)
+    return 0;
+  return ring;
+}
+
+// unbind a ring from port, freeing any packets still queued on
+// it, and wake up anyone waiting in recv() so they notice.
+// does nothing if the ring isn't bound to port any more: it was
+// unbound, and maybe rebound, since the caller found it.
+// the caller must hold netlock, and should call netpoll_wakeup()
+// once it has released it.
+static void ring_unbind(struct bind_ring_buffer *ring, int port) {
+  int shared = 0;
+
+  if (ring->dport != port || port == 0)
+    return;
+  acquirewrite(&ringtable.lock);
+  for (struct bind_ring_buffer **pp = ring_bucket(ring->dport); *pp; ) {
+    if (*pp == ring) {
+      *pp = ring->hnext;
+      continue;
+    }
+    if ((*pp)->dport == ring->dport)
+      shared = 1; // another BIND_REUSEPORT binding keeps the port
+    pp = &(*pp)->hnext;
+  }
+  releasewrite(&ringtable.lock);
+  if (!shared)
+    ephemeral_mark(ring->dport, 0);
+  acquire(&ring->lock);
+  ring->dport = 0;
+  ring->reuseport = 0;
+  ring->broadcast = 0;
+  ring->autobound = 0;
+  ring->sock = 0;
+  ring_drain(ring);
+  ring_unsize(ring);
+  wakeup(ring);
+  release(&ring->lock);
+}
+
+// like ring_unbind(), taking netlock. locks are taken in the
+// same order as bind_ring(): netlock first.
+void ring_release(struct bind_ring_buffer *ring, int port) {
+  acquire(&netlock);
+  ring_unbind(ring, port);
+  release(&netlock);
+  netpoll_wakeup(); // so netpoll() notices the port is gone
+}
+
+// the ring send() bound to an ephemeral port for the calling
+// process, the first time it sent from port 0; with create set,
+// bind one if there isn't one yet. returns 0 if there isn't,
+// or if no port is free.
+struct bind_ring_buffer *auto_ring(int create) {
+  struct proc *p = myproc();
+  struct bind_ring_buffer *ring = 0;
+
+  acquire(&netlock);
+  for (int i = 0; i < RINGS_NUM; i++) {
+    if (rings[i].dport != 0 && rings[i].autobound && rings[i].pid == p->pid) {
+      ring = &rings[i];
+      break;
+    }
+  }
+  release(&netlock);
+
+  // only this process binds its own auto ring, so
+  // no other can have bound one since we looked.
+  if (ring == 0 && create && (ring = bind_any_ring()) != 0)
+    ring->autobound = 1;
+  return ring;
+}
+
+// release the rings process pid bound, with bind() or send(),
+// freeing the packets still queued on them; it's exiting, and
+// nothing else would. a socket's ring is released when its
+// last file is closed instead, which may be by another process.
+void
+net_exit(int pid)
+{
+  int n = 0;
+
+  // binds hold netlock, so none can claim a ring
+  // between checking its owner and unbinding it.
+  acquire(&netlock);
+  for (int i = 0; i < RINGS_NUM; i++) {
+    struct bind_ring_buffer *ring = &rings[i];
+    if (ring->dport != 0 && ring->pid == pid && !ring->sock) {
+      debugf("net_exit: pid %d left port %d bound, %d packets queued\n",
+             pid, ring->dport, ring_size(ring));
+      ring_unbind(ring, ring->dport);
+      n++;
+    }
+  }
+  release(&netlock);
+  if (n > 0)
+    netpoll_wakeup();
 }
 
 //
 // bind(int port)
 // prepare to receive UDP packets address to the port,
 // i.e. allocate any queues &c needed.
+// if port is 0, pick a free ephemeral port.
+// returns the bound port, or -NET_E* on failure.
 //
+static int
+bind(int port, int flags)
+{
+  if (port < 0 || port > 65535 || (flags & ~(BIND_REUSEPORT | BIND_BROADCAST)) != 0)
+    return -NET_EINVAL;
+
+  struct bind_ring_buffer *ring;
+  int rc;
+  if ((rc = bind_ring(port, flags, &ring)) != 0)
+    return rc;
+  printf("sys_bind: %d\n", ring->dport);
+  return ring->dport;
+}
+
 uint64
 sys_bind(void)
 {
   int port; 
   argint(0, &port);
 
-  struct bind_ring_buffer *ring = next_free_ring(port);
-  ring_init(ring);
-  printf("sys_bind: %d\n", port);
-  return 1;
+  return bind(port, 0);
+}
+
+//
+// bindflags(int port, int flags)
+// like bind(), with BIND_* flags. with BIND_REUSEPORT, several
+// processes can each bind the same port, as long as they all ask
+// for it, up to MAXREUSE of them; incoming packets are spread
+// round-robin across them.
+// only bindings with BIND_BROADCAST receive datagrams sent to
+// 255.255.255.255 or to our network's broadcast address.
+//
+uint64
+sys_bindflags(void)
+{
+  int port; 
+  int flags;
+  argint(0, &port);
+  argint(1, &flags);
+
+  return bind(port, flags);
 }
 
 //
 // unbind(int port)
 // release any resources previously created by bind(port);
 // from now on UDP packets addressed to port should be dropped.
+// returns 0, or -NET_ENOTBOUND if the caller hasn't bound port,
+// or -NET_EINVAL if it's a socket's, which close() unbinds.
 //
 uint64
 sys_unbind(void)
 {
-  //
-  // Optional: Your code here.
-  //
+  int port;
+  argint(0, &port);
+
+  // hold netlock from the lookup to the release, so that the
+  // ring can't be unbound and rebound by someone else between.
+  int rc = 0;
+  acquire(&netlock);
+  struct bind_ring_buffer *ring = own_ring(port);
+  if (ring == 0)
+    rc = -NET_ENOTBOUND;
+  else if (ring->sock)
+    rc = -NET_EINVAL;
+  else
+    ring_unbind(ring, port);
+  release(&netlock);
+
+  if (rc == 0)
+    netpoll_wakeup(); // so netpoll() notices the port is gone
+  return rc;
+}
+
+// the n bytes at offset off in a len-byte packet,
+// or 0 if they run past the end of it.
+static void *
+pkt_at(char *buf, int len, int off, int n)
+{
+  if (off < 0 || n < 0 || off > len || n > len - off)
+    return 0;
+  return buf + off;
+}
+
+// the layers of a UDP packet, found by udp_parse().
+struct udpview {
+  struct eth *eth;
+  struct ip *ip;
+  struct udp *udp;
+  char *payload;
+  int paylen;     // UDP payload bytes, per the UDP header
+};
+
+// check that the len-byte frame in buf holds complete ethernet,
+// IP and UDP headers and as much payload as the UDP header claims,
+// and fill in *v. afterwards the fields of *v can be used freely.
+// returns 0, or -NET_ETRUNC.
+static int
+udp_parse(char *buf, int len, struct udpview *v)
+{
+  v->eth = pkt_at(buf, len, 0, sizeof(struct eth));
+  v->ip = pkt_at(buf, len, sizeof(struct eth), sizeof(struct ip));
+  if (v->eth == 0 || v->ip == 0)
+    return -NET_ETRUNC;
+  int ihl = (v->ip->ip_vhl & 0x0F) * 4;        // bytes
+  int off = sizeof(struct eth) + ihl;
+  if (ihl < sizeof(struct ip) ||
+      (v->udp = pkt_at(buf, len, off, sizeof(struct udp))) == 0)
+    return -NET_ETRUNC;
+  v->paylen = ntohs(v->udp->ulen) - (int)sizeof(struct udp);
+  v->payload = pkt_at(buf, len, off + sizeof(struct udp), v->paylen);
+  return v->payload ? 0 : -NET_ETRUNC;
+}
+
+// copy up to maxlen bytes of a received packet's UDP payload out
+// to user address bufaddr, and its source into *src and *sport.
+// returns the number of bytes copied, or -NET_EINVAL,
+// or -NET_ETRUNC if the headers claim more than the packet holds.
+static int
+payload_copyout(struct packet *packet, uint64 bufaddr, int maxlen, uint32 *src, uint16 *sport)
+{
+  struct udpview v;
+
+  *src = 0;
+  *sport = packet->sport;
+
+  if (udp_parse(packet->buf, packet->len, &v) != 0)
+    return -NET_ETRUNC;
+  *src = ntohl(v.ip->ip_src);
+
+  if (maxlen > v.paylen ) {
+    maxlen = v.paylen;
+  }
+
+  if (maxlen > 0 && copyout(myproc()->pagetable, bufaddr, v.payload, maxlen) < 0)
+    maxlen = -NET_EINVAL;
+  return maxlen;
+}
+
+// like payload_copyout(), then free the packet.
+// a maxlen of 0 still consumes the packet, copying no payload.
+static int
+packet_copyout(struct packet *packet, uint64 bufaddr, int maxlen, uint32 *src, uint16 *sport)
+{
+  int n = payload_copyout(packet, bufaddr, maxlen, src, sport);
+  kfree(packet->buf);
+  return n;
+}
+
+// wait, holding ring->lock, until a packet is queued on ring.
+// being woken doesn't promise a packet (another receiver sharing
+// the ring may have taken it), so this checks again after each
+// wakeup. gives up if the ring is unbound from dport, if the
+// process is killed, or after timeout ticks unless timeout is -1.
+// returns 0 with a packet queued, -NET_ENOTBOUND, -NET_ETIMEDOUT,
+// or -1 if killed.
+static int
+ring_wait(struct bind_ring_buffer *ring, int dport, int timeout)
+{
+  uint start = ticks;
 
+  while (ring_empty(ring)) {
+    if (ring->dport != dport)
+      return -NET_ENOTBOUND; // unbound while we were waiting.
+    if (killed(myproc()))
+      return -1;
+    //printf("sys_recv, ring is empty, sleep!\n");
+    if (timeout == -1) {
+      sleep(ring, &ring->lock);
+      continue;
+    }
+    int left = timeout - (int)(ticks - start);
+    if (left <= 0 || sleeptimeout(ring, &ring->lock, left))
+      return ring_empty(ring) ? -NET_ETIMEDOUT : 0;
+  }
   return 0;
 }
 
+// receive one packet for dport, copying its payload out as
+// sys_recv() describes, and its source into *from.
+// wait at most timeout ticks for it, or forever if timeout is -1.
+static int
+recv(int dport, struct sockaddr_in *from, uint64 bufaddr, int maxlen, int timeout)
+{
+  if (maxlen < 0 || timeout < -1)
+    return -NET_EINVAL;
+
+  struct bind_ring_buffer *ring = dport ? find_ring(dport) : auto_ring(0);
+  if (ring == 0) {
+    printf("attempt to sys_recv an unbound port %d\n", dport);
+    return -NET_ENOTBOUND;
+  }
+  dport = ring->dport;
+  
+  struct packet packet;
+  int rc;
+  acquire(&ring->lock);
+  if ((rc = ring_wait(ring, dport, timeout)) == 0)
+    ring_dequeue(ring, &packet);
+  release(&ring->lock);
+  if (rc != 0)
+    return rc;
+
+  from->family = AF_INET;
+  return packet_copyout(&packet, bufaddr, maxlen, &from->addr, &from->port);
+}
+
+// copy a received packet's source out to recv()'s separate
+// src and sport arguments.
+static void
+source_copyout(struct sockaddr_in *from, uint64 srcaddr, uint64 sportaddr)
+{
+  pagetable_t pagetable = myproc()->pagetable;
+  copyout(pagetable, sportaddr, (char*)(&from->port), sizeof(from->port));
+  copyout(pagetable, srcaddr, (char*)(&from->addr), sizeof(from->addr));
+}
+
 //
 // recv(int dport, int *src, short *sport, char *buf, int maxlen)
 // if there's a received UDP packet already queued that was
@@ -146,12 +899,14 @@ sys_unbind(void)
 //
 // sets *src to the IP source address.
 // sets *sport to the UDP source port.
-// copies up to maxlen bytes of UDP payload to buf.
+// copies up to maxlen bytes of UDP payload to buf;
+// the packet is consumed even if maxlen is 0.
 // returns the number of bytes copied,
-// and -1 if there was an error.
+// or -NET_E* if there was an error.
 //
 // dport, *src, and *sport are host byte order.
-// bind(dport) must previously have been called.
+// bind(dport) must previously have been called, or, if dport
+// is 0, send() from port 0, to receive the replies.
 //
 uint64
 sys_recv(void)
@@ -168,150 +923,2367 @@ sys_recv(void)
   argaddr(3, &bufaddr);
   argint(4, &maxlen);
 
-  struct bind_ring_buffer *ring = find_ring(dport);
-  if (ring == 0) {
-    printf("attempt to sys_recv an unbound port %d\n", dport);
-    return -1;
-  }
+  struct sockaddr_in from;
+  int n = recv(dport, &from, bufaddr, maxlen, -1);
+  if (n >= 0)
+    source_copyout(&from, srcaddr, sportddr);
+  return n;
+}
+
+//
+// recvtimeout(int dport, int *src, short *sport, char *buf, int maxlen, int timeout)
+// like recv(), but give up after timeout ticks
+// with -NET_ETIMEDOUT if no packet has arrived.
+//
+uint64
+sys_recvtimeout(void)
+{
+  int dport;
+  uint64 srcaddr;
+  uint64 sportddr;
+  uint64 bufaddr;
+  int maxlen;
+  int timeout;
   
-  acquire(&ring->lock);
-  while (ring_empty(ring)) {
-    //printf("sys_recv, ring is empty, sleep!\n");
-    sleep(ring, &ring->lock);
-  }
+  argint(0, &dport);
+  argaddr(1, &srcaddr);
+  argaddr(2, &sportddr);
+  argaddr(3, &bufaddr);
+  argint(4, &maxlen);
+  argint(5, &timeout);
 
-  struct packet packet;
-  if (ring_dequeue(ring, &packet) != 0) {
-    printf("weird\n");
-    release(&ring->lock);
-    return -1;
-  }
+  if (timeout < 0)
+    return -NET_EINVAL;
+  struct sockaddr_in from;
+  int n = recv(dport, &from, bufaddr, maxlen, timeout);
+  if (n >= 0)
+    source_copyout(&from, srcaddr, sportddr);
+  return n;
+}
 
-  release(&ring->lock);
+//
+// recvfrom(int dport, char *buf, int maxlen, struct sockaddr_in *from)
+// like recv(), but set *from to the packet's source address.
+//
+uint64
+sys_recvfrom(void)
+{
+  int dport;
+  uint64 bufaddr;
+  int maxlen;
+  uint64 fromaddr;
 
-  struct eth *eth = (struct eth *) packet.buf;
-  struct ip  *ip  = (struct ip *)(eth + 1);
-  uint8 ihl = (ip->ip_vhl & 0x0F) * 4;        // bytes
-  struct udp *udp = (struct udp *)((char*)ip + ihl);
-  char *payload   = (char *)(udp + 1);
+  argint(0, &dport);
+  argaddr(1, &bufaddr);
+  argint(2, &maxlen);
+  argaddr(3, &fromaddr);
 
-  pagetable_t pagetable = myproc()->pagetable;
+  struct sockaddr_in from;
+  int n = recv(dport, &from, bufaddr, maxlen, -1);
+  if (n >= 0 && copyout(myproc()->pagetable, fromaddr, (char *)&from, sizeof(from)) < 0)
+    return -NET_EINVAL;
+  return n;
+}
 
-  uint32 ipsrc = ntohl(ip->ip_src);
-  copyout(pagetable, sportddr, (char*)(&packet.sport), sizeof(short));
-  copyout(pagetable, srcaddr, (char*)(&ipsrc), sizeof(uint32));
+//
+// recvmmsg(int dport, struct netmsg *msgs, int n, int flags)
+// like recv(), but receive up to n queued packets at once,
+// one per element of msgs; see struct netmsg in net.h.
+// waits only if no packet is queued.
+// with RECV_PEEK in flags, the packets are copied out but
+// left queued, to be received again. with RECV_DONTWAIT,
+// return -NET_EAGAIN at once if nothing is queued.
+// returns the number of packets received, or -NET_E* on error.
+//
+uint64
+sys_recvmmsg(void)
+{
+  int dport;
+  uint64 msgsaddr;
+  int n;
+  int flags;
 
-  //int buf_len = packet.len - sizeof(struct eth) - sizeof(struct ip) - sizeof(struct udp);
-  int udp_len = ntohs(udp->ulen) - (int)sizeof(struct udp);
-  if (maxlen > udp_len ) {
-    maxlen = udp_len;
-  }
+  argint(0, &dport);
+  argaddr(1, &msgsaddr);
+  argint(2, &n);
+  argint(3, &flags);
 
-  copyout(pagetable, bufaddr, (char*)(payload), maxlen);
-  kfree(packet.buf);
-  return maxlen;
-}
+  if (n <= 0 || (flags & ~(RECV_PEEK | RECV_DONTWAIT)) != 0)
+    return -NET_EINVAL;
+  if (n > MAX_QUEUE_LEN)
+    n = MAX_QUEUE_LEN; // the most one call returns
 
-// This code is lifted from FreeBSD's ping.c, and is copyright by the Regents
-// of the University of California.
-static unsigned short
-in_cksum(const unsigned char *addr, int len)
-{
-  int nleft = len;
-  const unsigned short *w = (const unsigned short *)addr;
-  unsigned int sum = 0;
-  unsigned short answer = 0;
+  struct bind_ring_buffer *ring = find_ring(dport);
+  if (ring == 0)
+    return -NET_ENOTBOUND;
 
-  /*
-   * Our algorithm is simple, using a 32 bit accumulator (sum), we add
-   * sequential 16 bit words to it, and at the end, fold back all the
-   * carry bits from the top 16 bits into the lower 16 bits.
-   */
-  while (nleft > 1)  {
-    sum += *w++;
-    nleft -= 2;
+  pagetable_t pagetable = myproc()->pagetable;
+  struct packet packets[MAX_QUEUE_LEN];
+  int got = 0, rc;
+  acquire(&ring->lock);
+  if ((rc = ring_wait(ring, dport, (flags & RECV_DONTWAIT) ? 0 : -1)) != 0) {
+    release(&ring->lock);
+    return rc == -NET_ETIMEDOUT ? -NET_EAGAIN : rc;
+  }
+  if (flags & RECV_PEEK) {
+    // copy out while holding the lock, so that
+    // no other receiver can free the packets meanwhile.
+    struct packet *p;
+    for (got = 0; got < n && (p = ring_at(ring, got)) != 0; got++) {
+      struct netmsg msg;
+      uint64 addr = msgsaddr + got * sizeof(msg);
+      if (copyin(pagetable, (char *)&msg, addr, sizeof(msg)) < 0 || msg.maxlen < 0) {
+        rc = -NET_EINVAL;
+        break;
+      }
+      msg.len = payload_copyout(p, (uint64)msg.buf, msg.maxlen, &msg.src, &msg.sport);
+      if (copyout(pagetable, addr, (char *)&msg, sizeof(msg)) < 0) {
+        rc = -NET_EINVAL;
+        break;
+      }
+    }
+    release(&ring->lock);
+    return rc ? rc : got;
   }
+  got = ring_dequeue_many(ring, packets, n);
+  release(&ring->lock);
 
-  /* mop up an odd byte, if necessary */
-  if (nleft == 1) {
-    *(unsigned char *)(&answer) = *(const unsigned char *)w;
-    sum += answer;
+  rc = got;
+  for (int i = 0; i < got; i++) {
+    struct netmsg msg;
+    uint64 addr = msgsaddr + i * sizeof(msg);
+    if (copyin(pagetable, (char *)&msg, addr, sizeof(msg)) < 0 || msg.maxlen < 0) {
+      // still free the rest of the packets.
+      kfree(packets[i].buf);
+      rc = -NET_EINVAL;
+      continue;
+    }
+    msg.len = packet_copyout(&packets[i], (uint64)msg.buf, msg.maxlen, &msg.src, &msg.sport);
+    if (copyout(pagetable, addr, (char *)&msg, sizeof(msg)) < 0)
+      rc = -NET_EINVAL;
   }
 
-  /* add back carry outs from top 16 bits to low 16 bits */
-  sum = (sum & 0xffff) + (sum >> 16);
-  sum += (sum >> 16);
-  /* guaranteed now that the lower 16 bits of sum are correct */
+  return rc;
+}
+
+// netpoll() sleeps on pollchan, holding polllock while it
+// looks at the rings, so that a packet queued after it looked
+// can't be missed: net_rx_flush() and ring_release() take
+// polllock to wake it. lock order: polllock, then a ring's lock.
+static struct spinlock polllock;
+static int pollchan;
 
-  answer = ~sum; /* truncate to 16 bits */
-  return answer;
+static void
+netpoll_wakeup(void)
+{
+  acquire(&polllock);
+  wakeup(&pollchan);
+  release(&polllock);
 }
 
+#define NETPOLL_MAX 32 // ports per netpoll(), one bit each
+
 //
-// send(int sport, int dst, int dport, char *buf, int len)
+// netpoll(uint16 *ports, int n, int timeout)
+// wait until a packet is queued for at least one of the n bound
+// ports, for at most timeout ticks, or forever if it's -1.
+// returns a bitmap with bit i set if ports[i] has a packet,
+// or -NET_ETIMEDOUT, -NET_ENOTBOUND, or -NET_EINVAL.
 //
 uint64
-sys_send(void)
+sys_netpoll(void)
 {
-  struct proc *p = myproc();
-  int sport;
-  int dst;
-  int dport;
-  uint64 bufaddr;
-  int len;
+  uint64 portsaddr;
+  int n, timeout;
+  uint16 ports[NETPOLL_MAX];
 
-  argint(0, &sport);
+  argaddr(0, &portsaddr);
+  argint(1, &n);
+  argint(2, &timeout);
+
+  if (n <= 0 || n > NETPOLL_MAX || timeout < -1)
+    return -NET_EINVAL;
+  if (copyin(myproc()->pagetable, (char *)ports, portsaddr, n * sizeof(ports[0])) < 0)
+    return -NET_EINVAL;
+
+  uint start = ticks;
+  int ready = 0;
+  acquire(&polllock);
+  for (;;) {
+    for (int i = 0; i < n; i++) {
+      struct bind_ring_buffer *ring = find_ring(ports[i]);
+      if (ring == 0) {
+        release(&polllock);
+        return -NET_ENOTBOUND;
+      }
+      acquire(&ring->lock);
+      if (!ring_empty(ring))
+        ready |= 1 << i;
+      release(&ring->lock);
+    }
+    if (ready || killed(myproc()))
+      break;
+    if (timeout == -1) {
+      sleep(&pollchan, &polllock);
+      continue;
+    }
+    int left = timeout - (int)(ticks - start);
+    if (left <= 0)
+      break;
+    sleeptimeout(&pollchan, &polllock, left);
+  }
+  release(&polllock);
+
+  return ready ? ready : -NET_ETIMEDOUT;
+}
+
+// start a UDP or TCP checksum with the IP pseudo-header
+// (RFC 768, RFC 793). src and dst are in network byte order;
+// len, of the UDP or TCP header and payload, in host byte order.
+uint32
+ip_pseudo_sum(uint32 src, uint32 dst, int proto, int len)
+{
+  struct {
+    uint32 src;
+    uint32 dst;
+    uint8 zero;
+    uint8 proto;
+    uint16 len;
+  } __attribute__((packed)) ph = { src, dst, 0, proto, htons(len) };
+
+  return cksum_partial(0, &ph, sizeof(ph));
+}
+
+// whether udp_send() computes UDP checksums and ip_rx() checks
+// them. with it off, datagrams are sent with a checksum of 0,
+// meaning none, which is also what a NIC that fills them in
+// would want. see udpcksum().
+static int udp_cksum = 1;
+
+// ip_id for the next outgoing datagram, protected by netlock.
+static uint16 next_ip_id;
+
+// take an ip_id for an outgoing datagram.
+uint16
+ip_next_id(void)
+{
+  acquire(&netlock);
+  uint16 id = next_ip_id++;
+  release(&netlock);
+  return id;
+}
+
+int ip_rx(char *buf, int len);
+
+// packets to our own address, or to 127.0.0.0/8, never reach
+// the e1000; they're handed straight back to ip_rx().
+static int
+is_loopback(uint32 dst)
+{
+  return dst == local_ip() || (dst >> 24) == 127;
+}
+
+// fill in the ethernet header at the start of the frame in buf,
+// from xv6 to dmac. returns where the ethernet payload goes.
+void *
+eth_build(char *buf, uint8 *dmac, int type)
+{
+  struct eth *eth = (struct eth *) buf;
+
+  memmove(eth->dhost, dmac, ETHADDR_LEN);
+  memmove(eth->shost, local_mac, ETHADDR_LEN); // ethernet source = xv6's ethernet address
+  eth->type = htons(type);
+  return eth + 1;
+}
+
+// fill in an IP header at hdr, from xv6 to dst, for len bytes of
+// payload at byte offset off of datagram id; mf says whether more
+// fragments follow. returns where the IP payload goes, or 0 if
+// the frame wouldn't fit in the page that hdr is in.
+void *
+ip_build(void *hdr, uint32 dst, int proto, int len, uint16 id, int off, int mf)
+{
+  struct ip *ip = hdr;
+
+  if((uint64)hdr % PGSIZE + sizeof(*ip) + len > PGSIZE)
+    return 0;
+
+  ip->ip_vhl = 0x45; // version 4, header length 4*5
+  ip->ip_tos = 0;
+  ip->ip_len = htons(sizeof(*ip) + len);
+  ip->ip_id = htons(id);
+  ip->ip_off = htons((off >> 3) | (mf ? IP_MF : 0));
+  ip->ip_ttl = 100;
+  ip->ip_p = proto;
+  ip->ip_src = htonl(local_ip());
+  ip->ip_dst = htonl(dst);
+  ip->ip_sum = 0;
+  ip->ip_sum = in_cksum(ip, sizeof(*ip));
+  return ip + 1;
+}
+
+//
+// routing: every packet xv6 originates is sent the way
+// route() says, whatever its protocol.
+//
+
+// decide how to send a packet to dst. loopback packets go
+// nowhere near the e1000, and aren't limited by the wire, only
+// by the page. broadcasts go to every host, multicasts to their
+// group, and anything else on our network straight to dst, or
+// else through the gateway; while ARP hasn't found the next hop's
+// ethernet address yet, use the qemu host's, which will pass the
+// packet on. returns 0, or -NET_ENOROUTE if there's no gateway.
+int
+route(uint32 dst, struct route *rt)
+{
+  struct netconf c;
+
+  get_netconfig(&c);
+  memset(rt, 0, sizeof(*rt));
+  rt->hop = dst;
+  rt->mtu = c.mtu;
+
+  if(is_loopback(dst)){
+    rt->loopback = 1;
+    rt->mtu = PGSIZE - sizeof(struct eth);
+    memmove(rt->mac, local_mac, ETHADDR_LEN);
+    return 0;
+  }
+  if(is_broadcast(dst)){
+    memset(rt->mac, 0xff, ETHADDR_LEN);
+    return 0;
+  }
+  if(is_multicast(dst)){
+    multicast_mac(dst, rt->mac);
+    return 0;
+  }
+
+  if((dst & c.mask) != (c.ip & c.mask)){
+    if(c.gateway == 0)
+      return -NET_ENOROUTE;
+    rt->hop = c.gateway;
+  }
+  if(arp_resolve(rt->hop, rt->mac) != 0)
+    memmove(rt->mac, host_mac, ETHADDR_LEN);
+  return 0;
+}
+
+// send the len-byte IP packet, ethernet header and all, in buf
+// the way rt says. takes ownership of buf.
+// returns 0, or -NET_E* if it was dropped.
+int
+route_output(struct route *rt, char *buf, int len)
+{
+  int rc;
+
+  if(rt->loopback){
+    pcap_tap(buf, len, PCAP_LOOP);
+    // ip_rx() frees the buffer itself if it drops the packet.
+    rc = ip_rx(buf, len);
+    net_rx_flush();
+    return rc;
+  }
+  if((rc = e1000_transmit(buf, len)) != 0)
+    kfree(buf);
+  return rc;
+}
+
+// ticks udp_send() waits for the e1000 to free a descriptor.
+#define UDP_TXWAIT 10
+
+// the most ethernet frames one UDP datagram can need. netconf()
+// doesn't allow MTUs below NET_MINMTU, which keeps this few
+// enough for udp_send()'s stack.
+#define UDP_MAXFRAGS (IP_MAXPACKET / ((NET_MINMTU - sizeof(struct ip)) & ~7) + 1)
+
+// build and send a UDP datagram whose payload is the `len` bytes
+// at `src`, a user virtual address if `user` is set, else a kernel
+// address. datagrams that don't fit in one frame are sent as
+// several IP fragments.
+// returns 0, or -NET_E* if the datagram couldn't be sent.
+static int
+udp_send(int sport, uint32 dst, int dport, int user, uint64 src, int len)
+{
+  if(len < 0 || len > IP_MAXPACKET - sizeof(struct ip) - sizeof(struct udp))
+    return -NET_EINVAL;
+
+  struct udp udp;
+  udp.sport = htons(sport);
+  udp.dport = htons(dport);
+  udp.ulen = htons(len + sizeof(struct udp));
+  udp.sum = 0;
+
+  uint16 id = ip_next_id();
+
+  struct route rt;
+  int rc;
+  if((rc = route(dst, &rt)) != 0)
+    return rc;
+
+  // the IP payload is the UDP header followed by the caller's bytes.
+  // every fragment but the last carries a multiple of 8 bytes of it.
+  int ulen = sizeof(struct udp) + len;
+  int maxfrag = (rt.mtu - sizeof(struct ip)) & ~7;
+
+  // the UDP checksum covers the whole datagram, so fragments are
+  // collected and only sent once it's known. those for the wire
+  // are handed to the e1000 in as few batches as its ring allows,
+  // rather than ringing its doorbell once per fragment.
+  uint32 sum = ip_pseudo_sum(htonl(local_ip()), htonl(dst), IPPROTO_UDP, ulen);
+
+  char *frags[UDP_MAXFRAGS];
+  int fraglens[UDP_MAXFRAGS];
+  int nfrags = 0;
+
+  for(int off = 0; off < ulen; off += maxfrag){
+    int n = ulen - off;
+    if(n > maxfrag)
+      n = maxfrag;
+
+    char *buf = kzalloc();
+    if(buf == 0){
+      printf("udp_send: kalloc failed\n");
+      rc = -NET_ENOMEM;
+      goto out;
+    }
+
+    char *payload = ip_build(eth_build(buf, rt.mac, ETHTYPE_IP), dst, IPPROTO_UDP,
+                             n, id, off, off + n < ulen);
+    if(payload == 0){
+      kfree(buf);
+      rc = -NET_EINVAL;
+      goto out;
+    }
+    int hdr = 0;
+    if(off == 0){
+      memmove(payload, &udp, sizeof(udp));
+      hdr = sizeof(udp);
+    }
+
+    uint64 from = src + off + hdr - sizeof(struct udp);
+    if(either_copyin(payload + hdr, user, from, n - hdr) < 0){
+      kfree(buf);
+      printf("send: copyin failed\n");
+      rc = -NET_EINVAL;
+      goto out;
+    }
+
+    sum = cksum_partial(sum, payload, n);
+
+    frags[nfrags] = buf;
+    fraglens[nfrags] = sizeof(struct eth) + sizeof(struct ip) + n;
+    nfrags++;
+  }
+
+  // 0 means "no checksum", so a computed 0 is sent as its
+  // one's complement equivalent.
+  struct udp *uh = (struct udp *)((struct ip *)((struct eth *)frags[0] + 1) + 1);
+  if(atomic_read4(&udp_cksum)){
+    uh->sum = cksum_fold(sum);
+    if(uh->sum == 0)
+      uh->sum = 0xffff;
+  }
+
+  // we're one of the hosts a broadcast or multicast is for,
+  // so hear it too, as well as sending it.
+  if(is_broadcast(dst) || is_multicast(dst)){
+    for(int i = 0; i < nfrags; i++){
+      char *copy = kclone(frags[i], fraglens[i]);
+      if(copy)
+        ip_rx(copy, fraglens[i]);
+    }
+    net_rx_flush();
+  }
+
+  if(rt.loopback){
+    for(int i = 0; i < nfrags && rc == 0; i++){
+      char *buf = frags[i];
+      frags[i] = 0;
+      // ip_rx() frees the buffer itself if it drops the packet.
+      rc = ip_rx(buf, fraglens[i]);
+    }
+    net_rx_flush();
+    goto out;
+  }
+
+  // a datagram missing a fragment can never be put back
+  // together, so when the ring fills partway through, wait for
+  // the e1000 to free descriptors rather than give up.
+  int sent = 0;
+  while(sent < nfrags){
+    int k = e1000_transmit_batch(frags + sent, fraglens + sent, nfrags - sent);
+    for(int i = sent; i < sent + k; i++)
+      frags[i] = 0; // the e1000 owns these now.
+    sent += k;
+    if(k == 0 && e1000_tx_wait(UDP_TXWAIT) != 0){
+      rc = -NET_ERINGBUSY;
+      break;
+    }
+  }
+
+out:
+  for(int i = 0; i < nfrags; i++)
+    if(frags[i])
+      kfree(frags[i]);
+  return rc;
+}
+
+// send a datagram from user memory, as sys_send() describes.
+static int
+send(int sport, uint32 dst, int dport, uint64 bufaddr, int len)
+{
+  int rc;
+
+  if(sport == 0){
+    struct bind_ring_buffer *ring = auto_ring(1);
+    if(ring == 0)
+      return -NET_ENOMEM;
+    sport = ring->dport;
+  }
+  if((rc = udp_send(sport, dst, dport, 1, bufaddr, len)) != 0)
+    return rc;
+  return len;
+}
+
+//
+// send(int sport, int dst, int dport, char *buf, int len)
+// if sport is 0, send from an ephemeral port bound for the
+// caller the first time it does so; recv(0, ...) receives the
+// replies, and the port is unbound when the caller exits.
+// returns the number of payload bytes sent,
+// or -NET_E* if the packet couldn't be sent.
+//
+uint64
+sys_send(void)
+{
+  int sport;
+  int dst;
+  int dport;
+  uint64 bufaddr;
+  int len;
+
+  argint(0, &sport);
   argint(1, &dst);
   argint(2, &dport);
   argaddr(3, &bufaddr);
   argint(4, &len);
 
-  int total = len + sizeof(struct eth) + sizeof(struct ip) + sizeof(struct udp);
-  if(total > PGSIZE)
-    return -1;
+  return send(sport, dst, dport, bufaddr, len);
+}
+
+//
+// sendto(int sport, char *buf, int len, struct sockaddr_in *to)
+// like send(), to the address and port in *to.
+//
+uint64
+sys_sendto(void)
+{
+  int sport;
+  uint64 bufaddr;
+  int len;
+  uint64 toaddr;
+  struct sockaddr_in to;
+
+  argint(0, &sport);
+  argaddr(1, &bufaddr);
+  argint(2, &len);
+  argaddr(3, &toaddr);
+
+  if(copyin(myproc()->pagetable, (char *)&to, toaddr, sizeof(to)) < 0)
+    return -NET_EINVAL;
+  if(to.family != AF_INET)
+    return -NET_EPROTO;
+  return send(sport, to.addr, to.port, bufaddr, len);
+}
+
+//
+// sockets: a bound ring behind a file descriptor, so that
+// read(), write() and close() work on it; see sys_socket().
+//
+
+// bind port, or an ephemeral port if it's 0, and make *f
+// a socket file for it. returns 0, or -NET_E*.
+int
+sockalloc(struct file **f, int port)
+{
+  struct bind_ring_buffer *ring;
+  int rc;
+
+  if(port < 0 || port > 65535)
+    return -NET_EINVAL;
+  if((rc = bind_ring(port, 0, &ring)) != 0)
+    return rc;
+  if((*f = filealloc()) == 0){
+    ring_release(ring, ring->dport);
+    return -NET_ENOMEM;
+  }
+  ring->sock = 1;
+  (*f)->type = FD_SOCK;
+  (*f)->readable = 1;
+  (*f)->writable = 1;
+  (*f)->ring = ring;
+  return 0;
+}
+
+// the socket's last file was closed.
+void
+sockclose(struct bind_ring_buffer *ring)
+{
+  ring_release(ring, ring->dport);
+}
+
+// wait for a datagram on the socket, and copy up to n bytes
+// of its payload to user address addr.
+// returns the number of bytes copied, or -NET_E*.
+int
+sockread(struct bind_ring_buffer *ring, uint64 addr, int n)
+{
+  struct packet packet;
+  uint32 src;
+  uint16 sport;
+  int rc;
+
+  if(n < 0)
+    return -NET_EINVAL;
+
+  acquire(&ring->lock);
+  if((rc = ring_wait(ring, ring->dport, -1)) == 0)
+    ring_dequeue(ring, &packet);
+  release(&ring->lock);
+  if(rc != 0)
+    return rc;
+
+  return packet_copyout(&packet, addr, n, &src, &sport);
+}
+
+// send the n bytes at user address addr as one datagram to
+// the peer the socket's port is connected to.
+// returns n, or -NET_E*, -NET_ENOROUTE if it isn't connected.
+int
+sockwrite(struct bind_ring_buffer *ring, uint64 addr, int n)
+{
+  acquire(&ring->lock);
+  uint32 dst = ring->peer_ip;
+  int dport = ring->peer_port;
+  int sport = ring->dport;
+  release(&ring->lock);
+
+  if(dst == 0 || dport == 0)
+    return -NET_ENOROUTE;
+
+  int rc;
+  if((rc = udp_send(sport, dst, dport, 1, addr, n)) != 0)
+    return rc;
+  return n;
+}
+
+// attach a filter to the socket's ring. returns 0.
+int
+sock_setfilter(struct bind_ring_buffer *ring, struct pktfilter *f)
+{
+  acquire(&ring->lock);
+  ring->filter = *f;
+  release(&ring->lock);
+  return 0;
+}
+
+//
+// raw sockets: every IP packet of one protocol, header and all,
+// for experimenting with protocols from user space; see
+// sys_rawsocket(). they see packets the kernel handles too, so
+// only processes that keep their network privilege get them.
+//
+
+#define NRAW     4  // raw sockets at once
+#define RAW_QLEN 16 // packets a raw socket holds; more are dropped
+
+struct rawsock {
+  struct spinlock lock;
+  int proto;                 // IPPROTO_* it sends and receives; 0 if the slot is free
+  uint32 dst;                // where write() sends, or 0
+  struct packet q[RAW_QLEN]; // IP packets, from the IP header on
+  uint32 read;               // counted as ring_add() does
+  uint32 write;
+  struct pktfilter filter;   // packets to queue; see setfilter()
+};
+
+static struct rawsock raws[NRAW];
+static int nraw; // slots in use, so ip_rx() can skip raw_deliver()
+
+static void
+raws_init(void)
+{
+  for(int i = 0; i < NRAW; i++)
+    initlock(&raws[i].lock, "rawsock");
+}
+
+// give each raw socket for ip's protocol a copy of the len-byte
+// packet; a full one drops its copy. the original goes on to
+// be handled as if there were no raw sockets.
+static void
+raw_deliver(struct ip *ip, int len)
+{
+  if(atomic_read4(&nraw) == 0)
+    return;
+  for(struct rawsock *rs = raws; rs < raws + NRAW; rs++){
+    acquire(&rs->lock);
+    if(rs->proto == ip->ip_p && ring_count(rs->read, rs->write, RAW_QLEN) < RAW_QLEN &&
+       pf_run(&rs->filter, (char *)ip - sizeof(struct eth), sizeof(struct eth) + len)){
+      char *copy = kalloc();
+      if(copy){
+        memmove(copy, ip, len);
+        rs->q[rs->write % RAW_QLEN] = (struct packet){ .buf = copy, .len = len };
+        rs->write = ring_add(rs->write, 1, RAW_QLEN);
+        wakeup(rs);
+      }
+    }
+    release(&rs->lock);
+  }
+}
+
+// open a raw socket for IP protocol proto, sending to dst,
+// and make *f a file for it. returns 0, or -NET_E*.
+int
+rawalloc(struct file **f, int proto, uint32 dst)
+{
+  if(!myproc()->netpriv)
+    return -NET_EPERM;
+  if(proto <= 0 || proto > 255)
+    return -NET_EINVAL;
+
+  struct rawsock *rs;
+  for(rs = raws; rs < raws + NRAW; rs++){
+    acquire(&rs->lock);
+    if(rs->proto == 0){
+      rs->proto = proto;
+      rs->dst = dst;
+      rs->read = rs->write = 0;
+      rs->filter.n = 0;
+      release(&rs->lock);
+      break;
+    }
+    release(&rs->lock);
+  }
+  if(rs == raws + NRAW)
+    return -NET_ENOMEM;
+  __sync_fetch_and_add(&nraw, 1);
+
+  if((*f = filealloc()) == 0){
+    rawclose(rs);
+    return -NET_ENOMEM;
+  }
+  (*f)->type = FD_RAW;
+  (*f)->readable = 1;
+  (*f)->writable = 1;
+  (*f)->raw = rs;
+  return 0;
+}
+
+// the raw socket's last file was closed.
+void
+rawclose(struct rawsock *rs)
+{
+  acquire(&rs->lock);
+  while(rs->read != rs->write){
+    kfree(rs->q[rs->read % RAW_QLEN].buf);
+    rs->read = ring_add(rs->read, 1, RAW_QLEN);
+  }
+  rs->proto = 0;
+  release(&rs->lock);
+  __sync_fetch_and_sub(&nraw, 1);
+}
+
+// attach a filter to the raw socket. returns 0.
+int
+raw_setfilter(struct rawsock *rs, struct pktfilter *f)
+{
+  acquire(&rs->lock);
+  rs->filter = *f;
+  release(&rs->lock);
+  return 0;
+}
+
+// wait for a packet, and copy up to n bytes of it, IP header
+// first, to user address addr.
+// returns the number of bytes copied, or -NET_E*, or -1.
+int
+rawread(struct rawsock *rs, uint64 addr, int n)
+{
+  struct proc *p = myproc();
+
+  if(n < 0)
+    return -NET_EINVAL;
+
+  acquire(&rs->lock);
+  while(rs->read == rs->write){
+    if(killed(p)){
+      release(&rs->lock);
+      return -1;
+    }
+    sleep(rs, &rs->lock);
+  }
+  struct packet packet = rs->q[rs->read % RAW_QLEN];
+  rs->read = ring_add(rs->read, 1, RAW_QLEN);
+  release(&rs->lock);
+
+  if(n > packet.len)
+    n = packet.len;
+  if(copyout(p->pagetable, addr, packet.buf, n) < 0)
+    n = -NET_EINVAL;
+  kfree(packet.buf);
+  return n;
+}
+
+// send the n bytes at user address addr as the payload of one
+// IP packet of the socket's protocol to its destination. the
+// kernel writes the IP header; packets aren't fragmented.
+// returns n, or -NET_E*.
+int
+rawwrite(struct rawsock *rs, uint64 addr, int n)
+{
+  struct route rt;
+  int rc;
+
+  if(rs->dst == 0)
+    return -NET_ENOROUTE;
+  if((rc = route(rs->dst, &rt)) != 0)
+    return rc;
+  if(n < 0 || sizeof(struct ip) + n > rt.mtu)
+    return -NET_EINVAL;
+
+  char *buf = kalloc();
+  if(buf == 0)
+    return -NET_ENOMEM;
+  void *payload = ip_build(buf + sizeof(struct eth), rs->dst, rs->proto, n,
+                           ip_next_id(), 0, 0);
+  if(copyin(myproc()->pagetable, payload, addr, n) < 0){
+    kfree(buf);
+    return -NET_EINVAL;
+  }
+  eth_build(buf, rt.mac, ETHTYPE_IP);
+  if((rc = route_output(&rt, buf, sizeof(struct eth) + sizeof(struct ip) + n)) != 0)
+    return rc;
+  return n;
+}
+
+//
+// dropnetpriv()
+// give up network privilege, for good: the process, and the
+// children it forks from now on, can no longer open raw
+// sockets. returns 0.
+//
+uint64
+sys_dropnetpriv(void)
+{
+  myproc()->netpriv = 0;
+  return 0;
+}
+
+//
+// portstats(int port, struct portstats *st)
+// copy out the queue counters of the caller's binding of port.
+// returns 0, -NET_ENOTBOUND if the caller hasn't bound port,
+// or -NET_EINVAL if st is a bad address.
+//
+uint64
+sys_portstats(void)
+{
+  int port;
+  uint64 addr;
+  struct portstats st;
+  argint(0, &port);
+  argaddr(1, &addr);
+
+  struct bind_ring_buffer *ring = lock_own_ring(port);
+  if (ring == 0)
+    return -NET_ENOTBOUND;
+
+  st.received = ring->pushed;
+  st.dropped = ring->dropped;
+  st.depth = ring_size(ring);
+  st.wakeups = ring->wakeups;
+  st.badsum = ring->badsum;
+  st.bytes = ring->bytes;
+  st.dropbytes = ring->dropbytes;
+  release(&ring->lock);
+
+  if (copyout(myproc()->pagetable, addr, (char *)&st, sizeof(st)) < 0)
+    return -NET_EINVAL;
+  return 0;
+}
+
+//
+// netstat(struct netstat *ns, int n)
+// copy out up to n lines describing the network's state: one
+// for each bound UDP port, then one for each TCP connection.
+// returns the number of lines, or -NET_EINVAL or -NET_ENOMEM.
+//
+uint64
+sys_netstat(void)
+{
+  uint64 addr;
+  int n;
+  argaddr(0, &addr);
+  argint(1, &n);
+
+  if (n < 0)
+    return -NET_EINVAL;
+  if (n > PGSIZE / sizeof(struct netstat))
+    n = PGSIZE / sizeof(struct netstat);
+  // gather the lines first, so that no lock is held
+  // across copyout().
+  struct netstat *ns = (struct netstat *)kalloc();
+  if (ns == 0)
+    return -NET_ENOMEM;
+
+  int i = 0;
+  acquireread(&ringtable.lock);
+  for (int b = 0; b < RING_BUCKETS; b++) {
+    for (struct bind_ring_buffer *r = ringtable.buckets[b]; r && i < n; r = r->hnext) {
+      struct netstat *s = &ns[i++];
+      memset(s, 0, sizeof(*s));
+      acquire(&r->lock);
+      s->proto = IPPROTO_UDP;
+      s->lport = r->dport;
+      s->rport = r->peer_port;
+      s->raddr = r->peer_ip;
+      s->pid = r->pid;
+      s->recvq = ring_size(r);
+      s->dropped = r->dropped;
+      release(&r->lock);
+    }
+  }
+  releaseread(&ringtable.lock);
+  i += tcp_netstat(ns + i, n - i);
+
+  int rc = i;
+  if (copyout(myproc()->pagetable, addr, (char *)ns, i * sizeof(*ns)) < 0)
+    rc = -NET_EINVAL;
+  kfree((char *)ns);
+  return rc;
+}
+
+//
+// connect(int port, int peer_ip, int peer_port)
+// only queue packets for the caller's binding of port that come
+// from peer_ip:peer_port; 0 for either means any. connect(port, 0, 0)
+// accepts everything again. packets already queued are kept.
+// on a port shared with BIND_REUSEPORT, the peer's packets go to
+// this binding alone, and the rest to the unconnected ones.
+// returns 0, or -NET_ENOTBOUND if the caller hasn't bound port.
+//
+uint64
+sys_connect(void)
+{
+  int port, peer_ip, peer_port;
+  argint(0, &port);
+  argint(1, &peer_ip);
+  argint(2, &peer_port);
+
+  if (peer_port < 0 || peer_port > 65535)
+    return -NET_EINVAL;
+
+  struct bind_ring_buffer *ring = lock_own_ring(port);
+  if (ring == 0)
+    return -NET_ENOTBOUND;
+
+  ring->peer_ip = peer_ip;
+  ring->peer_port = peer_port;
+  release(&ring->lock);
+  return 0;
+}
+
+//
+// queuelen(int port, int n)
+// let the caller's binding of port queue up to n packets, from 1
+// to a page's worth, rather than MAX_QUEUE_LEN. packets already
+// queued are kept, so n can't be less than how many there are.
+// returns 0, or -NET_EINVAL, -NET_ENOTBOUND, or -NET_ENOMEM.
+//
+uint64
+sys_queuelen(void)
+{
+  int port, n;
+  argint(0, &port);
+  argint(1, &n);
+
+  if (n < 1 || n > RING_MAXCAP)
+    return -NET_EINVAL;
+
+  if (own_ring(port) == 0)
+    return -NET_ENOTBOUND;
+
+  // kalloc() before taking the lock, then look again:
+  // the port may have been unbound in between.
+  struct packet *q = 0;
+  if (n > MAX_QUEUE_LEN && (q = (struct packet *)kalloc()) == 0)
+    return -NET_ENOMEM;
+
+  struct bind_ring_buffer *ring = lock_own_ring(port);
+  if (ring == 0) {
+    if (q)
+      kfree((char *)q);
+    return -NET_ENOTBOUND;
+  }
+  int have = ring_size(ring);
+  if (have > n) {
+    release(&ring->lock);
+    if (q)
+      kfree((char *)q);
+    return -NET_EINVAL;
+  }
+
+  // copy the queued packets out in order, oldest to slot 0,
+  // before switching queues: the new one may be the old one.
+  struct packet tmp[MAX_QUEUE_LEN];
+  struct packet *to = q ? q : tmp;
+  for (int i = 0; i < have; i++)
+    to[i] = *ring_at(ring, i);
+  if (ring->queue != ring->small)
+    kfree((char *)ring->queue);
+  if (q == 0)
+    memmove(ring->small, tmp, have * sizeof(tmp[0]));
+  ring->queue = q ? q : ring->small;
+  ring->cap = n;
+  ring->read = 0;
+  ring->write = have;
+  release(&ring->lock);
+  return 0;
+}
+
+//
+// joinmulticast(int port, int group)
+// also queue packets for the caller's binding of port that are
+// sent to multicast group (224.0.0.0/4); a binding is in at most
+// one group, and joinmulticast(port, 0) leaves it.
+// returns 0, or -NET_EINVAL or -NET_ENOTBOUND.
+//
+uint64
+sys_joinmulticast(void)
+{
+  int port, group;
+  argint(0, &port);
+  argint(1, &group);
+
+  if (group != 0 && !is_multicast(group))
+    return -NET_EINVAL;
+
+  if (own_ring(port) == 0)
+    return -NET_ENOTBOUND;
+
+  // the e1000 drops multicast frames it hasn't been told about.
+  if (group != 0) {
+    uint8 mac[ETHADDR_LEN];
+    multicast_mac(group, mac);
+    e1000_join_multicast(mac);
+  }
+
+  struct bind_ring_buffer *ring = lock_own_ring(port);
+  if (ring == 0)
+    return -NET_ENOTBOUND;
+  ring->group = group;
+  release(&ring->lock);
+  return 0;
+}
+
+//
+// netconf(struct netconf *old, struct netconf *new)
+// if old isn't 0, copy the network configuration out to it.
+// then, if new isn't 0, replace the configuration with *new,
+// all but its mac, which is always the e1000's. changing it
+// needs network privilege; see dropnetpriv().
+// returns 0, -NET_EPERM, or -NET_EINVAL if an address is bad:
+// new's ip is 0 or multicast, its netmask isn't contiguous,
+// its gateway is outside its subnet, or its mtu is out of range.
+//
+uint64
+sys_netconf(void)
+{
+  uint64 oldaddr, newaddr;
+  struct netconf c;
+  pagetable_t pagetable = myproc()->pagetable;
+
+  argaddr(0, &oldaddr);
+  argaddr(1, &newaddr);
+
+  if (newaddr != 0) {
+    if (!myproc()->netpriv)
+      return -NET_EPERM;
+    if (copyin(pagetable, (char *)&c, newaddr, sizeof(c)) < 0)
+      return -NET_EINVAL;
+    uint32 host = ~c.mask;
+    if ((host & (host + 1)) != 0 || c.mtu < NET_MINMTU || c.mtu > ETH_MTU)
+      return -NET_EINVAL;
+    if (c.ip == 0 || is_multicast(c.ip) ||
+        (c.gateway != 0 && (c.gateway & c.mask) != (c.ip & c.mask)))
+      return -NET_EINVAL;
+  }
+
+  if (oldaddr != 0) {
+    struct netconf old;
+    get_netconfig(&old);
+    memmove(old.mac, local_mac, ETHADDR_LEN);
+    if (copyout(pagetable, oldaddr, (char *)&old, sizeof(old)) < 0)
+      return -NET_EINVAL;
+  }
+
+  if (newaddr != 0)
+    set_netconfig(&c);
+  return 0;
+}
+
+//
+// nicstats(struct nicstats *st)
+// copy the e1000's packet counters out to *st.
+// returns 0, or -NET_EINVAL if st is a bad address.
+//
+uint64
+sys_nicstats(void)
+{
+  uint64 addr;
+  struct nicstats st;
+  argaddr(0, &addr);
+
+  e1000_stats(&st);
+  if (copyout(myproc()->pagetable, addr, (char *)&st, sizeof(st)) < 0)
+    return -NET_EINVAL;
+  return 0;
+}
+
+//
+// linkstatus()
+// returns the e1000's link speed in Mb/s (10, 100 or 1000),
+// or 0 if the link is down.
+//
+uint64
+sys_linkstatus(void)
+{
+  return e1000_link_status();
+}
+
+//
+// udpcksum(int on)
+// turn computing and checking UDP checksums on (1) or off (0).
+// returns the previous setting, or -NET_EINVAL.
+//
+uint64
+sys_udpcksum(void)
+{
+  int on;
+  argint(0, &on);
+
+  if (on != 0 && on != 1)
+    return -NET_EINVAL;
+  return __atomic_exchange_n(&udp_cksum, on, __ATOMIC_SEQ_CST);
+}
+
+//
+// loglevel(int level)
+// set the kernel log level (LOG_ERROR .. LOG_DEBUG in
+// kernel/param.h); messages less severe than it are
+// suppressed. needs network privilege; see dropnetpriv().
+// returns the previous level, or -NET_EPERM.
+//
+uint64
+sys_loglevel(void)
+{
+  int level;
+  argint(0, &level);
+
+  if (!myproc()->netpriv)
+    return -NET_EPERM;
+  return setloglevel(level);
+}
+
+// check the ring index arithmetic with a capacity that isn't a
+// power of 2: a FIFO of ints, pushed and popped unevenly until
+// its indices have wrapped many times. then fill and empty a
+// real ring whose indices start just short of wrapping, and
+// peek into it and overwrite its oldest packets once it's full.
+// returns 0, or -1 on failure.
+static int
+ring_selftest(void)
+{
+  enum { CAP = 10 };
+  int q[CAP];
+  uint32 read = 0, write = 0;
+  int in = 0, out = 0;
+
+  for(int round = 0; round < 100; round++){
+    for(int k = 0; k < round % 7 + 1 && ring_count(read, write, CAP) < CAP; k++){
+      q[write % CAP] = in++;
+      write = ring_add(write, 1, CAP);
+    }
+    if(ring_count(read, write, CAP) != in - out)
+      return -1;
+    for(int k = 0; k < round % 5 + 1 && read != write; k++){
+      if(q[read % CAP] != out++)
+        return -1;
+      read = ring_add(read, 1, CAP);
+    }
+  }
+  if(in < 10 * CAP)
+    return -1; // didn't wrap enough to prove anything
+
+  struct bind_ring_buffer ring;
+  struct packet p[MAX_QUEUE_LEN];
+  memset(&ring, 0, sizeof(ring));
+  ring.queue = ring.small;
+  ring.cap = MAX_QUEUE_LEN;
+  ring.read = ring.write = 2 * MAX_QUEUE_LEN - 3;
+  for(int i = 0; i < MAX_QUEUE_LEN; i++){
+    struct packet packet = { .len = i };
+    if(ring_enqueue(&ring, packet) != 0)
+      return -1;
+  }
+  struct packet extra = { .len = -1 };
+  if(!ring_full(&ring) || ring_enqueue(&ring, extra) == 0)
+    return -1;
+  if(ring_dequeue_many(&ring, p, MAX_QUEUE_LEN) != MAX_QUEUE_LEN || !ring_empty(&ring))
+    return -1;
+  for(int i = 0; i < MAX_QUEUE_LEN; i++)
+    if(p[i].len != i)
+      return -1;
+
+  // peek and overwrite on the now empty ring, past the wrap:
+  // fill it, then keep pushing so each push evicts the oldest.
+  struct packet evicted;
+  if(ring_peek(&ring) != 0 || ring_at(&ring, 0) != 0)
+    return -1;
+  for(int i = 0; i < 2 * MAX_QUEUE_LEN; i++){
+    struct packet packet = { .len = 100 + i };
+    int full = i >= MAX_QUEUE_LEN;
+    if(ring_enqueue_overwrite(&ring, packet, &evicted) != full)
+      return -1;
+    if(full && evicted.len != 100 + i - MAX_QUEUE_LEN)
+      return -1;
+    if(ring_peek(&ring) == 0 || ring_peek(&ring)->len != (full ? 101 + i - MAX_QUEUE_LEN : 100))
+      return -1;
+  }
+  if(!ring_full(&ring) || ring.dropped != 1)
+    return -1;
+  if(ring_at(&ring, MAX_QUEUE_LEN - 1)->len != 99 + 2 * MAX_QUEUE_LEN ||
+     ring_at(&ring, MAX_QUEUE_LEN) != 0)
+    return -1;
+  for(int i = 0; i < MAX_QUEUE_LEN; i++)
+    if(ring_dequeue(&ring, &evicted) != 0 || evicted.len != 100 + MAX_QUEUE_LEN + i)
+      return -1;
+  if(ring_peek(&ring) != 0 || ring_dequeue(&ring, &evicted) == 0)
+    return -1;
+
+  return 0;
+}
+
+// check route()'s decisions for ourselves, a host on our network,
+// one off it, and a broadcast. returns 0, or -1 if one is wrong.
+static int
+route_selftest(void)
+{
+  struct netconf c;
+  struct route rt;
+
+  get_netconfig(&c);
+  // the address next to ours is on our network, unless it's a /32;
+  // flipping the top bit leaves it, unless it's a /0.
+  uint32 onlink = c.ip ^ 1;
+  uint32 offlink = c.ip ^ 0x80000000;
+
+  if(route(c.ip, &rt) != 0 || !rt.loopback)
+    return -1;
+  if((c.mask & 1) == 0 && (route(onlink, &rt) != 0 || rt.loopback || rt.hop != onlink))
+    return -1;
+  if(c.mask != 0 && c.gateway && (route(offlink, &rt) != 0 || rt.hop != c.gateway))
+    return -1;
+  if(route(0xffffffff, &rt) != 0 || rt.mac[0] != 0xff || rt.mtu != c.mtu)
+    return -1;
+  return 0;
+}
+
+// bind ring's port a second time, which must fail, leaving
+// the port mapped to ring alone. returns 0, or -1 if it isn't.
+static int
+bind_dup_selftest(struct bind_ring_buffer *ring)
+{
+  struct bind_ring_buffer *dup, *found[2];
+
+  int rc = bind_ring(ring->dport, 0, &dup);
+  if(rc == 0)
+    ring_release(dup, dup->dport);
+  if(rc != -NET_EADDRINUSE)
+    return -1;
+  if(find_rings(ring->dport, found, NELEM(found)) != 1 || found[0] != ring)
+    return -1;
+  return 0;
+}
+
+// hand ip_rx() frames that are shorter than their headers say,
+// to port. each must be dropped as truncated.
+// returns 0, or -1 if one wasn't.
+static int
+rx_truncated_selftest(int port)
+{
+  int lens[] = {
+    sizeof(struct eth) + 4,                                      // a piece of IP header
+    sizeof(struct eth) + sizeof(struct ip) + sizeof(struct udp), // UDP says 100 bytes
+  };
+
+  for(int i = 0; i < NELEM(lens); i++){
+    char *buf = kzalloc();
+    if(buf == 0)
+      return -1;
+    struct udp *udp = ip_build(eth_build(buf, local_mac, ETHTYPE_IP), local_ip(),
+                               IPPROTO_UDP, 100, 0, 0, 0);
+    udp->sport = udp->dport = htons(port);
+    udp->ulen = htons(100);
+    if(ip_rx(buf, lens[i]) != -NET_ETRUNC)
+      return -1;
+  }
+  return 0;
+}
+
+// hand ip_rx() a datagram for ring's port with a wrong UDP
+// checksum. if checksums are on, it must be dropped and counted
+// against the port. returns 0, or -1 on failure.
+static int
+rx_badsum_selftest(struct bind_ring_buffer *ring)
+{
+  int ulen = sizeof(struct udp) + 4;
+
+  if(!atomic_read4(&udp_cksum))
+    return 0;
+
+  char *buf = kzalloc();
+  if(buf == 0)
+    return -1;
+  struct udp *udp = ip_build(eth_build(buf, local_mac, ETHTYPE_IP), local_ip(),
+                             IPPROTO_UDP, ulen, 0, 0, 0);
+  udp->sport = udp->dport = htons(ring->dport);
+  udp->ulen = htons(ulen);
+  uint16 good = cksum_fold(cksum_partial(ip_pseudo_sum(htonl(local_ip()), htonl(local_ip()), IPPROTO_UDP, ulen),
+                                         udp, ulen));
+  udp->sum = good == 0x1234 ? 0x4321 : 0x1234;
+
+  uint64 before = ring->badsum;
+  if(ip_rx(buf, sizeof(struct eth) + sizeof(struct ip) + ulen) != -NET_EBADSUM ||
+     ring->badsum != before + 1)
+    return -1;
+  return 0;
+}
+
+// a receive filter for rx_filter_selftest(): drop whatever
+// comes from 10.0.2.99.
+static int
+drop_from_99(struct ip *ip, struct udp *udp)
+{
+  return ntohl(ip->ip_src) == 0x0A000263 ? NET_DROP : NET_ACCEPT;
+}
+
+// with drop_from_99() installed as the receive filter, hand
+// ip_rx() a datagram from 10.0.2.99 for ring's port. it must be
+// dropped as filtered, leaving the ring empty.
+// returns 0, or -1 on failure.
+static int
+rx_filter_selftest(struct bind_ring_buffer *ring)
+{
+  int ulen = sizeof(struct udp);
+
+  char *buf = kzalloc();
+  if(buf == 0)
+    return -1;
+  struct udp *udp = ip_build(eth_build(buf, local_mac, ETHTYPE_IP), local_ip(),
+                             IPPROTO_UDP, ulen, 0, 0, 0);
+  struct ip *ip = (struct ip *)(buf + sizeof(struct eth));
+  ip->ip_src = htonl(0x0A000263);
+  ip->ip_sum = 0;
+  ip->ip_sum = in_cksum(ip, sizeof(*ip));
+  udp->sport = udp->dport = htons(ring->dport);
+  udp->ulen = htons(ulen);
+
+  net_set_rx_filter(drop_from_99);
+  int rc = ip_rx(buf, sizeof(struct eth) + sizeof(struct ip) + ulen);
+  net_set_rx_filter(0);
+
+  acquire(&ring->lock);
+  int empty = ring_empty(ring);
+  release(&ring->lock);
+  return rc == -NET_EFILTERED && empty ? 0 : -1;
+}
+
+// hand ip_rx() a broadcast for a port that two rings share.
+// both must be given the same page rather than copies, and it
+// must outlive the first kfree(). returns 0, or -1 on failure.
+static int
+rx_shared_selftest(void)
+{
+  struct bind_ring_buffer *r[2] = { 0, 0 };
+  struct packet p[2];
+  struct udpview v;
+  int ulen = sizeof(struct udp) + 4;
+  int n = 0, rc = -1;
+
+  struct bind_ring_buffer *any = bind_any_ring();
+  if(any == 0)
+    return -1;
+  int port = any->dport;
+  ring_release(any, port);
+  for(int i = 0; i < 2; i++)
+    if(bind_ring(port, BIND_REUSEPORT | BIND_BROADCAST, &r[i]) != 0)
+      goto out;
+
+  char *buf = kzalloc();
+  if(buf == 0)
+    goto out;
+  struct udp *udp = ip_build(eth_build(buf, local_mac, ETHTYPE_IP), 0xffffffff,
+                             IPPROTO_UDP, ulen, 0, 0, 0);
+  udp->sport = udp->dport = htons(port);
+  udp->ulen = htons(ulen);
+  memmove(udp + 1, "bcst", 4);
+  if(ip_rx(buf, sizeof(struct eth) + sizeof(struct ip) + ulen) != 0)
+    goto out;
+
+  for(int i = 0; i < 2; i++){
+    acquire(&r[i]->lock);
+    if(ring_dequeue(r[i], &p[n]) == 0)
+      n++;
+    release(&r[i]->lock);
+  }
+  if(n == 2 && p[0].buf == buf && p[1].buf == buf){
+    // had that freed the page, kfree() would have junked it.
+    kfree(p[0].buf);
+    if(udp_parse(p[1].buf, p[1].len, &v) == 0 && memcmp(v.payload, "bcst", 4) == 0)
+      rc = 0;
+    kfree(p[1].buf);
+    n = 0;
+  }
+  for(int i = 0; i < n; i++)
+    kfree(p[i].buf);
+
+out:
+  for(int i = 0; i < 2; i++)
+    if(r[i])
+      ring_release(r[i], r[i]->dport);
+  return rc;
+}
+
+//
+// netselftest()
+// send a UDP packet to ourselves over the loopback path,
+// receive it, and check that the payload arrived intact.
+// returns 0 on success, -1 on failure.
+//
+uint64
+sys_netselftest(void)
+{
+  static char msg[] = "xv6 loopback self-test";
+  int rc = -1;
+
+  if(cksum_selftest() != 0){
+    printf("netselftest: checksum mismatch\n");
+    return -1;
+  }
+
+  if(e1000_rx_selftest() != 0){
+    printf("netselftest: rx refill handed out a descriptor without a buffer\n");
+    return -1;
+  }
+
+  if(e1000_tx_copy_selftest() != 0){
+    printf("netselftest: transmitting a copy disturbed the caller's buffer\n");
+    return -1;
+  }
+
+  if(rwlock_selftest() != 0){
+    printf("netselftest: reader-writer lock kept the wrong state\n");
+    return -1;
+  }
+
+  if(sleeptimeout_selftest() != 0){
+    printf("netselftest: sleeptimeout() didn't time out\n");
+    return -1;
+  }
+
+  if(ring_selftest() != 0){
+    printf("netselftest: packet ring lost count across wrap-around\n");
+    return -1;
+  }
+
+  if(route_selftest() != 0){
+    printf("netselftest: bad routing decision\n");
+    return -1;
+  }
+
+  if(arp_selftest() != 0){
+    printf("netselftest: ARP requests weren't held off\n");
+    return -1;
+  }
+
+  if(tcp_selftest() != 0){
+    printf("netselftest: bad TCP retransmission timeout\n");
+    return -1;
+  }
+
+  struct bind_ring_buffer *ring = bind_any_ring();
+  if(ring == 0)
+    return -1;
+  int port = ring->dport;
+
+  // the datagram below must then arrive on ring.
+  if(bind_dup_selftest(ring) != 0){
+    printf("netselftest: port bound twice\n");
+    goto out;
+  }
+
+  if(udp_send(port, local_ip(), port, 0, (uint64)msg, sizeof(msg)) != 0){
+    printf("netselftest: send failed\n");
+    goto out;
+  }
+
+  // the loopback path delivers synchronously, so there's no need to sleep.
+  struct packet packet;
+  acquire(&ring->lock);
+  int empty = ring_dequeue(ring, &packet);
+  release(&ring->lock);
+  if(empty){
+    printf("netselftest: nothing was received\n");
+    goto out;
+  }
+
+  struct udpview v;
+  if(udp_parse(packet.buf, packet.len, &v) == 0 &&
+     ntohl(v.ip->ip_src) == local_ip() && packet.sport == port &&
+     v.paylen == sizeof(msg) && memcmp(v.payload, msg, sizeof(msg)) == 0)
+    rc = 0;
+  else
+    printf("netselftest: payload mismatch\n");
+  kfree(packet.buf);
+
+  if(rc == 0 && rx_truncated_selftest(port) != 0){
+    printf("netselftest: truncated packet wasn't dropped\n");
+    rc = -1;
+  }
+
+  if(rc == 0 && rx_badsum_selftest(ring) != 0){
+    printf("netselftest: bad UDP checksum wasn't dropped and counted\n");
+    rc = -1;
+  }
+
+  if(rc == 0 && rx_filter_selftest(ring) != 0){
+    printf("netselftest: the receive filter didn't drop a packet\n");
+    rc = -1;
+  }
+
+  if(rc == 0 && rx_shared_selftest() != 0){
+    printf("netselftest: a broadcast wasn't shared by its port's rings\n");
+    rc = -1;
+  }
+
+out:
+  ring_release(ring, port);
+  return rc;
+}
+
+//
+// DNS resolver
+//
+
+#define DNS_TIMEOUT 50 // ticks to wait for a reply, about 5 seconds
+
+// encode host ("a.b.c", with or without a trailing dot) as a
+// DNS name in qn. returns the encoded length, or -1 if a label
+// is empty or too long.
+static int
+dns_encode_name(char *qn, char *host)
+{
+  char *start = qn;
+
+  while(*host){
+    char *dot = host;
+    while(*dot && *dot != '.')
+      dot++;
+    int l = dot - host;
+    if(l == 0 || l > 63)
+      return -1;
+    *qn++ = l;
+    memmove(qn, host, l);
+    qn += l;
+    host = *dot ? dot + 1 : dot;
+  }
+  *qn++ = 0;
+
+  return qn - start;
+}
+
+// skip the possibly compressed name at buf[off].
+// returns the offset just after it, or -1 if it runs off the end.
+static int
+dns_skip_name(uint8 *buf, int len, int off)
+{
+  while(off < len){
+    int l = buf[off];
+    if((l & 0xc0) == 0xc0) // a pointer ends the name
+      return off + 2 <= len ? off + 2 : -1;
+    off += 1 + l;
+    if(l == 0)
+      return off;
+  }
+  return -1;
+}
+
+// find the first A record in the DNS reply to query id.
+// returns 0 and sets *ip, or -1 if there isn't one.
+static int
+dns_parse(uint8 *buf, int len, uint16 id, uint32 *ip)
+{
+  struct dns *hdr = (struct dns *) buf;
+
+  if(len < sizeof(*hdr) || !hdr->qr || ntohs(hdr->id) != id || hdr->rcode != 0)
+    return -1;
+
+  int off = sizeof(*hdr);
+  for(int i = 0; i < ntohs(hdr->qdcount); i++){
+    if((off = dns_skip_name(buf, len, off)) < 0)
+      return -1;
+    off += sizeof(struct dns_question);
+  }
+
+  for(int i = 0; i < ntohs(hdr->ancount); i++){
+    if((off = dns_skip_name(buf, len, off)) < 0 || off + sizeof(struct dns_data) > len)
+      return -1;
+    struct dns_data *d = (struct dns_data *) (buf + off);
+    off += sizeof(*d);
+    int dlen = ntohs(d->len);
+    if(off + dlen > len)
+      return -1;
+    if(ntohs(d->type) == ARECORD && ntohs(d->class) == QCLASS && dlen == 4){
+      *ip = ((uint32)buf[off] << 24) | (buf[off+1] << 16) | (buf[off+2] << 8) | buf[off+3];
+      return 0;
+    }
+    off += dlen;
+  }
+
+  return -1;
+}
+
+//
+// resolve(char *host, uint32 *ip)
+// look up host's IPv4 address with a DNS query to the
+// name server in the network configuration.
+// sets *ip (host byte order) to the first A record in the reply.
+// returns 0, or -NET_E* if the name couldn't be resolved.
+//
+uint64
+sys_resolve(void)
+{
+  char host[128];
+  uint64 ipaddr;
+  uint8 q[256];
+  int rc;
+
+  if(argstr(0, host, sizeof(host)) < 0)
+    return -NET_EINVAL;
+  argaddr(1, &ipaddr);
+
+  uint16 id = ip_next_id();
+
+  memset(q, 0, sizeof(q));
+  struct dns *hdr = (struct dns *) q;
+  hdr->id = htons(id);
+  hdr->rd = 1;
+  hdr->qdcount = htons(1);
+  int len = sizeof(*hdr);
+  int n = dns_encode_name((char *)q + len, host);
+  if(n < 0)
+    return -NET_EINVAL;
+  len += n;
+  struct dns_question *qu = (struct dns_question *) (q + len);
+  qu->qtype = htons(ARECORD);
+  qu->qclass = htons(QCLASS);
+  len += sizeof(*qu);
+
+  struct bind_ring_buffer *ring = bind_any_ring();
+  if(ring == 0)
+    return -NET_ENOMEM;
+
+  struct netconf c;
+  get_netconfig(&c);
+  if((rc = udp_send(ring->dport, c.dns, 53, 0, (uint64)q, len)) != 0)
+    goto out;
+
+  // replies that aren't the answer are skipped, and the wait
+  // goes on for what's left of DNS_TIMEOUT.
+  uint start = ticks;
+  rc = -NET_ETIMEDOUT;
+  while(!killed(myproc())){
+    struct packet packet;
+    int left = DNS_TIMEOUT - (int)(ticks - start);
+    acquire(&ring->lock);
+    if((rc = ring_wait(ring, ring->dport, left < 0 ? 0 : left)) == 0)
+      ring_dequeue(ring, &packet);
+    release(&ring->lock);
+    if(rc != 0)
+      break;
+
+    rc = -NET_ETIMEDOUT;
+    struct udpview v;
+    uint32 addr;
+    int ok = packet.sport == 53 && udp_parse(packet.buf, packet.len, &v) == 0 &&
+      dns_parse((uint8 *)v.payload, v.paylen, id, &addr) == 0;
+    kfree(packet.buf);
+    if(ok){
+      rc = copyout(myproc()->pagetable, ipaddr, (char *)&addr, sizeof(addr)) < 0 ? -NET_EINVAL : 0;
+      break;
+    }
+  }
+
+out:
+  ring_release(ring, ring->dport);
+  return rc;
+}
+
+//
+// DHCP client
+//
+// dhcp() leases xv6's IP address from qemu's DHCP server
+// at boot, along with the netmask, gateway, and name server:
+// broadcast a DISCOVER, wait for an OFFER, REQUEST the offered
+// address, and wait for the ACK. the client's states are those
+// of RFC 2131, less the ones for renewing a lease.
+//
+
+enum dhcp_state {
+  DHCP_SELECTING,  // DISCOVER sent, waiting for an OFFER
+  DHCP_REQUESTING, // REQUEST sent, waiting for an ACK
+  DHCP_BOUND,      // leased
+};
+
+#define DHCP_TRIES   4
+#define DHCP_TIMEOUT 10  // ticks to wait for the first reply; doubled on each retry
+#define DHCP_MINLEN  300 // BOOTP's fixed message size, which some servers insist on
+
+// what a DHCP server's reply offers.
+struct dhcp_lease {
+  uint32 ip;
+  uint32 mask;
+  uint32 gateway;
+  uint32 dns;
+  uint32 server; // the server's identifier, to REQUEST from
+};
+
+// the IP address in the 4 bytes at p, network byte order.
+static uint32
+dhcp_get_addr(uint8 *p)
+{
+  return ((uint32)p[0] << 24) | (p[1] << 16) | (p[2] << 8) | p[3];
+}
+
+// append an option holding IP address ip to the options at o.
+// returns where the next option goes.
+static uint8 *
+dhcp_put_addr(uint8 *o, int code, uint32 ip)
+{
+  *o++ = code;
+  *o++ = 4;
+  *o++ = ip >> 24;
+  *o++ = ip >> 16;
+  *o++ = ip >> 8;
+  *o++ = ip;
+  return o;
+}
+
+// build a client message of the given type for transaction xid
+// in buf, which must hold DHCP_MINLEN bytes. a DHCP_REQUEST asks
+// for lease->ip from lease->server. returns the message's length.
+static int
+dhcp_build(uint8 *buf, int type, uint32 xid, struct dhcp_lease *lease)
+{
+  struct dhcp *d = (struct dhcp *) buf;
+
+  memset(buf, 0, DHCP_MINLEN);
+  d->op = DHCP_BOOTREQUEST;
+  d->htype = ARP_HRD_ETHER;
+  d->hlen = ETHADDR_LEN;
+  d->xid = htonl(xid);
+  d->flags = htons(0x8000); // broadcast the reply; we have no address yet
+  memmove(d->chaddr, local_mac, ETHADDR_LEN);
+  d->magic = htonl(DHCP_MAGIC);
+
+  uint8 *o = (uint8 *)(d + 1);
+  *o++ = DHCP_OPT_MSGTYPE;
+  *o++ = 1;
+  *o++ = type;
+  *o++ = DHCP_OPT_PARAMS;
+  *o++ = 3;
+  *o++ = DHCP_OPT_NETMASK;
+  *o++ = DHCP_OPT_ROUTER;
+  *o++ = DHCP_OPT_DNS;
+  if(type == DHCP_REQUEST){
+    o = dhcp_put_addr(o, DHCP_OPT_REQIP, lease->ip);
+    o = dhcp_put_addr(o, DHCP_OPT_SERVERID, lease->server);
+  }
+  *o++ = DHCP_OPT_END;
 
-  char *buf = kalloc();
-  if(buf == 0){
-    printf("sys_send: kalloc failed\n");
+  return o - buf < DHCP_MINLEN ? DHCP_MINLEN : o - buf;
+}
+
+// parse a server's reply to transaction xid into *lease.
+// returns its message type, or -1 if it isn't such a reply.
+static int
+dhcp_parse(uint8 *buf, int len, uint32 xid, struct dhcp_lease *lease)
+{
+  struct dhcp *d = (struct dhcp *) buf;
+  int type = -1;
+
+  if(len < sizeof(*d) || d->op != DHCP_BOOTREPLY || ntohl(d->xid) != xid ||
+     ntohl(d->magic) != DHCP_MAGIC)
     return -1;
+
+  memset(lease, 0, sizeof(*lease));
+  lease->ip = ntohl(d->yiaddr);
+
+  int off = sizeof(*d);
+  while(off < len){
+    int code = buf[off++];
+    if(code == DHCP_OPT_PAD)
+      continue;
+    if(code == DHCP_OPT_END)
+      break;
+    if(off >= len || buf[off] > len - off - 1)
+      return -1;
+    int olen = buf[off++];
+    uint8 *opt = buf + off;
+    off += olen;
+
+    if(code == DHCP_OPT_MSGTYPE && olen == 1)
+      type = opt[0];
+    else if(code == DHCP_OPT_NETMASK && olen == 4)
+      lease->mask = dhcp_get_addr(opt);
+    else if(code == DHCP_OPT_ROUTER && olen >= 4) // the first of a list
+      lease->gateway = dhcp_get_addr(opt);
+    else if(code == DHCP_OPT_DNS && olen >= 4) // likewise
+      lease->dns = dhcp_get_addr(opt);
+    else if(code == DHCP_OPT_SERVERID && olen == 4)
+      lease->server = dhcp_get_addr(opt);
   }
-  memset(buf, 0, PGSIZE);
 
-  struct eth *eth = (struct eth *) buf;
-  memmove(eth->dhost, host_mac, ETHADDR_LEN);
-  memmove(eth->shost, local_mac, ETHADDR_LEN);
-  eth->type = htons(ETHTYPE_IP);
+  return type;
+}
+
+// broadcast a message of the given type to the DHCP servers, and
+// wait up to timeout ticks for a reply of type want, filling in
+// *lease. replies to other transactions, and of other types, are
+// ignored, except that a DHCP_NAK gives up at once.
+// returns 0, or -NET_E*.
+static int
+dhcp_exchange(struct bind_ring_buffer *ring, int type, uint32 xid, int want,
+              int timeout, struct dhcp_lease *lease)
+{
+  uint8 msg[DHCP_MINLEN];
+  int rc;
+
+  int len = dhcp_build(msg, type, xid, lease);
+  if((rc = udp_send(DHCP_CLIENT_PORT, 0xffffffff, DHCP_SERVER_PORT, 0, (uint64)msg, len)) != 0)
+    return rc;
+
+  uint start = ticks;
+  for(;;){
+    struct packet packet;
+    int left = timeout - (int)(ticks - start);
+    acquire(&ring->lock);
+    if((rc = ring_wait(ring, DHCP_CLIENT_PORT, left < 0 ? 0 : left)) == 0)
+      ring_dequeue(ring, &packet);
+    release(&ring->lock);
+    if(rc != 0)
+      return rc;
+
+    struct udpview v;
+    struct dhcp_lease l;
+    int got = -1;
+    if(packet.sport == DHCP_SERVER_PORT && udp_parse(packet.buf, packet.len, &v) == 0)
+      got = dhcp_parse((uint8 *)v.payload, v.paylen, xid, &l);
+    kfree(packet.buf);
+
+    if(got == want){
+      *lease = l;
+      return 0;
+    }
+    if(got == DHCP_NAK)
+      return -NET_EPROTO;
+  }
+}
+
+//
+// dhcp()
+// lease xv6's IP address, netmask, and gateway by DHCP.
+// if no server answers after DHCP_TRIES attempts, each waiting
+// twice as long as the last, keep the static defaults. they
+// stay in place meanwhile, so the network works while this
+// waits; init runs it in a process of its own at boot, rather
+// than hold everything else up. needs network privilege.
+// returns 0, or -NET_E*.
+//
+uint64
+sys_dhcp(void)
+{
+  struct netconf c;
+  struct dhcp_lease lease;
+  struct bind_ring_buffer *ring;
+  int rc;
+
+  if(!myproc()->netpriv)
+    return -NET_EPERM;
+
+  // the server broadcasts its replies, which needn't be for
+  // the address we have now.
+  if((rc = bind_ring(DHCP_CLIENT_PORT, BIND_BROADCAST, &ring)) != 0){
+    warnf("dhcp: %s\n", netstrerror(rc));
+    return rc;
+  }
+
+  // a NAK, or an ACK without an address, starts over with a
+  // new DISCOVER, as does a server that stops answering.
+  uint32 xid = dhcp_get_addr(local_mac + 2) ^ ticks;
+  int timeout = DHCP_TIMEOUT;
+  enum dhcp_state state = DHCP_SELECTING;
+  for(int try = 0; try < DHCP_TRIES && state != DHCP_BOUND; ){
+    switch(state){
+    case DHCP_SELECTING:
+      rc = dhcp_exchange(ring, DHCP_DISCOVER, xid, DHCP_OFFER, timeout, &lease);
+      if(rc == 0)
+        state = DHCP_REQUESTING;
+      break;
+    case DHCP_REQUESTING:
+      rc = dhcp_exchange(ring, DHCP_REQUEST, xid, DHCP_ACK, timeout, &lease);
+      if(rc == 0 && lease.ip == 0)
+        rc = -NET_EPROTO; // acked without an address
+      state = rc == 0 ? DHCP_BOUND : DHCP_SELECTING;
+      break;
+    case DHCP_BOUND:
+      break;
+    }
+    if(rc != 0){
+      if(killed(myproc()))
+        break;
+      try++;
+      xid++;
+      timeout *= 2;
+    }
+  }
+  ring_release(ring, DHCP_CLIENT_PORT);
+
+  get_netconfig(&c);
+  if(rc != 0){
+    if(!killed(myproc())) // -1 isn't a NET_E* code
+      warnf("dhcp: no lease (%s), using %d.%d.%d.%d\n", netstrerror(rc),
+            c.ip >> 24, (c.ip >> 16) & 0xff, (c.ip >> 8) & 0xff, c.ip & 0xff);
+    return rc;
+  }
+
+  c.ip = lease.ip;
+  if(lease.mask)
+    c.mask = lease.mask;
+  if(lease.gateway)
+    c.gateway = lease.gateway;
+  if(lease.dns)
+    c.dns = lease.dns;
+  set_netconfig(&c);
+  infof("dhcp: leased %d.%d.%d.%d\n",
+        c.ip >> 24, (c.ip >> 16) & 0xff, (c.ip >> 8) & 0xff, c.ip & 0xff);
+  return 0;
+}
+
+// why ip_rx() dropped packets.
+static struct {
+  uint64 badsum;    // bad IP header checksum
+  uint64 udpbadsum; // bad UDP checksum
+  uint64 truncated; // shorter than their headers claim
+  uint64 reasmfail; // fragments of datagrams that couldn't be reassembled
+  uint64 notours;   // addressed to some other host
+} ipstats;
+
+//
+// IP reassembly: fragments are collected into a page per
+// datagram, keyed on (source, destination, protocol, id),
+// until every byte up to the last fragment is there.
+// a reassembled datagram has to fit in one page, like any
+// other received packet. protected by netlock.
+//
+#define REASM_SLOTS   4   // datagrams being reassembled at once
+#define REASM_TIMEOUT 30  // ticks to wait for a datagram's missing fragments
+#define REASM_MAX     (PGSIZE - sizeof(struct eth) - sizeof(struct ip)) // payload bytes
+
+static struct reasm {
+  char *buf;     // eth and IP headers, then the payload so far; 0 if the slot is free
+  uint32 src, dst;
+  uint16 id;
+  uint8 proto;
+  int have;      // payload bytes received
+  int total;     // payload length, or -1 until the last fragment arrives
+  uint start;    // ticks when the first fragment arrived
+  uint8 map[(REASM_MAX / 8 + 7) / 8]; // which 8-byte blocks have arrived
+} reasms[REASM_SLOTS];
+
+// free a reassembly slot and whatever it collected.
+// caller holds netlock.
+static void
+reasm_free(struct reasm *r)
+{
+  kfree(r->buf);
+  r->buf = 0;
+}
 
+// add the fragment in *bufp to its datagram's reassembly.
+// frees the fragment, and sets *bufp to 0, or if this fragment
+// completes the datagram, to a new buffer holding all of it
+// (with *lenp set to its length).
+// returns 0, or -NET_E* if the fragment had to be dropped.
+static int
+ip_reassemble(char **bufp, int *lenp)
+{
+  char *buf = *bufp;
+  struct eth *eth = (struct eth *) buf;
   struct ip *ip = (struct ip *)(eth + 1);
-  ip->ip_vhl = 0x45; // version 4, header length 4*5
-  ip->ip_tos = 0;
-  ip->ip_len = htons(sizeof(struct ip) + sizeof(struct udp) + len);
-  ip->ip_id = 0;
-  ip->ip_off = 0;
+  int ihl = (ip->ip_vhl & 0x0F) * 4;
+  int off = (ntohs(ip->ip_off) & IP_OFFMASK) * 8;
+  int mf = (ntohs(ip->ip_off) & IP_MF) != 0;
+  int n = ntohs(ip->ip_len) - ihl;
+  struct reasm *r = 0;
+  int rc = 0;
+
+  *bufp = 0;
+
+  // every fragment but the last carries a multiple of 8 bytes.
+  if (n <= 0 || *lenp < sizeof(*eth) + ihl + n || (mf && n % 8 != 0)) {
+    rc = -NET_ETRUNC;
+    goto drop;
+  }
+  if (off + n > REASM_MAX) {
+    rc = -NET_ENOMEM;
+    goto drop;
+  }
+
+  acquire(&netlock);
+  for (int i = 0; i < REASM_SLOTS; i++) {
+    struct reasm *s = &reasms[i];
+    if (s->buf && ticks - s->start >= REASM_TIMEOUT) {
+      // a fragment went missing.
+      reasm_free(s);
+      __sync_fetch_and_add(&ipstats.reasmfail, 1);
+    }
+    if (s->buf && s->src == ip->ip_src && s->dst == ip->ip_dst &&
+        s->proto == ip->ip_p && s->id == ip->ip_id)
+      r = s;
+  }
+  if (r == 0) {
+    for (int i = 0; i < REASM_SLOTS && r == 0; i++)
+      if (reasms[i].buf == 0)
+        r = &reasms[i];
+    if (r == 0 || (r->buf = kzalloc()) == 0) {
+      release(&netlock);
+      rc = -NET_ENOMEM;
+      goto drop;
+    }
+    r->src = ip->ip_src;
+    r->dst = ip->ip_dst;
+    r->proto = ip->ip_p;
+    r->id = ip->ip_id;
+    r->have = 0;
+    r->total = -1;
+    r->start = ticks;
+    memset(r->map, 0, sizeof(r->map));
+  }
+
+  // overlapping fragments aren't worth sorting out; give up on the datagram.
+  // so is a fragment beyond the end, or a second end.
+  int first = off / 8, last = (off + n + 7) / 8;
+  int bad = (!mf && r->total >= 0) || (r->total >= 0 && off + n > r->total);
+  for (int b = first; b < (mf ? last : REASM_MAX / 8 + 1) && !bad; b++)
+    bad = (r->map[b / 8] >> (b % 8)) & 1;
+  if (bad) {
+    reasm_free(r);
+    release(&netlock);
+    rc = -NET_EPROTO;
+    goto drop;
+  }
+
+  for (int b = first; b < last; b++)
+    r->map[b / 8] |= 1 << (b % 8);
+  memmove(r->buf + sizeof(*eth) + sizeof(*ip) + off, (char *)ip + ihl, n);
+  r->have += n;
+  if (!mf)
+    r->total = off + n;
+  if (off == 0) {
+    // options are dropped; the reassembled header is a plain one.
+    memmove(r->buf, eth, sizeof(*eth) + sizeof(*ip));
+  }
+
+  if (r->total >= 0 && r->have == r->total) {
+    struct ip *rip = (struct ip *)((struct eth *) r->buf + 1);
+    rip->ip_vhl = 0x45;
+    rip->ip_len = htons(sizeof(*ip) + r->total);
+    rip->ip_off = 0;
+    rip->ip_sum = 0;
+    rip->ip_sum = in_cksum(rip, sizeof(*rip));
+    *bufp = r->buf;
+    *lenp = sizeof(*eth) + sizeof(*ip) + r->total;
+    r->buf = 0;
+  }
+  release(&netlock);
+
+  kfree(buf);
+  return 0;
+
+drop:
+  __sync_fetch_and_add(&ipstats.reasmfail, 1);
+  kfree(buf);
+  return rc;
+}
+
+// consulted by udp_rx() for each UDP packet before it's queued;
+// returns NET_ACCEPT or NET_DROP. 0 if there's no filter.
+static int (*rx_filter)(struct ip *, struct udp *);
+
+// install f as the receive filter, replacing any previous one.
+// f(0) removes the filter.
+void
+net_set_rx_filter(int (*f)(struct ip *, struct udp *))
+{
+  acquire(&netlock);
+  rx_filter = f;
+  release(&netlock);
+}
+
+// answer an ICMP echo request (a ping) in buf, by turning it into
+// the reply in place and transmitting it. other ICMP messages
+// are dropped. takes ownership of `buf`.
+// returns 0, or -NET_E* describing why the packet was dropped.
+static int
+icmp_rx(char *buf, int len, struct rxinfo *ri)
+{
+  struct eth *eth = (struct eth *) buf;
+  struct ip *ip = (struct ip *)(buf + ri->nh);
+  struct icmp *icmp = (struct icmp *)(buf + ri->th);
+  int ihl = ri->th - ri->nh;
+  int iplen = ntohs(ip->ip_len);
+  struct route rt;
+  int rc;
+
+  if (iplen < ihl + sizeof(*icmp) || len < sizeof(*eth) + iplen) {
+    rc = -NET_ETRUNC;
+    goto drop;
+  }
+  if (in_cksum((unsigned char *)icmp, iplen - ihl) != 0) {
+    rc = -NET_EBADSUM;
+    goto drop;
+  }
+  if (icmp->type != ICMP_ECHO || icmp->code != 0 || ntohl(ip->ip_dst) != local_ip()) {
+    rc = -NET_EPROTO;
+    goto drop;
+  }
+  if ((rc = route(ntohl(ip->ip_src), &rt)) != 0)
+    goto drop;
+  // a reassembled request can be too big to answer in one frame.
+  if (iplen > rt.mtu) {
+    rc = -NET_EPROTO;
+    goto drop;
+  }
+
+  eth_build(buf, rt.mac, ETHTYPE_IP);
+
+  ip->ip_dst = ip->ip_src;
+  ip->ip_src = htonl(local_ip());
   ip->ip_ttl = 100;
-  ip->ip_p = IPPROTO_UDP;
-  ip->ip_src = htonl(local_ip);
-  ip->ip_dst = htonl(dst);
-  ip->ip_sum = in_cksum((unsigned char *)ip, sizeof(*ip));
+  ip->ip_sum = 0;
+  ip->ip_sum = in_cksum((unsigned char *)ip, ihl);
 
-  struct udp *udp = (struct udp *)(ip + 1);
-  udp->sport = htons(sport);
-  udp->dport = htons(dport);
-  udp->ulen = htons(len + sizeof(struct udp));
+  // the id, sequence number, and data are echoed back unchanged,
+  // so only the type's word of the checksum needs updating.
+  uint16 old = *(uint16 *)icmp;
+  icmp->type = ICMP_ECHOREPLY;
+  icmp->sum = cksum_update(icmp->sum, old, *(uint16 *)icmp);
 
-  char *payload = (char *)(udp + 1);
-  if(copyin(p->pagetable, payload, bufaddr, len) < 0){
-    kfree(buf);
-    printf("send: copyin failed\n");
-    return -1;
+  return route_output(&rt, buf, sizeof(*eth) + iplen);
+
+drop:
+  kfree(buf);
+  return rc;
+}
+
+#define ICMP_RATE 10 // unreachables to send per ICMP_RATE_TICKS, at most
+#define ICMP_RATE_TICKS 10 // about a second
+
+// send the sender of the IP packet at ip an ICMP destination
+// unreachable message with the given code, quoting its header
+// and the first 8 bytes of its payload (RFC 792). sends at most
+// ICMP_RATE of them per ICMP_RATE_TICKS, so that a flood of
+// undeliverable packets doesn't become a flood of replies.
+static void
+icmp_unreach(struct ip *ip, int code)
+{
+  static uint window;
+  static int sent;
+  uint32 src = ntohl(ip->ip_src);
+  struct route rt;
+
+  // never about broadcasts or multicasts, nor to
+  // a source that doesn't name one host.
+  uint32 dst = ntohl(ip->ip_dst);
+  if (is_broadcast(dst) || is_multicast(dst) ||
+      src == 0 || is_broadcast(src) || is_multicast(src))
+    return;
+
+  acquire(&netlock);
+  if (ticks - window >= ICMP_RATE_TICKS) {
+    window = ticks;
+    sent = 0;
   }
+  int ok = sent < ICMP_RATE;
+  if (ok)
+    sent++;
+  uint16 id = next_ip_id++;
+  release(&netlock);
+  if (!ok || route(src, &rt) != 0)
+    return;
+
+  int ihl = (ip->ip_vhl & 0x0F) * 4;
+  int quote = ntohs(ip->ip_len);
+  if (quote > ihl + 8)
+    quote = ihl + 8;
+
+  char *buf = kzalloc();
+  if (buf == 0)
+    return;
+  int len = sizeof(struct icmp) + quote;
+  struct icmp *icmp = ip_build(eth_build(buf, rt.mac, ETHTYPE_IP), src, IPPROTO_ICMP,
+                               len, id, 0, 0);
+  icmp->type = ICMP_UNREACH;
+  icmp->code = code;
+  memmove(icmp + 1, ip, quote);
+  icmp->sum = in_cksum(icmp, len);
 
-  e1000_transmit(buf, total);
+  route_output(&rt, buf, sizeof(struct eth) + sizeof(struct ip) + len);
+}
 
+// queue packet, carrying paylen bytes of UDP payload, on ring,
+// and mark the ring for net_rx_flush() to wake up its receivers.
+// takes ownership of packet.buf, freeing it if the ring is full.
+// returns 0, or -NET_EQFULL.
+static int
+ring_deliver(struct bind_ring_buffer *ring, struct packet packet, int paylen)
+{
+  acquire(&ring->lock);
+  if (ring_enqueue(ring, packet) != 0) {
+    ring->dropbytes += paylen;
+    release(&ring->lock);
+    debugf("ip_rx: dropping packet, queue is full\n");
+    kfree(packet.buf);
+    return -NET_EQFULL;
+  }
+  ring->bytes += paylen;
+  ring->dirty = 1;
+  release(&ring->lock);
   return 0;
 }
 
+// whether ring's filter lets the len-byte frame in buf through.
+static int
+ring_accepts(struct bind_ring_buffer *ring, char *buf, int len)
+{
+  acquire(&ring->lock);
+  int ok = pf_run(&ring->filter, buf, len);
+  release(&ring->lock);
+  return ok;
+}
+
+// how closely ring is connected to a peer: 2 for an address,
+// plus 1 for a port. read without the ring's lock, like the
+// peer itself in ip_rx(); connect() only changes whole fields.
+static int
+ring_specificity(struct bind_ring_buffer *ring)
+{
+  return (ring->peer_ip ? 2 : 0) + (ring->peer_port ? 1 : 0);
+}
+
+// wake up the receivers of every ring that's had packets queued
+// since it was last flushed, once each, however many arrived.
+// whoever hands packets to ip_rx() or net_rx() must call this
+// when done with the batch, or receivers may sleep on a packet.
+void
+net_rx_flush(void)
+{
+  int woke = 0;
+
+  for (int i = 0; i < RINGS_NUM; i++) {
+    struct bind_ring_buffer *ring = &rings[i];
+    // dirty is only set with the ring's lock held, and a ring our
+    // caller queued on is seen dirty here, since it was the same
+    // thread; another flusher clearing it first has woken it.
+    if (atomic_read4(&ring->dirty) == 0)
+      continue;
+    acquire(&ring->lock);
+    if (ring->dirty) {
+      ring->dirty = 0;
+      ring->wakeups++;
+      wakeup(ring);
+      woke = 1;
+    }
+    release(&ring->lock);
+  }
+  if (woke)
+    netpoll_wakeup();
+}
+
+//
+// the receive path's dispatch tables: net_rx() hands a frame to
+// the handler registered for its ethertype, and ip_rx() hands a
+// packet to the handler registered for its IP protocol. each
+// handler is told where the headers are, in a struct rxinfo, and
+// takes ownership of the buffer. handlers are registered during
+// netinit(), before any frame arrives, so the tables need no lock.
+//
+#define NETHTYPES 4
+
+static struct ethtype {
+  int type;       // ETHTYPE_*, host byte order; 0 if the slot is free
+  int minlen;     // shorter frames are dropped before rx sees them
+  int (*rx)(char *, int, struct rxinfo *);
+} ethtypes[NETHTYPES];
+
+static int (*ipprotos[256])(char *, int, struct rxinfo *);
+
+// hand frames of ethertype type, at least minlen bytes long,
+// to rx.
+void
+eth_register(int type, int minlen, int (*rx)(char *, int, struct rxinfo *))
+{
+  for(struct ethtype *e = ethtypes; e < ethtypes + NETHTYPES; e++){
+    if(e->type == type)
+      panic("eth_register: already registered");
+    if(e->type == 0){
+      e->type = type;
+      e->minlen = minlen;
+      e->rx = rx;
+      return;
+    }
+  }
+  panic("eth_register: no slots");
+}
+
+// hand IP packets of protocol proto, IPPROTO_*, to rx.
 void
+ip_register(int proto, int (*rx)(char *, int, struct rxinfo *))
+{
+  if(proto < 0 || proto > 255 || ipprotos[proto])
+    panic("ip_register");
+  ipprotos[proto] = rx;
+}
+
+// check an IP packet addressed to xv6, put it back together if
+// it's a fragment, and hand it to the handler registered for its
+// protocol; see ip_register().
+// takes ownership of `buf`, freeing it if the packet is dropped.
+// returns 0, or -NET_E* describing why the packet was dropped.
+int
 ip_rx(char *buf, int len)
 {
   // don't delete this printf; make grade depends on it.
@@ -319,18 +3291,148 @@ ip_rx(char *buf, int len)
   if(seen_ip == 0)
     printf("ip_rx: received an IP packet\n");
   seen_ip = 1;
+  if(klogging(LOG_DEBUG)){
+    debugf("ip_rx: %d bytes\n", len);
+    hexdump(buf, len);
+  }
 
   struct eth *eth = (struct eth *) buf;
   struct ip *ip = (struct ip *)(eth + 1);
-  struct udp *udp = (struct udp *)(ip + 1);
+  int ihl = (ip->ip_vhl & 0x0F) * 4; // bytes
+
+  if (ihl < sizeof(*ip) || len < sizeof(*eth) + ihl) {
+    __sync_fetch_and_add(&ipstats.truncated, 1);
+    kfree(buf);
+    return -NET_ETRUNC;
+  }
+
+  // the checksum of a header including its checksum field is 0.
+  if (in_cksum((unsigned char *)ip, ihl) != 0) {
+    __sync_fetch_and_add(&ipstats.badsum, 1);
+    kfree(buf);
+    return -NET_EBADSUM;
+  }
+
+  uint32 dst = ntohl(ip->ip_dst);
+  int bcast = is_broadcast(dst);
+  int group = bcast || is_multicast(dst);
+  if (!group && dst != local_ip() && (dst >> 24) != 127) {
+    __sync_fetch_and_add(&ipstats.notours, 1);
+    kfree(buf);
+    return -NET_ENOROUTE;
+  }
+
+  if (ntohs(ip->ip_off) & (IP_MF | IP_OFFMASK)) {
+    int rc = ip_reassemble(&buf, &len);
+    if (buf == 0)
+      return rc; // dropped, or waiting for the rest
+    eth = (struct eth *) buf;
+    ip = (struct ip *)(eth + 1);
+    ihl = sizeof(*ip);
+  }
+
+  int iplen = ntohs(ip->ip_len);
+  if (iplen >= ihl && iplen <= len - sizeof(*eth))
+    raw_deliver(ip, iplen);
+
+  struct rxinfo ri = {
+    .nh = sizeof(*eth),
+    .th = sizeof(*eth) + ihl,
+    .bcast = bcast,
+    .group = group,
+  };
+  int (*rx)(char *, int, struct rxinfo *) = ipprotos[ip->ip_p];
+  if (rx == 0) {
+    kfree(buf);
+    return -NET_EPROTO;
+  }
+  return rx(buf, len, &ri);
+}
+
+// the ETHTYPE_IP handler: a frame from net_rx().
+static int
+ip_input(char *buf, int len, struct rxinfo *ri)
+{
+  return ip_rx(buf, len);
+}
+
+// deliver a UDP datagram to the rings bound to its port.
+// takes ownership of `buf`, freeing it if the datagram is dropped.
+// returns 0, or -NET_E* describing why it was dropped.
+static int
+udp_rx(char *buf, int len, struct rxinfo *ri)
+{
+  struct ip *ip = (struct ip *)(buf + ri->nh);
+  uint32 dst = ntohl(ip->ip_dst);
+  int bcast = ri->bcast;
+  int group = ri->group;
+
+  struct udpview v;
+  if (udp_parse(buf, len, &v) != 0) {
+    debugf("ip_rx: %s\n", netstrerror(NET_ETRUNC));
+    __sync_fetch_and_add(&ipstats.truncated, 1);
+    kfree(buf);
+    return -NET_ETRUNC;
+  }
+  struct udp *udp = v.udp;
+
+  // a UDP checksum of 0 means the sender didn't compute one.
+  // a bad one is counted against every binding of the port.
+  if (udp->sum != 0 && atomic_read4(&udp_cksum) &&
+      cksum_fold(cksum_partial(ip_pseudo_sum(ip->ip_src, ip->ip_dst, IPPROTO_UDP, ntohs(udp->ulen)),
+                               udp, ntohs(udp->ulen))) != 0) {
+    __sync_fetch_and_add(&ipstats.udpbadsum, 1);
+    count_badsum(ntohs(udp->dport));
+    kfree(buf);
+    return -NET_EBADSUM;
+  }
   
-  struct bind_ring_buffer *ring = find_ring(ntohs(udp->dport));
+  int (*filter)(struct ip *, struct udp *) = rx_filter;
+  if (filter && filter(ip, udp) == NET_DROP) {
+    kfree(buf);
+    return -NET_EFILTERED;
+  }
+
+  // bind_ring() lets no more than MAXREUSE rings share a port.
+  struct bind_ring_buffer *bound[MAXREUSE];
+  int nbound = find_rings(ntohs(udp->dport), bound, MAXREUSE);
   
-  if (ring == 0) {
-    printf("ip_rx: recieved a packet but no process is bound to port %d\n", ntohs(udp->dport));
+  if (nbound == 0) {
+    debugf("ip_rx: received a packet but no process is bound to port %d\n", ntohs(udp->dport));
+    icmp_unreach(ip, ICMP_UNREACH_PORT);
     kfree(buf);
-    return;
+    return -NET_ENOTBOUND;
+  }
+
+  // skip rings connected to some other peer, and, for a
+  // multicast, rings that haven't joined its group, and for a
+  // broadcast, rings that didn't ask for them, and rings whose
+  // filters turn the packet down.
+  // a unicast goes only to the rings connected most specifically
+  // to its sender, as BSD does: a ring connected to the sender
+  // takes it from an unconnected one sharing the port, which
+  // still gets everything else.
+  int nmatch = 0, best = 0;
+  for (int i = 0; i < nbound; i++) {
+    struct bind_ring_buffer *r = bound[i];
+    if ((r->peer_ip == 0 || r->peer_ip == ntohl(ip->ip_src)) &&
+        (r->peer_port == 0 || r->peer_port == ntohs(udp->sport)) &&
+        (!is_multicast(dst) || r->group == dst) &&
+        (!bcast || r->broadcast) && ring_accepts(r, buf, len)) {
+      int spec = group ? 0 : ring_specificity(r);
+      if (spec > best) {
+        best = spec;
+        nmatch = 0;
+      }
+      if (spec == best)
+        bound[nmatch++] = r;
+    }
+  }
+  if (nmatch == 0) {
+    kfree(buf);
+    return -NET_EFILTERED;
   }
+  nbound = nmatch;
 
   struct packet packet = {
     .len = len,
@@ -338,76 +3440,269 @@ ip_rx(char *buf, int len)
     .buf = buf
   };
 
-  if (ring_enqueue(ring, packet) != 0) {
-    printf("ip_rx: dropping packet, queue is full.");
-    kfree(buf);
+  // a broadcast or multicast is for everyone listening, so each
+  // ring on a shared port gets it; a unicast is for just one of
+  // them, so they take turns, round-robin. receivers only read
+  // queued packets, so the rings can share the one page, each
+  // holding a reference; see kdup().
+  if (group) {
+    int rc = 0;
+    for (int i = 0; i < nbound; i++) {
+      if (i < nbound - 1)
+        kdup(buf);
+      int r = ring_deliver(bound[i], packet, v.paylen);
+      if (r != 0)
+        rc = r;
+    }
+    return rc;
   }
 
-  acquire(&ring->lock);
-  wakeup(ring);
-  release(&ring->lock);
+  struct bind_ring_buffer *ring = bound[0];
+  if (nbound > 1)
+    ring = bound[__sync_fetch_and_add(&bound[0]->rr, 1) % nbound];
+  return ring_deliver(ring, packet, v.paylen);
 }
 
 //
-// send an ARP reply packet to tell qemu to map
-// xv6's ip address to its ethernet address.
-// this is the bare minimum needed to persuade
-// qemu to send IP packets to xv6; the real ARP
-// protocol is more complex.
+// ARP cache: the ethernet addresses of peers, learned from their
+// ARP packets. entries expire after ARP_TTL ticks, so that a
+// reused IP address doesn't resolve to a stale MAC forever.
+// protected by arplock.
 //
+#define ARP_CACHE_SIZE 16
+#define ARP_TTL 600 // ticks, about a minute
+
+struct arp_entry {
+  uint32 ip;               // host byte order; 0 if the entry is free
+  uint8 mac[ETHADDR_LEN];
+  uint ticks;              // when the entry was last refreshed
+};
+
+static struct arp_entry arp_cache[ARP_CACHE_SIZE];
+
+// addresses arp_resolve() has asked about and not yet heard
+// back on, so that a burst of sends to one doesn't become a
+// burst of requests: at most one per ARP_HOLDOFF ticks each.
+// also protected by arplock.
+#define ARP_PENDING 8
+#define ARP_HOLDOFF 10 // ticks, about a second, as RFC 1122 asks
+
+static struct arp_entry arp_pending[ARP_PENDING]; // mac unused
+
+// remember that ip is at mac, replacing the oldest
+// entry if the cache is full.
 void
-arp_rx(char *inbuf)
+arp_insert(uint32 ip, uint8 *mac)
 {
-  static int seen_arp = 0;
+  struct arp_entry *e = 0;
 
-  if(seen_arp){
-    kfree(inbuf);
-    return;
+  acquirewrite(&arplock);
+  for(int i = 0; i < ARP_CACHE_SIZE; i++){
+    if(arp_cache[i].ip == ip){
+      e = &arp_cache[i];
+      break;
+    }
+    // otherwise prefer a free entry, then the oldest.
+    if(e == 0 || (e->ip != 0 && (arp_cache[i].ip == 0 || arp_cache[i].ticks < e->ticks)))
+      e = &arp_cache[i];
   }
-  printf("arp_rx: received an ARP packet\n");
-  seen_arp = 1;
+  e->ip = ip;
+  memmove(e->mac, mac, ETHADDR_LEN);
+  e->ticks = ticks;
+  for(int i = 0; i < ARP_PENDING; i++)
+    if(arp_pending[i].ip == ip)
+      arp_pending[i].ip = 0; // answered
+  releasewrite(&arplock);
+}
 
-  struct eth *ineth = (struct eth *) inbuf;
-  struct arp *inarp = (struct arp *) (ineth + 1);
+// copy the MAC address for ip into mac.
+// returns 0, or -1 if ip isn't in the cache.
+int
+arp_lookup(uint32 ip, uint8 *mac)
+{
+  int rc = -1;
 
-  char *buf = kalloc();
-  if(buf == 0)
-    panic("send_arp_reply");
-  
-  struct eth *eth = (struct eth *) buf;
-  memmove(eth->dhost, ineth->shost, ETHADDR_LEN); // ethernet destination = query source
-  memmove(eth->shost, local_mac, ETHADDR_LEN); // ethernet source = xv6's ethernet address
-  eth->type = htons(ETHTYPE_ARP);
+  acquireread(&arplock);
+  for(int i = 0; i < ARP_CACHE_SIZE; i++){
+    if(ip != 0 && arp_cache[i].ip == ip){
+      memmove(mac, arp_cache[i].mac, ETHADDR_LEN);
+      rc = 0;
+      break;
+    }
+  }
+  releaseread(&arplock);
+  return rc;
+}
+
+// invalidate ARP cache entries older than ARP_TTL.
+// called from the e1000 interrupt handler.
+void
+arp_sweep(void)
+{
+  acquirewrite(&arplock);
+  for(int i = 0; i < ARP_CACHE_SIZE; i++){
+    if(arp_cache[i].ip != 0 && ticks - arp_cache[i].ticks >= ARP_TTL)
+      arp_cache[i].ip = 0;
+  }
+  releasewrite(&arplock);
+}
 
-  struct arp *arp = (struct arp *)(eth + 1);
+// send an ARP packet with operation op to the ethernet address
+// dmac, asking about (or answering) the IP address tip.
+static void
+arp_send(int op, uint8 *dmac, uint8 *tha, uint32 tip)
+{
+  char *buf = kzalloc();
+  if(buf == 0){
+    printf("arp_send: kalloc failed\n");
+    return;
+  }
+  
+  struct arp *arp = eth_build(buf, dmac, ETHTYPE_ARP);
   arp->hrd = htons(ARP_HRD_ETHER);
   arp->pro = htons(ETHTYPE_IP);
   arp->hln = ETHADDR_LEN;
   arp->pln = sizeof(uint32);
-  arp->op = htons(ARP_OP_REPLY);
+  arp->op = htons(op);
 
   memmove(arp->sha, local_mac, ETHADDR_LEN);
-  arp->sip = htonl(local_ip);
-  memmove(arp->tha, ineth->shost, ETHADDR_LEN);
-  arp->tip = inarp->sip;
+  arp->sip = htonl(local_ip());
+  memmove(arp->tha, tha, ETHADDR_LEN);
+  arp->tip = htonl(tip);
+
+  if(e1000_transmit(buf, sizeof(struct eth) + sizeof(*arp)) != 0)
+    kfree(buf);
+}
+
+// whether to broadcast a request for ip now: not if one went
+// out less than ARP_HOLDOFF ticks ago. if so, records that it's
+// going out, in place of the oldest pending request if need be.
+static int
+arp_should_ask(uint32 ip)
+{
+  struct arp_entry *e = 0;
+  int ask = 1;
+
+  acquirewrite(&arplock);
+  for(int i = 0; i < ARP_PENDING; i++){
+    if(arp_pending[i].ip == ip){
+      e = &arp_pending[i];
+      break;
+    }
+    if(e == 0 || (e->ip != 0 && (arp_pending[i].ip == 0 || arp_pending[i].ticks < e->ticks)))
+      e = &arp_pending[i];
+  }
+  if(e->ip == ip && ticks - e->ticks < ARP_HOLDOFF)
+    ask = 0;
+  else {
+    e->ip = ip;
+    e->ticks = ticks;
+  }
+  releasewrite(&arplock);
+  return ask;
+}
+
+// find the ethernet address for ip, copying it into mac.
+// returns 0, or -1 if it isn't known yet, in which case
+// an ARP request has been broadcast, unless one was just
+// now, and a later call should find the answer in the cache.
+int
+arp_resolve(uint32 ip, uint8 *mac)
+{
+  static uint8 broadcast[ETHADDR_LEN] = { 0xff, 0xff, 0xff, 0xff, 0xff, 0xff };
+  static uint8 unknown[ETHADDR_LEN];
+
+  if(arp_lookup(ip, mac) == 0)
+    return 0;
+  if(arp_should_ask(ip))
+    arp_send(ARP_OP_REQUEST, broadcast, unknown, ip);
+  return -1;
+}
+
+// check arp_should_ask()'s holdoff on addresses no one has:
+// a second request for one waits, one for another doesn't,
+// and once ARP_HOLDOFF has passed, the first can go again.
+// returns 0, or -1 on failure.
+static int
+arp_selftest(void)
+{
+  uint32 a = MAKE_IP_ADDR(10, 0, 2, 201), b = MAKE_IP_ADDR(10, 0, 2, 202);
+
+  if(!arp_should_ask(a) || arp_should_ask(a) || !arp_should_ask(b))
+    return -1;
+
+  acquirewrite(&arplock);
+  for(int i = 0; i < ARP_PENDING; i++)
+    if(arp_pending[i].ip == a)
+      arp_pending[i].ticks -= ARP_HOLDOFF;
+  releasewrite(&arplock);
+  if(!arp_should_ask(a))
+    return -1;
+
+  acquirewrite(&arplock);
+  for(int i = 0; i < ARP_PENDING; i++)
+    if(arp_pending[i].ip == a || arp_pending[i].ip == b)
+      arp_pending[i].ip = 0;
+  releasewrite(&arplock);
+  return 0;
+}
+
+//
+// handle an incoming ARP packet: remember the sender's
+// addresses, and answer requests for xv6's IP address.
+// a gratuitous ARP (one announcing the sender's own address,
+// sip == tip) only updates the cache.
+//
+static int
+arp_rx(char *inbuf, int len, struct rxinfo *ri)
+{
+  // don't delete this printf; make grade depends on it.
+  static int seen_arp = 0;
+  if(seen_arp == 0)
+    printf("arp_rx: received an ARP packet\n");
+  seen_arp = 1;
+
+  struct eth *ineth = (struct eth *) inbuf;
+  struct arp *inarp = (struct arp *) (inbuf + ri->nh);
+
+  if(ntohs(inarp->hrd) != ARP_HRD_ETHER || ntohs(inarp->pro) != ETHTYPE_IP){
+    kfree(inbuf);
+    return -NET_EPROTO;
+  }
 
-  e1000_transmit(buf, sizeof(*eth) + sizeof(*arp));
+  if(inarp->sip != 0)
+    arp_insert(ntohl(inarp->sip), (uint8 *) inarp->sha);
+
+  if(ntohs(inarp->op) == ARP_OP_REQUEST && ntohl(inarp->tip) == local_ip() &&
+     inarp->sip != inarp->tip){
+    // ethernet destination = query source
+    arp_send(ARP_OP_REPLY, ineth->shost, (uint8 *) inarp->sha, ntohl(inarp->sip));
+  }
 
   kfree(inbuf);
+  return 0;
 }
 
+// hand a frame from the e1000 to the handler registered for
+// its ethertype; see eth_register(). takes ownership of buf.
 void
 net_rx(char *buf, int len)
 {
   struct eth *eth = (struct eth *) buf;
 
-  if(len >= sizeof(struct eth) + sizeof(struct arp) &&
-     ntohs(eth->type) == ETHTYPE_ARP){
-    arp_rx(buf);
-  } else if(len >= sizeof(struct eth) + sizeof(struct ip) &&
-     ntohs(eth->type) == ETHTYPE_IP){
-    ip_rx(buf, len);
-  } else {
-    kfree(buf);
+  pcap_tap(buf, len, PCAP_IN);
+
+  if(len >= sizeof(struct eth)){
+    int type = ntohs(eth->type);
+    for(struct ethtype *e = ethtypes; e < ethtypes + NETHTYPES && e->type != 0; e++){
+      if(e->type == type){
+        if(len < e->minlen)
+          break;
+        struct rxinfo ri = { .nh = sizeof(struct eth) };
+        e->rx(buf, len, &ri);
+        return;
+      }
+    }
   }
+  kfree(buf);
 }
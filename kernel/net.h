@@ -24,6 +24,162 @@ static inline uint32 bswapl(uint32 val)
 #define htonl bswapl
 
 
+//
+// network error codes
+//
+// kernel networking functions return 0 on success or the
+// negation of one of these; the syscalls pass them straight
+// through to user space, so callers can tell failures apart.
+//
+
+enum {
+  NET_ETRUNC = 1, // packet is shorter than its headers claim
+  NET_EBADSUM,    // header checksum mismatch
+  NET_EQFULL,     // receive queue is full, packet dropped
+  NET_ENOROUTE,   // no destination for the packet
+  NET_ERINGBUSY,  // tx descriptor is still owned by the e1000
+  NET_ENOMEM,     // out of kernel memory
+  NET_EINVAL,     // bad argument
+  NET_ENOTBOUND,  // port is not bound
+  NET_EPROTO,     // unsupported protocol
+  NET_ETIMEDOUT,  // no reply in time
+  NET_EFILTERED,  // dropped by the receive filter
+  NET_EADDRINUSE, // port is already bound
+  NET_EAGAIN,     // nothing to receive, and asked not to wait
+  NET_ECONNREFUSED, // no one listening on the TCP port
+  NET_ECONNRESET, // the TCP peer reset the connection
+  NET_EPERM,      // the process lacks network privilege; see dropnetpriv()
+  NET_NERR,
+};
+
+
+// bindflags() flags.
+#define BIND_REUSEPORT 0x1 // let other BIND_REUSEPORT binds share the port
+#define BIND_BROADCAST 0x2 // also receive broadcasts sent to the port
+#define MAXREUSE       8   // bindings that can share one port
+
+// e1000 counters, for nicstats(). they only ever go up.
+struct nicstats {
+  uint64 tx_packets;         // frames handed to the e1000
+  uint64 tx_busy;            // transmits refused, descriptor still in flight
+  uint64 tx_reclaimed;       // sent buffers freed
+  uint64 rx_packets;         // frames passed up to net_rx()
+  uint64 rx_alloc_failures;  // times the rx ring, or a frame, went without a page
+  uint64 rx_dropped;         // frames too big for a page, or whose chain had no end
+};
+
+// a bound port's queue, for portstats().
+struct portstats {
+  uint64 received;  // packets queued for the port
+  uint64 dropped;   // packets dropped because the queue was full
+  int depth;        // packets queued now, not yet received
+  uint64 wakeups;   // times receivers were woken, once per burst of packets
+  uint64 badsum;    // datagrams for the port dropped for a bad UDP checksum
+  uint64 bytes;     // UDP payload bytes in the packets received
+  uint64 dropbytes; // and in those dropped because the queue was full
+};
+
+// one line of netstat(): a bound UDP port or a TCP connection.
+struct netstat {
+  int proto;        // IPPROTO_UDP or IPPROTO_TCP
+  uint16 lport;     // local port
+  uint16 rport;     // remote port, or 0 for any
+  uint32 raddr;     // remote address, host byte order, or 0 for any
+  int state;        // TCP: TCP_LISTEN, ...; UDP: 0
+  int pid;          // UDP: the process that bound the port
+  int recvq;        // packets (UDP) or bytes (TCP) waiting to be read
+  int sendq;        // TCP: bytes written but not yet acknowledged
+  uint64 dropped;   // UDP: packets dropped because the queue was full
+};
+
+// recvmmsg() flags.
+#define RECV_PEEK     0x1 // leave the packets queued
+#define RECV_DONTWAIT 0x2 // fail with -NET_EAGAIN rather than wait
+
+// one datagram for recvmmsg().
+struct netmsg {
+  char *buf;    // in: where to copy the UDP payload
+  int maxlen;   // in: size of buf
+  int len;      // out: number of payload bytes copied
+  uint32 src;   // out: IP source address, host byte order
+  uint16 sport; // out: UDP source port, host byte order
+};
+
+#define AF_INET 2 // sockaddr_in family: IPv4
+
+// a UDP endpoint, for sendto() and recvfrom(). family leaves
+// room for addresses of other protocols.
+struct sockaddr_in {
+  uint16 family; // AF_INET
+  uint16 port;   // host byte order
+  uint32 addr;   // IP address, host byte order
+};
+
+// the interface's configuration, for netconf().
+// addresses are host byte order.
+struct netconf {
+  uint32 ip;
+  uint32 mask;    // netmask
+  uint32 gateway; // forwards packets off our network
+  uint32 dns;     // name server for resolve()
+  int mtu;        // largest IP packet to send in one frame
+  uint8 mac[6];   // ethernet address; can't be changed
+};
+
+// pcapture() commands.
+#define PCAP_START 1 // start capturing, discarding anything captured before
+#define PCAP_STOP  2 // stop capturing
+#define PCAP_READ  3 // wait for captured frames and copy them out
+
+// which way a captured frame went.
+#define PCAP_IN   0 // received from the e1000
+#define PCAP_OUT  1 // handed to the e1000 to send
+#define PCAP_LOOP 2 // sent by xv6 to itself; never reached the e1000
+
+#define PCAP_SNAPLEN 128 // bytes of each frame captured
+
+// a captured frame, for pcapture().
+struct pcaprec {
+  uint64 usec;   // when, in microseconds since boot
+  int dir;       // PCAP_IN, PCAP_OUT or PCAP_LOOP
+  int len;       // length of the frame
+  int caplen;    // bytes of it in data: len, up to PCAP_SNAPLEN
+  int drops;     // frames lost just before this one, the ring being full
+  char data[PCAP_SNAPLEN]; // the frame, from its ethernet header
+};
+
+// a packet filter instruction, for setfilter(). a program runs
+// from its first instruction; each compares a field of the
+// packet, in host byte order and masked, with k, and skips jt
+// instructions ahead if they're equal, or jf if not, until a
+// PF_RET. a field the packet doesn't have, such as the ports
+// of an ARP packet, is never equal.
+struct pf_insn {
+  uint8 op;    // PF_*
+  uint8 jt;
+  uint8 jf;
+  uint32 mask; // bits of the field to compare
+  uint32 k;    // what they must be; for PF_RET, non-zero to accept the packet
+};
+
+#define PF_RET     0 // stop, accepting or dropping the packet
+#define PF_ETHTYPE 1 // ethernet type
+#define PF_PROTO   2 // IP protocol
+#define PF_SRC     3 // IP source address
+#define PF_DST     4 // IP destination address
+#define PF_SPORT   5 // UDP or TCP source port
+#define PF_DPORT   6 // UDP or TCP destination port
+
+#define PF_MAXINSNS 16 // longest program
+#define PF_CAPTURE  -1 // setfilter() target: the capture, not a socket
+
+// receive filter verdicts; see net_set_rx_filter().
+enum {
+  NET_ACCEPT,
+  NET_DROP,
+};
+
+
 //
 // useful networking headers
 //
@@ -41,6 +197,9 @@ struct eth {
 #define ETHTYPE_ARP 0x0806 // Address resolution protocol
 
 // an IP packet header (comes after an Ethernet header).
+// the 14-byte ethernet header leaves it only 2-byte aligned in a
+// frame, so it's packed to make the compiler use accesses that
+// don't assume the 4-byte alignment of ip_src and ip_dst.
 struct ip {
   uint8  ip_vhl; // version << 4 | header length >> 2
   uint8  ip_tos; // type of service
@@ -51,7 +210,14 @@ struct ip {
   uint8  ip_p;   // protocol
   uint16 ip_sum; // checksum, covers just IP header
   uint32 ip_src, ip_dst;
-};
+} __attribute__((packed));
+
+#define IP_MF      0x2000 // ip_off: more fragments follow
+#define IP_OFFMASK 0x1fff // ip_off: fragment offset, in 8-byte units
+
+#define ETH_MTU       1500  // largest IP packet in one ethernet frame
+#define NET_MINMTU    1280  // smallest MTU netconf() allows
+#define IP_MAXPACKET  65535 // largest IP packet, after reassembly
 
 #define IPPROTO_ICMP 1  // Control message protocol
 #define IPPROTO_TCP  6  // Transmission control protocol
@@ -67,8 +233,59 @@ struct udp {
   uint16 dport; // destination port
   uint16 ulen;  // length, including udp header, not including IP header
   uint16 sum;   // checksum
+} __attribute__((packed));
+
+// a TCP segment header (comes after an IP header), RFC 793.
+// options, if any, come between it and the data.
+struct tcp {
+  uint16 sport; // source port
+  uint16 dport; // destination port
+  uint32 seq;   // sequence number of the first data byte, or of the SYN
+  uint32 ack;   // next sequence number expected, if TCP_ACK
+  uint8  off;   // header length in 32-bit words << 4
+  uint8  flags; // TCP_*
+  uint16 win;   // receive window, in bytes
+  uint16 sum;   // checksum, covers a pseudo-header, the header and data
+  uint16 urp;   // urgent pointer
+} __attribute__((packed));
+
+#define TCP_FIN 0x01 // no more data from the sender
+#define TCP_SYN 0x02 // synchronize sequence numbers
+#define TCP_RST 0x04 // reset the connection
+#define TCP_PSH 0x08 // push
+#define TCP_ACK 0x10 // the ack field is significant
+
+// TCP connection states, RFC 793 section 3.2.
+enum {
+  TCP_FREE,        // slot unused
+  TCP_CLOSED,      // over, but a file still refers to it
+  TCP_LISTEN,
+  TCP_SYN_SENT,
+  TCP_SYN_RCVD,
+  TCP_ESTABLISHED,
+  TCP_FIN_WAIT_1,
+  TCP_FIN_WAIT_2,
+  TCP_CLOSING,
+  TCP_TIME_WAIT,
+  TCP_CLOSE_WAIT,
+  TCP_LAST_ACK,
 };
 
+// an ICMP echo request or reply header (comes after an IP header).
+struct icmp {
+  uint8  type;
+  uint8  code;
+  uint16 sum;  // checksum, covers the header and data
+  uint16 id;   // echo identifier
+  uint16 seq;  // echo sequence number
+} __attribute__((packed));
+
+#define ICMP_ECHOREPLY 0
+#define ICMP_UNREACH   3 // destination unreachable; code says what
+#define ICMP_ECHO      8
+
+#define ICMP_UNREACH_PORT 3 // ICMP_UNREACH code: no one bound to the port
+
 // an ARP packet (comes after an Ethernet header).
 struct arp {
   uint16 hrd; // format of hardware address
@@ -125,3 +342,72 @@ struct dns_data {
   uint32 ttl;
   uint16 len;
 } __attribute__((packed));
+
+// a DHCP message (comes after an UDP header), RFC 2131.
+// the options follow, starting with DHCP_MAGIC.
+struct dhcp {
+  uint8  op;      // DHCP_BOOTREQUEST or DHCP_BOOTREPLY
+  uint8  htype;   // hardware address type, ARP_HRD_ETHER
+  uint8  hlen;    // hardware address length
+  uint8  hops;
+  uint32 xid;     // transaction ID, chosen by the client
+  uint16 secs;
+  uint16 flags;
+  uint32 ciaddr;  // client's address, if it already has one
+  uint32 yiaddr;  // "your" address, assigned by the server
+  uint32 siaddr;
+  uint32 giaddr;
+  uint8  chaddr[16]; // client hardware address
+  uint8  sname[64];
+  uint8  file[128];
+  uint32 magic;
+} __attribute__((packed));
+
+#define DHCP_BOOTREQUEST 1
+#define DHCP_BOOTREPLY   2
+#define DHCP_MAGIC       0x63825363
+#define DHCP_SERVER_PORT 67
+#define DHCP_CLIENT_PORT 68
+
+// options
+#define DHCP_OPT_PAD      0
+#define DHCP_OPT_NETMASK  1
+#define DHCP_OPT_ROUTER   3
+#define DHCP_OPT_DNS      6
+#define DHCP_OPT_REQIP    50 // requested IP address
+#define DHCP_OPT_MSGTYPE  53
+#define DHCP_OPT_SERVERID 54
+#define DHCP_OPT_PARAMS   55 // parameter request list
+#define DHCP_OPT_END      255
+
+// DHCP_OPT_MSGTYPE values
+#define DHCP_DISCOVER 1
+#define DHCP_OFFER    2
+#define DHCP_REQUEST  3
+#define DHCP_ACK      5
+#define DHCP_NAK      6
+
+// how to reach a destination; see route().
+struct route {
+  int loopback;            // hand the packet back to ip_rx()
+  uint32 hop;              // the host to hand it to: the destination, or the gateway
+  uint8 mac[ETHADDR_LEN];  // that host's ethernet address
+  int mtu;                 // largest IP packet to send in one frame
+};
+
+// where a received frame's headers are, for the handlers
+// registered with eth_register() and ip_register(): offsets
+// from the start of the frame.
+struct rxinfo {
+  int nh;                  // the network header, IP or ARP
+  int th;                  // the transport header; IP protocol handlers only
+  int bcast;               // sent to a broadcast address
+  int group;               // sent to a broadcast or multicast address
+};
+
+// a program attached by setfilter(); one of no instructions
+// accepts everything.
+struct pktfilter {
+  int n;
+  struct pf_insn prog[PF_MAXINSNS];
+};
@@ -13,3 +13,9 @@
 #define MAXPATH      128   // maximum file path name
 #define USERSTACK    1     // user stack pages
 
+// kernel log levels, most severe first; see klog().
+#define LOG_ERROR    0
+#define LOG_WARN     1
+#define LOG_INFO     2
+#define LOG_DEBUG    3
+
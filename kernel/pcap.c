@@ -0,0 +1,143 @@
+//
+// packet capture: while a process has asked for it with
+// pcapture(), every frame received from the e1000, handed to
+// it, or looped back inside xv6 is copied, up to PCAP_SNAPLEN
+// bytes, into a ring of records for user space to read.
+// when the reader falls behind, new frames are dropped and
+// counted in the next record that makes it in. a filter can
+// narrow down what's captured; see setfilter().
+//
+
+#include "types.h"
+#include "param.h"
+#include "memlayout.h"
+#include "riscv.h"
+#include "spinlock.h"
+#include "proc.h"
+#include "defs.h"
+#include "net.h"
+
+#define PCAP_SLOTS 32 // records the ring holds; a power of 2
+
+static struct {
+  struct spinlock lock;
+  int on;       // capturing
+  struct pcaprec ring[PCAP_SLOTS];
+  uint read;    // counts of records ever read and written;
+  uint write;   // PCAP_SLOTS divides 2^32, so they can wrap
+  int drops;    // frames dropped since the last record written
+  struct pktfilter filter; // frames to capture; see setfilter()
+} pcap;
+
+void
+pcapinit(void)
+{
+  initlock(&pcap.lock, "pcap");
+}
+
+// the tap: record a copy of the len-byte frame in buf, which
+// went in direction dir, PCAP_*, if capture is on.
+void
+pcap_tap(const char *buf, int len, int dir)
+{
+  if(!atomic_read4(&pcap.on))
+    return;
+
+  acquire(&pcap.lock);
+  if(pcap.on && pf_run(&pcap.filter, buf, len)){
+    if(pcap.write - pcap.read == PCAP_SLOTS){
+      pcap.drops++;
+    } else {
+      struct pcaprec *r = &pcap.ring[pcap.write++ % PCAP_SLOTS];
+      r->usec = r_time() / 10; // qemu's timer runs at 10 MHz
+      r->dir = dir;
+      r->len = len;
+      r->caplen = len < PCAP_SNAPLEN ? len : PCAP_SNAPLEN;
+      r->drops = pcap.drops;
+      memmove(r->data, buf, r->caplen);
+      pcap.drops = 0;
+      wakeup(&pcap);
+    }
+  }
+  release(&pcap.lock);
+}
+
+// capture only frames f accepts. returns 0, or -NET_EPERM.
+int
+pcap_setfilter(struct pktfilter *f)
+{
+  if(!myproc()->netpriv)
+    return -NET_EPERM;
+  acquire(&pcap.lock);
+  pcap.filter = *f;
+  release(&pcap.lock);
+  return 0;
+}
+
+//
+// pcapture(int cmd, struct pcaprec *recs, int n)
+// PCAP_START starts capturing, throwing away whatever was
+// captured before, and PCAP_STOP stops. PCAP_READ waits for
+// captured frames and copies up to n records of them to recs;
+// once capture has stopped, it returns what's left, then 0.
+// capture sees every packet, so it needs network privilege;
+// see dropnetpriv().
+// returns 0, or the number of records read, or -NET_E*, or -1.
+//
+uint64
+sys_pcapture(void)
+{
+  int cmd, n;
+  uint64 addr;
+  struct proc *p = myproc();
+
+  argint(0, &cmd);
+  argaddr(1, &addr);
+  argint(2, &n);
+
+  if(!p->netpriv)
+    return -NET_EPERM;
+
+  acquire(&pcap.lock);
+  switch(cmd){
+  case PCAP_START:
+    pcap.read = pcap.write = 0;
+    pcap.drops = 0;
+    pcap.on = 1;
+    release(&pcap.lock);
+    return 0;
+  case PCAP_STOP:
+    pcap.on = 0;
+    wakeup(&pcap);
+    release(&pcap.lock);
+    return 0;
+  case PCAP_READ:
+    break;
+  default:
+    release(&pcap.lock);
+    return -NET_EINVAL;
+  }
+
+  if(n <= 0){
+    release(&pcap.lock);
+    return -NET_EINVAL;
+  }
+  while(pcap.read == pcap.write && pcap.on){
+    if(killed(p)){
+      release(&pcap.lock);
+      return -1;
+    }
+    sleep(&pcap, &pcap.lock);
+  }
+  int i;
+  for(i = 0; i < n && pcap.read != pcap.write; i++){
+    struct pcaprec *r = &pcap.ring[pcap.read % PCAP_SLOTS];
+    if(copyout(p->pagetable, addr + i * sizeof(*r), (char *)r, sizeof(*r)) < 0){
+      release(&pcap.lock);
+      return i > 0 ? i : -NET_EINVAL;
+    }
+    pcap.read++;
+  }
+  release(&pcap.lock);
+  return i;
+}
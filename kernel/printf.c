@@ -25,6 +25,21 @@ static struct {
 
 static char digits[] = "0123456789abcdef";
 
+// a single printf() prints at most PRINTF_MAX characters,
+// so that printing untrusted data (e.g. a packet payload)
+// can't flood the console and stall the kernel.
+#define PRINTF_MAX 1024
+
+// characters emitted by the current printf(), protected by pr.lock.
+static int prcount;
+
+static void
+prputc(int c)
+{
+  if(prcount++ < PRINTF_MAX)
+    consputc(c);
+}
+
 static void
 printint(long long xx, int base, int sign)
 {
@@ -46,24 +61,34 @@ printint(long long xx, int base, int sign)
     buf[i++] = '-';
 
   while(--i >= 0)
-    consputc(buf[i]);
+    prputc(buf[i]);
 }
 
 static void
 printptr(uint64 x)
 {
   int i;
-  consputc('0');
-  consputc('x');
+  prputc('0');
+  prputc('x');
   for (i = 0; i < (sizeof(uint64) * 2); i++, x <<= 4)
-    consputc(digits[x >> (sizeof(uint64) * 8 - 4)]);
+    prputc(digits[x >> (sizeof(uint64) * 8 - 4)]);
 }
 
-// Print to the console.
-int
-printf(char *fmt, ...)
+// klog() messages below this severity are suppressed.
+static volatile int loglevel = LOG_INFO;
+
+static char *logtags[] = {
+[LOG_ERROR] "error: ",
+[LOG_WARN]  "warning: ",
+[LOG_INFO]  "info: ",
+[LOG_DEBUG] "debug: ",
+};
+
+// Print prefix (if any) and then fmt to the console,
+// as a single message.
+static void
+vprintf(char *prefix, char *fmt, va_list ap)
 {
-  va_list ap;
   int i, cx, c0, c1, c2, locking;
   char *s;
 
@@ -71,10 +96,12 @@ printf(char *fmt, ...)
   if(locking)
     acquire(&pr.lock);
 
-  va_start(ap, fmt);
+  prcount = 0;
+  for(s = prefix; s && *s; s++)
+    prputc(*s);
   for(i = 0; (cx = fmt[i] & 0xff) != 0; i++){
     if(cx != '%'){
-      consputc(cx);
+      prputc(cx);
       continue;
     }
     i++;
@@ -112,15 +139,15 @@ printf(char *fmt, ...)
       if((s = va_arg(ap, char*)) == 0)
         s = "(null)";
       for(; *s; s++)
-        consputc(*s);
+        prputc(*s);
     } else if(c0 == '%'){
-      consputc('%');
+      prputc('%');
     } else if(c0 == 0){
       break;
     } else {
       // Print unknown % sequence to draw attention.
-      consputc('%');
-      consputc(c0);
+      prputc('%');
+      prputc(c0);
     }
 
 #if 0
@@ -151,14 +178,106 @@ printf(char *fmt, ...)
     }
 #endif
   }
-  va_end(ap);
+
+  if(prcount > PRINTF_MAX)
+    for(s = "...[truncated]\n"; *s; s++)
+      consputc(*s);
 
   if(locking)
     release(&pr.lock);
+}
+
+// Print to the console.
+int
+printf(char *fmt, ...)
+{
+  va_list ap;
+
+  va_start(ap, fmt);
+  vprintf(0, fmt, ap);
+  va_end(ap);
 
   return 0;
 }
 
+// Print to the console, tagged with level, unless
+// level is less severe than the current log level.
+// Use through errorf(), warnf(), infof() and debugf().
+void
+klog(int level, char *fmt, ...)
+{
+  va_list ap;
+
+  if(level > loglevel)
+    return;
+
+  va_start(ap, fmt);
+  vprintf(logtags[level], fmt, ap);
+  va_end(ap);
+}
+
+// Whether messages at level are printed, for callers with
+// more to log than one klog() call, such as a hexdump().
+int
+klogging(int level)
+{
+  return level <= loglevel;
+}
+
+// Set the log level, returning the old one.
+int
+setloglevel(int level)
+{
+  int old = loglevel;
+
+  if(level < LOG_ERROR)
+    level = LOG_ERROR;
+  if(level > LOG_DEBUG)
+    level = LOG_DEBUG;
+  loglevel = level;
+  return old;
+}
+
+// Print len bytes at p as a hex dump, 16 bytes a line:
+// the offset, the bytes in hex, then the printable ones as text.
+//   00000000  45 00 00 1c 00 01 00 00  40 11 ...  |E.......@.|
+void
+hexdump(const void *p, int len)
+{
+  const uchar *b = p;
+  char line[80];
+
+  for(int off = 0; off < len; off += 16){
+    char *l = line;
+    for(int sh = 28; sh >= 0; sh -= 4)
+      *l++ = digits[(off >> sh) & 0xf];
+    *l++ = ' ';
+    for(int i = 0; i < 16; i++){
+      if(i == 8)
+        *l++ = ' ';
+      *l++ = ' ';
+      if(off + i < len){
+        *l++ = digits[b[off+i] >> 4];
+        *l++ = digits[b[off+i] & 0xf];
+      } else {
+        // pad a short last line so the text column lines up.
+        *l++ = ' ';
+        *l++ = ' ';
+      }
+    }
+    *l++ = ' ';
+    *l++ = ' ';
+    *l++ = '|';
+    for(int i = 0; i < 16 && off + i < len; i++){
+      uchar c = b[off+i];
+      *l++ = (c >= ' ' && c < 0x7f) ? c : '.';
+    }
+    *l++ = '|';
+    *l = 0;
+    printf("%s\n", line);
+  }
+}
+
 void
 panic(char *s)
 {
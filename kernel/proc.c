@@ -248,6 +248,9 @@ userinit(void)
 
   safestrcpy(p->name, "initcode", sizeof(p->name));
   p->cwd = namei("/");
+#ifdef LAB_NET
+  p->netpriv = 1; // init, and so everything, starts out privileged
+#endif
 
   p->state = RUNNABLE;
 
@@ -307,6 +310,9 @@ fork(void)
     if(p->ofile[i])
       np->ofile[i] = filedup(p->ofile[i]);
   np->cwd = idup(p->cwd);
+#ifdef LAB_NET
+  np->netpriv = p->netpriv;
+#endif
 
   safestrcpy(np->name, p->name, sizeof(p->name));
 
@@ -365,6 +371,11 @@ exit(int status)
   end_op();
   p->cwd = 0;
 
+#ifdef LAB_NET
+  // unbind the ports we left bound, freeing their packets.
+  net_exit(p->pid);
+#endif
+
   acquire(&wait_lock);
 
   // Give any children to init.
@@ -573,6 +584,83 @@ sleep(void *chan, struct spinlock *lk)
   acquire(lk);
 }
 
+// processes in sleeptimeout(), so timeoutwakeup() can skip the scan.
+static int ntimeouts;
+
+// Like sleep(), but give up after n ticks even if nobody
+// calls wakeup(chan). Reacquires lk before returning either way.
+// Returns 1 if it timed out, 0 if woken.
+int
+sleeptimeout(void *chan, struct spinlock *lk, uint n)
+{
+  struct proc *p = myproc();
+  int timedout;
+
+  acquire(&p->lock);
+  release(lk);
+
+  p->wakeat = ticks + n;
+  if(p->wakeat == 0)
+    p->wakeat = 1; // 0 means no deadline
+  p->chan = chan;
+  p->state = SLEEPING;
+  __sync_fetch_and_add(&ntimeouts, 1);
+
+  sched();
+
+  __sync_fetch_and_sub(&ntimeouts, 1);
+  // timeoutwakeup() clears wakeat; wakeup() doesn't.
+  timedout = (p->wakeat == 0);
+  p->chan = 0;
+  p->wakeat = 0;
+
+  release(&p->lock);
+  acquire(lk);
+
+  return timedout;
+}
+
+// Wake up processes in sleeptimeout() whose deadline has passed.
+// Called by the timer interrupt, with tickslock held.
+void
+timeoutwakeup(void)
+{
+  struct proc *p;
+
+  if(atomic_read4(&ntimeouts) == 0)
+    return;
+
+  for(p = proc; p < &proc[NPROC]; p++) {
+    if(p != myproc()){
+      acquire(&p->lock);
+      if(p->state == SLEEPING && p->wakeat != 0 && (int)(ticks - p->wakeat) >= 0) {
+        p->wakeat = 0;
+        p->state = RUNNABLE;
+      }
+      release(&p->lock);
+    }
+  }
+}
+
+// check that sleeptimeout() gives up on a channel nobody wakes,
+// and no sooner than asked. returns 0, or -1 on failure.
+int
+sleeptimeout_selftest(void)
+{
+  struct spinlock lk;
+  int chan;
+  int ok = 1;
+
+  initlock(&lk, "sleeptest");
+  acquire(&lk);
+  uint t0 = ticks;
+  if(sleeptimeout(&chan, &lk, 2) != 1 || ticks - t0 < 2)
+    ok = 0;
+  release(&lk);
+
+  return ok ? 0 : -1;
+}
+
 // Wake up all processes sleeping on chan.
 // Must be called without any p->lock.
 void
@@ -0,0 +1,96 @@
+// Reader-writer spin locks.
+//
+// Any number of readers may hold the lock at once; a writer
+// holds it alone. A writer takes lk, which keeps new readers
+// out, and then spins until the readers already inside leave.
+// xv6 has no condition variables, so readers don't wait for
+// a writer in any order: a steady stream of readers that never
+// lets the count reach zero starves the writer.
+//
+// Like spinlocks, readers and writers run with interrupts
+// off, so an interrupt handler that takes the lock can't
+// deadlock against the code it interrupted.
+
+#include "types.h"
+#include "param.h"
+#include "memlayout.h"
+#include "spinlock.h"
+#include "rwlock.h"
+#include "riscv.h"
+#include "proc.h"
+#include "defs.h"
+
+void
+initrwlock(struct rwlock *rw, char *name)
+{
+  initlock(&rw->lk, name);
+  rw->readers = 0;
+}
+
+void
+acquireread(struct rwlock *rw)
+{
+  push_off(); // stays off until releaseread().
+  acquire(&rw->lk);
+  __sync_fetch_and_add(&rw->readers, 1);
+  release(&rw->lk);
+}
+
+void
+releaseread(struct rwlock *rw)
+{
+  // the fetch-and-sub is a full fence, so the reads in
+  // the critical section happen before the writer can enter.
+  if(__sync_fetch_and_sub(&rw->readers, 1) <= 0)
+    panic("releaseread");
+  pop_off();
+}
+
+void
+acquirewrite(struct rwlock *rw)
+{
+  acquire(&rw->lk);
+  while(__atomic_load_n(&rw->readers, __ATOMIC_ACQUIRE) != 0)
+    ;
+}
+
+void
+releasewrite(struct rwlock *rw)
+{
+  release(&rw->lk);
+}
+
+// check the lock's state on this CPU: readers share it, run
+// with interrupts off, and leave lk free for more readers; a
+// writer holds lk, which keeps new readers out, and gets in
+// once the readers have left. returns 0, or -1 on failure.
+int
+rwlock_selftest(void)
+{
+  struct rwlock rw;
+  int ok = 1;
+
+  initrwlock(&rw, "rwtest");
+  acquireread(&rw);
+  acquireread(&rw);
+  if(rw.readers != 2 || holding(&rw.lk) || intr_get())
+    ok = 0;
+  releaseread(&rw);
+  if(rw.readers != 1 || intr_get())
+    ok = 0;
+  releaseread(&rw);
+
+  // would spin forever if a reader were left inside.
+  acquirewrite(&rw);
+  if(!holding(&rw.lk) || rw.readers != 0)
+    ok = 0;
+  releasewrite(&rw);
+
+  // and the writer left nothing behind to keep readers out.
+  acquireread(&rw);
+  if(rw.readers != 1 || holding(&rw.lk))
+    ok = 0;
+  releaseread(&rw);
+
+  return ok ? 0 : -1;
+}
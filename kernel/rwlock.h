@@ -0,0 +1,6 @@
+// Reader-writer spin locks, for data that's read far
+// more often than it's written.
+struct rwlock {
+  struct spinlock lk; // held by the writer; readers only hold it briefly
+  int readers;        // number of readers inside
+};
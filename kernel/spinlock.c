@@ -91,6 +91,30 @@ acquire(struct spinlock *lk)
   lk->cpu = mycpu();
 }
 
+// Try to acquire the lock without spinning.
+// Returns 1 with the lock held, exactly as after acquire(),
+// or 0 if some cpu (this one included) already holds it.
+// Either way interrupts are left as they were on entry.
+int
+tryacquire(struct spinlock *lk)
+{
+  push_off(); // disable interrupts to avoid deadlock.
+  if(holding(lk) || __sync_lock_test_and_set(&lk->locked, 1) != 0){
+    pop_off();
+    return 0;
+  }
+
+#ifdef LAB_LOCK
+    __sync_fetch_and_add(&(lk->n), 1);
+#endif      
+
+  // see acquire().
+  __sync_synchronize();
+
+  lk->cpu = mycpu();
+  return 1;
+}
+
 // Release the lock.
 void
 release(struct spinlock *lk)
@@ -107,6 +107,33 @@ extern uint64 sys_bind(void);
 extern uint64 sys_unbind(void);
 extern uint64 sys_send(void);
 extern uint64 sys_recv(void);
+extern uint64 sys_netselftest(void);
+extern uint64 sys_resolve(void);
+extern uint64 sys_recvmmsg(void);
+extern uint64 sys_bindflags(void);
+extern uint64 sys_nicstats(void);
+extern uint64 sys_linkstatus(void);
+extern uint64 sys_recvtimeout(void);
+extern uint64 sys_loglevel(void);
+extern uint64 sys_portstats(void);
+extern uint64 sys_connect(void);
+extern uint64 sys_joinmulticast(void);
+extern uint64 sys_udpcksum(void);
+extern uint64 sys_netpoll(void);
+extern uint64 sys_queuelen(void);
+extern uint64 sys_socket(void);
+extern uint64 sys_sendto(void);
+extern uint64 sys_recvfrom(void);
+extern uint64 sys_netconf(void);
+extern uint64 sys_tcplisten(void);
+extern uint64 sys_tcpaccept(void);
+extern uint64 sys_tcpconnect(void);
+extern uint64 sys_rawsocket(void);
+extern uint64 sys_dropnetpriv(void);
+extern uint64 sys_pcapture(void);
+extern uint64 sys_setfilter(void);
+extern uint64 sys_netstat(void);
+extern uint64 sys_dhcp(void);
 #endif
 #ifdef LAB_PGTBL
 extern uint64 sys_pgpte(void);
@@ -142,6 +169,33 @@ static uint64 (*syscalls[])(void) = {
 [SYS_unbind] sys_unbind,
 [SYS_send] sys_send,
 [SYS_recv] sys_recv,
+[SYS_netselftest] sys_netselftest,
+[SYS_resolve] sys_resolve,
+[SYS_recvmmsg] sys_recvmmsg,
+[SYS_bindflags] sys_bindflags,
+[SYS_nicstats] sys_nicstats,
+[SYS_linkstatus] sys_linkstatus,
+[SYS_recvtimeout] sys_recvtimeout,
+[SYS_loglevel] sys_loglevel,
+[SYS_portstats] sys_portstats,
+[SYS_connect] sys_connect,
+[SYS_joinmulticast] sys_joinmulticast,
+[SYS_udpcksum] sys_udpcksum,
+[SYS_netpoll] sys_netpoll,
+[SYS_queuelen] sys_queuelen,
+[SYS_socket] sys_socket,
+[SYS_sendto] sys_sendto,
+[SYS_recvfrom] sys_recvfrom,
+[SYS_netconf] sys_netconf,
+[SYS_tcplisten] sys_tcplisten,
+[SYS_tcpaccept] sys_tcpaccept,
+[SYS_tcpconnect] sys_tcpconnect,
+[SYS_rawsocket] sys_rawsocket,
+[SYS_dropnetpriv] sys_dropnetpriv,
+[SYS_pcapture] sys_pcapture,
+[SYS_setfilter] sys_setfilter,
+[SYS_netstat] sys_netstat,
+[SYS_dhcp] sys_dhcp,
 #endif
 #ifdef LAB_PGTBL
 [SYS_pgpte] sys_pgpte,
@@ -35,3 +35,30 @@
 #define SYS_recv      32
 #define SYS_pgpte     33
 #define SYS_kpgtbl    34
+#define SYS_netselftest 35
+#define SYS_resolve   36
+#define SYS_recvmmsg  37
+#define SYS_bindflags 38
+#define SYS_nicstats  39
+#define SYS_linkstatus 40
+#define SYS_recvtimeout 41
+#define SYS_loglevel  42
+#define SYS_portstats 43
+#define SYS_connect   44
+#define SYS_joinmulticast 45
+#define SYS_udpcksum  46
+#define SYS_netpoll   47
+#define SYS_queuelen  48
+#define SYS_socket    49
+#define SYS_sendto    50
+#define SYS_recvfrom  51
+#define SYS_netconf   52
+#define SYS_tcplisten 53
+#define SYS_tcpaccept 54
+#define SYS_tcpconnect 55
+#define SYS_rawsocket 56
+#define SYS_dropnetpriv 57
+#define SYS_pcapture  58
+#define SYS_setfilter 59
+#define SYS_netstat 60
+#define SYS_dhcp 61
@@ -474,6 +474,151 @@ sys_exec(void)
   return -1;
 }
 
+#ifdef LAB_NET
+//
+// socket(int port)
+// bind a UDP port, 0 for an ephemeral one, and return a file
+// descriptor for it: read() receives a datagram's payload, and
+// write() sends one to the peer set by connect(port, ...).
+// closing the last descriptor, or exiting, unbinds the port.
+// returns the descriptor, or -NET_E*, or -1 if the
+// process has no descriptors left.
+//
+uint64
+sys_socket(void)
+{
+  struct file *f;
+  int port, fd, rc;
+
+  argint(0, &port);
+  if((rc = sockalloc(&f, port)) != 0)
+    return rc;
+  if((fd = fdalloc(f)) < 0){
+    fileclose(f);
+    return -1;
+  }
+  return fd;
+}
+
+//
+// tcplisten(int port)
+// listen for TCP connections to port, returning a file
+// descriptor to pass to tcpaccept().
+// returns the descriptor, or -NET_E*, or -1 if the
+// process has no descriptors left.
+//
+uint64
+sys_tcplisten(void)
+{
+  struct file *f;
+  int port, fd, rc;
+
+  argint(0, &port);
+  if((rc = tcplisten(&f, port)) != 0)
+    return rc;
+  if((fd = fdalloc(f)) < 0){
+    fileclose(f);
+    return -1;
+  }
+  return fd;
+}
+
+//
+// tcpaccept(int fd)
+// wait for a connection to the listener fd, and return a
+// file descriptor for it: read() and write() move bytes
+// over the connection, and close() ends it.
+// returns the descriptor, or -NET_E*, or -1.
+//
+uint64
+sys_tcpaccept(void)
+{
+  struct file *lf, *f;
+  int fd, rc;
+
+  if(argfd(0, 0, &lf) < 0)
+    return -1;
+  if((rc = tcpaccept(lf, &f)) != 0)
+    return rc;
+  if((fd = fdalloc(f)) < 0){
+    fileclose(f);
+    return -1;
+  }
+  return fd;
+}
+
+//
+// tcpconnect(uint32 ip, int port)
+// connect to port at ip, host byte order, waiting until the
+// connection is established; returns a file descriptor for
+// it, as tcpaccept() does, or -NET_E*, or -1.
+//
+uint64
+sys_tcpconnect(void)
+{
+  struct file *f;
+  int ip, port, fd, rc;
+
+  argint(0, &ip);
+  argint(1, &port);
+  if((rc = tcpconnect(&f, (uint32)ip, port)) != 0)
+    return rc;
+  if((fd = fdalloc(f)) < 0){
+    fileclose(f);
+    return -1;
+  }
+  return fd;
+}
+
+//
+// setfilter(int fd, struct pf_insn *prog, int n)
+// attach the n-instruction filter program prog to the UDP or
+// raw socket fd, or, if fd is -1 (PF_CAPTURE), to the capture;
+// only packets it accepts are queued. n == 0 removes it.
+// returns 0, or -NET_E*, or -1 if fd isn't open.
+//
+uint64
+sys_setfilter(void)
+{
+  struct file *f = 0;
+  uint64 prog;
+  int fd, n;
+
+  argint(0, &fd);
+  argaddr(1, &prog);
+  argint(2, &n);
+  if(fd != -1 && argfd(0, 0, &f) < 0)
+    return -1;
+  return setfilter(f, prog, n);
+}
+
+//
+// rawsocket(int proto, uint32 dst)
+// open a raw socket for IP protocol proto: read() returns each
+// IP packet of that protocol that arrives, header and all, and
+// write() sends its bytes as the payload of an IP packet to dst,
+// host byte order, or fails if dst is 0. needs network
+// privilege; see dropnetpriv().
+// returns the descriptor, or -NET_E*, or -1.
+//
+uint64
+sys_rawsocket(void)
+{
+  struct file *f;
+  int proto, dst, fd, rc;
+
+  argint(0, &proto);
+  argint(1, &dst);
+  if((rc = rawalloc(&f, proto, (uint32)dst)) != 0)
+    return rc;
+  if((fd = fdalloc(f)) < 0){
+    fileclose(f);
+    return -1;
+  }
+  return fd;
+}
+#endif
+
 uint64
 sys_pipe(void)
 {
@@ -0,0 +1,1102 @@
+//
+// TCP (RFC 793), just enough for stream sockets: the
+// connection state machine, in-order delivery, flow control
+// by the receive window, and retransmission driven by the
+// clock tick. segments that arrive out of order are dropped
+// and left to the peer to send again. there are no options,
+// no urgent data, and no congestion control.
+//
+// each connection keeps the segments it has sent until they
+// are acknowledged, and retransmits the oldest when its timer
+// goes off. the timeout comes from round trip times measured
+// on the acks (Jacobson, RFC 6298), and timers live on a
+// wheel that clockintr() turns one slot per tick.
+//
+// every connection is a struct tcpcb, all protected by
+// tcplock. tcplisten(), tcpconnect() and tcpaccept() give
+// them file descriptors; read() and write() on those move
+// bytes through each tcpcb's receive and send buffers.
+//
+
+#include "types.h"
+#include "param.h"
+#include "memlayout.h"
+#include "riscv.h"
+#include "spinlock.h"
+#include "proc.h"
+#include "defs.h"
+#include "fs.h"
+#include "sleeplock.h"
+#include "file.h"
+#include "net.h"
+
+#define NTCP        16      // connections at once, listeners included
+#define TCP_BUFSZ   PGSIZE  // bytes in each of a connection's send and receive buffers
+#define TCP_MSS     (NET_MINMTU - sizeof(struct ip) - sizeof(struct tcp)) // largest segment sent
+#define TCP_RTQ     8       // segments a connection can have unacknowledged
+#define TCP_INITRTO 10      // ticks to wait for an ack before any round trip is timed
+#define TCP_MINRTO  2       // bounds on the retransmission timeout, in ticks
+#define TCP_MAXRTO  600
+#define TCP_RETRIES 8       // retransmissions, doubling the timeout each time, before giving up
+#define TCP_WHEEL   64      // slots in the timer wheel
+#define TCP_MSL     10      // ticks a segment can live in the network
+#define TCP_BACKLOG 4       // connections waiting for tcpaccept(), per listener
+#define TCP_OUTQ    32      // segments waiting for tcp_flush()
+
+// sequence numbers wrap, so compare them by their difference.
+#define SEQ_LT(a, b)  ((int)((a) - (b)) < 0)
+#define SEQ_LEQ(a, b) ((int)((a) - (b)) <= 0)
+
+// a segment sent but not yet acknowledged. its data, if any,
+// is still in sbuf, at seq - snd_una.
+struct tcpseg {
+  uint32 seq;
+  int len;     // sequence numbers it takes: its data, and one for a SYN or FIN
+  int flags;   // TCP_SYN or TCP_FIN, if it carries one
+  uint sent;   // ticks when it was first sent
+  int rexmit;  // it has been sent again, so its ack can't time the round trip
+};
+
+struct tcpcb {
+  int state;            // TCP_FREE, ..., in net.h
+  int owned;            // a file refers to it, or it waits in its listener's backlog
+  int err;              // why the connection ended, -NET_E*, or 0 if it closed cleanly
+  uint16 lport;         // local port
+  uint16 rport;         // remote port
+  uint32 rip;           // remote IP address, host byte order
+  struct tcpcb *parent; // the listener it arrived on, until tcpaccept() takes it
+
+  uint32 iss;           // initial send sequence number
+  uint32 snd_una;       // oldest sequence number not yet acknowledged
+  uint32 snd_nxt;       // next sequence number to send
+  uint32 snd_wnd;       // the peer's receive window
+  char *sbuf;           // bytes written but not yet acknowledged, from snd_una
+  int slen;
+  int closing;          // close() was called: send a FIN after sbuf
+  int finsent;          // and it has been sent, at snd_una + slen
+  struct tcpseg rtq[TCP_RTQ]; // the retransmission queue, oldest first
+  int rtqhead;
+  int rtqlen;
+
+  uint32 rcv_nxt;       // next sequence number expected from the peer
+  char *rbuf;           // bytes received but not yet read, a ring
+  int rhead;
+  int rlen;
+
+  int srtt;             // smoothed round trip time, ticks << 3; 0 until one is timed
+  int rttvar;           // its mean deviation, ticks << 2
+  int rto;              // retransmission timeout, ticks
+  int retries;          // retransmissions since the last new ack
+
+  uint deadline;        // ticks at which the timer goes off; 0 if it isn't set
+  struct tcpcb *tnext;  // next in the timer wheel slot
+};
+
+static struct spinlock tcplock;
+static struct tcpcb tcbs[NTCP];
+static int nactive;      // tcbs not TCP_FREE, so tcp_timer() can skip the scan
+static uint32 iss_seed;  // varies initial sequence numbers
+
+// the timer wheel: slot i lists the connections whose timers go
+// off at a tick t with t % TCP_WHEEL == i, so each tick looks at
+// one short list rather than every connection. a timer further
+// off than TCP_WHEEL ticks waits in its slot for a turn or more.
+static struct tcpcb *wheel[TCP_WHEEL];
+
+// segments built while holding tcplock, for tcp_flush() to
+// send once it's released: sending to a local address goes
+// straight back into tcp_rx(), which takes tcplock.
+static struct {
+  char *buf[TCP_OUTQ];
+  int len[TCP_OUTQ];
+  uint32 dst[TCP_OUTQ];
+  int head;
+  int n;
+  int flushing;          // a tcp_flush() is sending them
+} outq;
+
+static int tcp_rx(char *, int, struct rxinfo *);
+
+void
+tcpinit(void)
+{
+  initlock(&tcplock, "tcp");
+  ip_register(IPPROTO_TCP, tcp_rx);
+}
+
+// a free tcpcb, with its buffers, or 0.
+// caller holds tcplock.
+static struct tcpcb *
+tcp_alloc(void)
+{
+  for(struct tcpcb *tp = tcbs; tp < tcbs + NTCP; tp++){
+    if(tp->state != TCP_FREE)
+      continue;
+    char *sbuf = kalloc();
+    char *rbuf = kalloc();
+    if(sbuf == 0 || rbuf == 0){
+      if(sbuf)
+        kfree(sbuf);
+      if(rbuf)
+        kfree(rbuf);
+      return 0;
+    }
+    memset(tp, 0, sizeof(*tp));
+    tp->state = TCP_CLOSED;
+    tp->sbuf = sbuf;
+    tp->rbuf = rbuf;
+    tp->iss = ticks * 64000 + (iss_seed += 7919);
+    tp->rto = TCP_INITRTO;
+    nactive++;
+    return tp;
+  }
+  return 0;
+}
+
+// caller holds tcplock.
+static void
+tcp_canceltimer(struct tcpcb *tp)
+{
+  if(tp->deadline == 0)
+    return;
+  struct tcpcb **pp = &wheel[tp->deadline % TCP_WHEEL];
+  while(*pp != tp)
+    pp = &(*pp)->tnext;
+  *pp = tp->tnext;
+  tp->tnext = 0;
+  tp->deadline = 0;
+}
+
+// set tp's timer to go off in n ticks.
+// caller holds tcplock.
+static void
+tcp_settimer(struct tcpcb *tp, int n)
+{
+  tcp_canceltimer(tp);
+  tp->deadline = ticks + (n > 0 ? n : 1);
+  if(tp->deadline == 0)
+    tp->deadline = 1; // 0 means unset
+  struct tcpcb **slot = &wheel[tp->deadline % TCP_WHEEL];
+  tp->tnext = *slot;
+  *slot = tp;
+}
+
+// caller holds tcplock.
+static void
+tcp_free(struct tcpcb *tp)
+{
+  tcp_canceltimer(tp);
+  kfree(tp->sbuf);
+  kfree(tp->rbuf);
+  tp->sbuf = tp->rbuf = 0;
+  tp->state = TCP_FREE;
+  nactive--;
+}
+
+// an unused local port for tcpconnect(), or 0.
+// caller holds tcplock.
+static int
+tcp_port(void)
+{
+  static int next = 49152;
+
+  for(int i = 0; i < 65536 - 49152; i++){
+    int port = next;
+    next = next == 65535 ? 49152 : next + 1;
+    struct tcpcb *tp;
+    for(tp = tcbs; tp < tcbs + NTCP; tp++)
+      if(tp->state != TCP_FREE && tp->lport == port)
+        break;
+    if(tp == tcbs + NTCP)
+      return port;
+  }
+  return 0;
+}
+
+// build a segment from xv6's sport to dst's dport, carrying
+// the len bytes at data, and queue it for tcp_flush().
+// a segment that doesn't fit in the queue is dropped; the
+// peer, or retransmission, will recover.
+// caller holds tcplock.
+static void
+tcp_queue(uint32 dst, int sport, int dport, uint32 seq, uint32 ack,
+          int flags, int win, char *data, int len)
+{
+  if(outq.n == TCP_OUTQ)
+    return;
+  char *buf = kalloc();
+  if(buf == 0)
+    return;
+
+  int tlen = sizeof(struct tcp) + len;
+  struct tcp *th = ip_build(buf + sizeof(struct eth), dst, IPPROTO_TCP, tlen,
+                            ip_next_id(), 0, 0);
+  th->sport = htons(sport);
+  th->dport = htons(dport);
+  th->seq = htonl(seq);
+  th->ack = htonl(ack);
+  th->off = (sizeof(*th) / 4) << 4;
+  th->flags = flags;
+  th->win = htons(win > 65535 ? 65535 : win);
+  th->urp = 0;
+  memmove(th + 1, data, len);
+  th->sum = 0;
+  th->sum = cksum_fold(cksum_partial(ip_pseudo_sum(htonl(local_ip()), htonl(dst), IPPROTO_TCP, tlen),
+                                     th, tlen));
+
+  int i = (outq.head + outq.n++) % TCP_OUTQ;
+  outq.buf[i] = buf;
+  outq.len[i] = sizeof(struct eth) + sizeof(struct ip) + tlen;
+  outq.dst[i] = dst;
+}
+
+// send the segments tcp_queue() built. a segment that loops
+// back can queue replies while this runs; the loop sends those
+// too, rather than letting a nested call recurse on the stack.
+// called without tcplock.
+static void
+tcp_flush(void)
+{
+  acquire(&tcplock);
+  if(outq.flushing){
+    release(&tcplock);
+    return;
+  }
+  outq.flushing = 1;
+  while(outq.n > 0){
+    char *buf = outq.buf[outq.head];
+    int len = outq.len[outq.head];
+    uint32 dst = outq.dst[outq.head];
+    outq.head = (outq.head + 1) % TCP_OUTQ;
+    outq.n--;
+    release(&tcplock);
+
+    struct route rt;
+    if(route(dst, &rt) != 0){
+      kfree(buf);
+    } else {
+      eth_build(buf, rt.mac, ETHTYPE_IP);
+      route_output(&rt, buf, len);
+    }
+
+    acquire(&tcplock);
+  }
+  outq.flushing = 0;
+  release(&tcplock);
+}
+
+// the receive window to advertise: the room left in rbuf.
+static int
+tcp_window(struct tcpcb *tp)
+{
+  return TCP_BUFSZ - tp->rlen;
+}
+
+// queue a segment on tp's connection.
+// caller holds tcplock.
+static void
+tcp_send(struct tcpcb *tp, int flags, uint32 seq, char *data, int len)
+{
+  tcp_queue(tp->rip, tp->lport, tp->rport, seq, tp->rcv_nxt,
+            flags, tcp_window(tp), data, len);
+}
+
+// answer a segment that belongs to no connection with a reset
+// (RFC 793 section 3.4), unless it's a reset itself.
+// caller holds tcplock.
+static void
+tcp_reset(uint32 src, struct tcp *th, int dlen)
+{
+  if(th->flags & TCP_RST)
+    return;
+  if(th->flags & TCP_ACK){
+    tcp_queue(src, ntohs(th->dport), ntohs(th->sport), ntohl(th->ack), 0,
+              TCP_RST, 0, 0, 0);
+  } else {
+    uint32 ack = ntohl(th->seq) + dlen +
+      ((th->flags & TCP_SYN) != 0) + ((th->flags & TCP_FIN) != 0);
+    tcp_queue(src, ntohs(th->dport), ntohs(th->sport), 0, ack,
+              TCP_RST | TCP_ACK, 0, 0, 0);
+  }
+}
+
+// remember a segment just sent until it's acknowledged, and
+// start the timer if it isn't running.
+// caller holds tcplock.
+static void
+tcp_track(struct tcpcb *tp, uint32 seq, int len, int flags)
+{
+  struct tcpseg *sg = &tp->rtq[(tp->rtqhead + tp->rtqlen++) % TCP_RTQ];
+  sg->seq = seq;
+  sg->len = len;
+  sg->flags = flags & (TCP_SYN | TCP_FIN);
+  sg->sent = ticks;
+  sg->rexmit = 0;
+  if(tp->deadline == 0)
+    tcp_settimer(tp, tp->rto);
+}
+
+// send the oldest unacknowledged segment again.
+// caller holds tcplock.
+static void
+tcp_rexmit(struct tcpcb *tp)
+{
+  struct tcpseg *sg = &tp->rtq[tp->rtqhead];
+
+  sg->rexmit = 1;
+  if(sg->flags & TCP_SYN){
+    tcp_send(tp, tp->state == TCP_SYN_SENT ? TCP_SYN : TCP_SYN | TCP_ACK, sg->seq, 0, 0);
+    return;
+  }
+  int n = sg->len - ((sg->flags & TCP_FIN) != 0);
+  tcp_send(tp, sg->flags | TCP_ACK | (n > 0 ? TCP_PSH : 0), sg->seq,
+           tp->sbuf + (sg->seq - tp->snd_una), n);
+}
+
+// fold a round trip of m ticks into the estimate, and set
+// the timeout from it, RFC 6298 section 2. srtt and rttvar
+// are scaled, as in BSD, to keep the fractions.
+static void
+tcp_rtt(struct tcpcb *tp, int m)
+{
+  // a round trip quicker than the clock counts as one tick.
+  if(m < 1)
+    m = 1;
+  if(tp->srtt == 0){
+    tp->srtt = m << 3;
+    tp->rttvar = m << 1;
+  } else {
+    int delta = m - (tp->srtt >> 3);
+    tp->srtt += delta;                          // srtt += delta/8
+    if(delta < 0)
+      delta = -delta;
+    tp->rttvar += delta - (tp->rttvar >> 2);    // rttvar += (|delta| - rttvar)/4
+  }
+  // srtt + 4*rttvar, but at least a tick more than srtt.
+  int rto = (tp->srtt >> 3) + (tp->rttvar > 1 ? tp->rttvar : 1);
+  if(rto < TCP_MINRTO)
+    rto = TCP_MINRTO;
+  if(rto > TCP_MAXRTO)
+    rto = TCP_MAXRTO;
+  tp->rto = rto;
+}
+
+// the peer has everything before ack: drop it from sbuf and
+// the retransmission queue, and restart the timer for what's
+// left. Karn's rule: the round trip is timed only if no
+// segment the ack covers was retransmitted, since the ack
+// can't say which copy it's for. an ack for a retransmission
+// means the ones after it were probably lost too, so the next
+// is sent again straight away, not after another timeout.
+// caller holds tcplock.
+static void
+tcp_acked(struct tcpcb *tp, uint32 ack)
+{
+  int sample = -1, rexmit = 0;
+
+  while(tp->rtqlen > 0){
+    struct tcpseg *sg = &tp->rtq[tp->rtqhead];
+    if(SEQ_LT(ack, sg->seq + sg->len)){
+      // partly acknowledged: keep the rest.
+      if(SEQ_LT(sg->seq, ack)){
+        sg->len -= ack - sg->seq;
+        sg->seq = ack;
+      }
+      break;
+    }
+    if(sg->rexmit)
+      rexmit = 1;
+    else
+      sample = ticks - sg->sent;
+    tp->rtqhead = (tp->rtqhead + 1) % TCP_RTQ;
+    tp->rtqlen--;
+  }
+
+  int acked = ack - tp->snd_una;
+  int n = acked < tp->slen ? acked : tp->slen;
+  memmove(tp->sbuf, tp->sbuf + n, tp->slen - n);
+  tp->slen -= n;
+  tp->snd_una = ack;
+  tp->retries = 0;
+  if(sample >= 0 && !rexmit)
+    tcp_rtt(tp, sample);
+
+  if(tp->rtqlen == 0){
+    tcp_canceltimer(tp);
+  } else {
+    tcp_settimer(tp, tp->rto);
+    if(rexmit)
+      tcp_rexmit(tp);
+  }
+  wakeup(tp);
+}
+
+// send as much of sbuf as hasn't been sent and the peer's
+// window allows, then the FIN if close() asked for one.
+// caller holds tcplock.
+static void
+tcp_output(struct tcpcb *tp)
+{
+  if(tp->state != TCP_ESTABLISHED && tp->state != TCP_CLOSE_WAIT &&
+     tp->state != TCP_FIN_WAIT_1 && tp->state != TCP_CLOSING &&
+     tp->state != TCP_LAST_ACK)
+    return;
+  if(tp->finsent)
+    return;
+
+  int off = tp->snd_nxt - tp->snd_una;
+  // with nothing in flight, probe a closed window with one byte,
+  // so that the ack announcing it open can't be lost for good.
+  int wnd = tp->snd_wnd;
+  if(wnd == 0 && off == 0)
+    wnd = 1;
+  while(off < tp->slen && off < wnd && tp->rtqlen < TCP_RTQ){
+    int n = tp->slen - off;
+    if(n > wnd - off)
+      n = wnd - off;
+    if(n > TCP_MSS)
+      n = TCP_MSS;
+    tcp_send(tp, TCP_ACK | TCP_PSH, tp->snd_una + off, tp->sbuf + off, n);
+    tcp_track(tp, tp->snd_una + off, n, 0);
+    off += n;
+  }
+  tp->snd_nxt = tp->snd_una + off;
+
+  if(tp->closing && off == tp->slen && tp->rtqlen < TCP_RTQ){
+    tcp_send(tp, TCP_FIN | TCP_ACK, tp->snd_nxt, 0, 0);
+    tcp_track(tp, tp->snd_nxt, 1, TCP_FIN);
+    tp->snd_nxt++;
+    tp->finsent = 1;
+  }
+}
+
+// the connection is over; err says why.
+// caller holds tcplock.
+static void
+tcp_closed(struct tcpcb *tp, int err)
+{
+  tp->state = TCP_CLOSED;
+  tp->err = err;
+  tcp_canceltimer(tp);
+  wakeup(tp);
+  if(tp->parent)
+    wakeup(tp->parent);
+  if(!tp->owned)
+    tcp_free(tp);
+}
+
+// the connection tcp_rx() should give a segment to: the one
+// for exactly its addresses, or else a listener on its port.
+// caller holds tcplock.
+static struct tcpcb *
+tcp_lookup(uint32 src, int sport, int dport)
+{
+  struct tcpcb *listener = 0;
+
+  for(struct tcpcb *tp = tcbs; tp < tcbs + NTCP; tp++){
+    if(tp->state == TCP_FREE || tp->state == TCP_CLOSED || tp->lport != dport)
+      continue;
+    if(tp->state == TCP_LISTEN)
+      listener = tp;
+    else if(tp->rip == src && tp->rport == sport)
+      return tp;
+  }
+  return listener;
+}
+
+// a SYN for listener lp: start a connection in SYN_RCVD, unless
+// the backlog is full, in which case the SYN is ignored and the
+// peer will try again.
+// caller holds tcplock.
+static void
+tcp_passive(struct tcpcb *lp, uint32 src, struct tcp *th)
+{
+  int waiting = 0;
+  for(struct tcpcb *tp = tcbs; tp < tcbs + NTCP; tp++)
+    if(tp->state != TCP_FREE && tp->parent == lp)
+      waiting++;
+  if(waiting >= TCP_BACKLOG)
+    return;
+
+  struct tcpcb *tp = tcp_alloc();
+  if(tp == 0)
+    return;
+  tp->state = TCP_SYN_RCVD;
+  tp->owned = 1;
+  tp->parent = lp;
+  tp->lport = lp->lport;
+  tp->rport = ntohs(th->sport);
+  tp->rip = src;
+  tp->rcv_nxt = ntohl(th->seq) + 1;
+  tp->snd_una = tp->iss;
+  tp->snd_nxt = tp->iss + 1;
+  tp->snd_wnd = ntohs(th->win);
+  tcp_send(tp, TCP_SYN | TCP_ACK, tp->iss, 0, 0);
+  tcp_track(tp, tp->iss, 1, TCP_SYN);
+}
+
+// process a segment for tp, RFC 793 section 3.9.
+// data is the segment's dlen bytes of payload.
+// caller holds tcplock.
+static void
+tcp_input(struct tcpcb *tp, uint32 src, struct tcp *th, char *data, int dlen)
+{
+  uint32 seq = ntohl(th->seq);
+  uint32 ack = ntohl(th->ack);
+  int flags = th->flags;
+
+  if(tp->state == TCP_LISTEN){
+    if(flags & TCP_RST)
+      return;
+    if(flags & TCP_ACK)
+      tcp_reset(src, th, dlen);
+    else if(flags & TCP_SYN)
+      tcp_passive(tp, src, th);
+    return;
+  }
+
+  if(tp->state == TCP_SYN_SENT){
+    if((flags & TCP_ACK) && ack != tp->iss + 1){
+      tcp_reset(src, th, dlen);
+      return;
+    }
+    if(flags & TCP_RST){
+      if(flags & TCP_ACK)
+        tcp_closed(tp, -NET_ECONNREFUSED);
+      return;
+    }
+    if((flags & (TCP_SYN | TCP_ACK)) != (TCP_SYN | TCP_ACK))
+      return;
+    tp->rcv_nxt = seq + 1;
+    tcp_acked(tp, ack);
+    tp->snd_wnd = ntohs(th->win);
+    tp->state = TCP_ESTABLISHED;
+    tcp_send(tp, TCP_ACK, tp->snd_nxt, 0, 0);
+    wakeup(tp);
+    return;
+  }
+
+  // a retransmitted SYN: our SYN-ACK was lost.
+  if(tp->state == TCP_SYN_RCVD && (flags & TCP_SYN)){
+    tcp_send(tp, TCP_SYN | TCP_ACK, tp->iss, 0, 0);
+    return;
+  }
+
+  // only the next segment in order is accepted; the ack for
+  // anything else tells the peer where to start again.
+  // a bare ack is still worth its ack field.
+  if(seq != tp->rcv_nxt){
+    if(dlen > 0 || (flags & (TCP_SYN | TCP_FIN)))
+      tcp_send(tp, TCP_ACK, tp->snd_nxt, 0, 0);
+    if(dlen > 0 || (flags & (TCP_SYN | TCP_FIN | TCP_RST)))
+      return;
+  }
+
+  if(flags & TCP_RST){
+    tcp_closed(tp, -NET_ECONNRESET);
+    return;
+  }
+  if((flags & TCP_SYN) || !(flags & TCP_ACK))
+    return;
+
+  if(tp->state == TCP_SYN_RCVD){
+    if(ack != tp->iss + 1){
+      tcp_reset(src, th, dlen);
+      return;
+    }
+    tcp_acked(tp, ack);
+    tp->state = TCP_ESTABLISHED;
+    wakeup(tp->parent);
+  }
+
+  if(SEQ_LT(tp->snd_una, ack) && SEQ_LEQ(ack, tp->snd_nxt)){
+    tcp_acked(tp, ack);
+    if(tp->finsent && ack == tp->snd_nxt){
+      // our FIN is acknowledged.
+      if(tp->state == TCP_FIN_WAIT_1){
+        tp->state = TCP_FIN_WAIT_2;
+        // a peer that never sends its FIN mustn't keep
+        // a closed connection's tcpcb forever.
+        if(!tp->owned)
+          tcp_settimer(tp, 2 * TCP_MSL);
+      } else if(tp->state == TCP_CLOSING){
+        tp->state = TCP_TIME_WAIT;
+        tcp_settimer(tp, 2 * TCP_MSL);
+      } else if(tp->state == TCP_LAST_ACK){
+        tcp_closed(tp, 0);
+        return;
+      }
+    }
+  }
+  if(SEQ_LEQ(tp->snd_una, ack)){
+    // a peer answering probes of its closed window is still there.
+    if(ack == tp->snd_una && th->win == 0 && tp->snd_wnd == 0)
+      tp->retries = 0;
+    tp->snd_wnd = ntohs(th->win);
+  }
+
+  // nobody will read what arrives after close; tell the
+  // peer, rather than keep it in rbuf.
+  if(dlen > 0 && !tp->owned &&
+     (tp->state == TCP_FIN_WAIT_1 || tp->state == TCP_FIN_WAIT_2)){
+    tcp_send(tp, TCP_RST | TCP_ACK, tp->snd_nxt, 0, 0);
+    tcp_closed(tp, -NET_ECONNRESET);
+    return;
+  }
+
+  // data goes into rbuf as far as there's room; the rest is
+  // dropped, and sent again once the window opens.
+  int n = 0;
+  if(dlen > 0 && (tp->state == TCP_ESTABLISHED || tp->state == TCP_FIN_WAIT_1 ||
+                  tp->state == TCP_FIN_WAIT_2)){
+    n = TCP_BUFSZ - tp->rlen;
+    if(n > dlen)
+      n = dlen;
+    for(int i = 0; i < n; i++)
+      tp->rbuf[(tp->rhead + tp->rlen + i) % TCP_BUFSZ] = data[i];
+    tp->rlen += n;
+    tp->rcv_nxt += n;
+    if(n > 0)
+      wakeup(tp);
+  }
+
+  // the peer's FIN counts only once all the data before it is in.
+  if((flags & TCP_FIN) && n == dlen){
+    tp->rcv_nxt++;
+    if(tp->state == TCP_ESTABLISHED || tp->state == TCP_SYN_RCVD){
+      tp->state = TCP_CLOSE_WAIT;
+    } else if(tp->state == TCP_FIN_WAIT_1){
+      tp->state = TCP_CLOSING;
+    } else if(tp->state == TCP_FIN_WAIT_2){
+      tp->state = TCP_TIME_WAIT;
+      tcp_settimer(tp, 2 * TCP_MSL);
+    }
+    wakeup(tp);
+  }
+
+  if(dlen > 0 || (flags & TCP_FIN))
+    tcp_send(tp, TCP_ACK, tp->snd_nxt, 0, 0);
+  tcp_output(tp);
+}
+
+// handle a TCP segment from ip_rx(). takes ownership of buf.
+// returns 0, or -NET_E* if the segment was dropped.
+static int
+tcp_rx(char *buf, int len, struct rxinfo *ri)
+{
+  struct ip *ip = (struct ip *)(buf + ri->nh);
+  int ihl = ri->th - ri->nh;
+  int iplen = ntohs(ip->ip_len);
+  struct tcp *th = (struct tcp *)(buf + ri->th);
+  int rc = 0;
+
+  // TCP connections are between two hosts; no broadcasts.
+  if(ri->group){
+    kfree(buf);
+    return -NET_EPROTO;
+  }
+  if(iplen < ihl + sizeof(*th) || len < sizeof(struct eth) + iplen){
+    kfree(buf);
+    return -NET_ETRUNC;
+  }
+  int tlen = iplen - ihl;
+  int thl = (th->off >> 4) * 4;
+  if(thl < sizeof(*th) || thl > tlen){
+    kfree(buf);
+    return -NET_ETRUNC;
+  }
+  if(cksum_fold(cksum_partial(ip_pseudo_sum(ip->ip_src, ip->ip_dst, IPPROTO_TCP, tlen),
+                              th, tlen)) != 0){
+    kfree(buf);
+    return -NET_EBADSUM;
+  }
+
+  uint32 src = ntohl(ip->ip_src);
+  acquire(&tcplock);
+  struct tcpcb *tp = tcp_lookup(src, ntohs(th->sport), ntohs(th->dport));
+  if(tp){
+    tcp_input(tp, src, th, (char *)th + thl, tlen - thl);
+  } else {
+    tcp_reset(src, th, tlen - thl);
+    rc = -NET_ENOTBOUND;
+  }
+  release(&tcplock);
+
+  kfree(buf);
+  tcp_flush();
+  return rc;
+}
+
+// tp's timer has gone off: leave TIME_WAIT, give up on a peer
+// that hasn't sent its FIN, or send the oldest unacknowledged
+// segment again and double the timeout.
+// caller holds tcplock.
+static void
+tcp_expire(struct tcpcb *tp)
+{
+  if(tp->state == TCP_TIME_WAIT || tp->state == TCP_FIN_WAIT_2){
+    tcp_closed(tp, 0);
+    return;
+  }
+  if(tp->rtqlen == 0)
+    return;
+  if(tp->retries >= TCP_RETRIES){
+    tcp_send(tp, TCP_RST | TCP_ACK, tp->snd_nxt, 0, 0);
+    tcp_closed(tp, -NET_ETIMEDOUT);
+    return;
+  }
+  tp->retries++;
+  tp->rto = tp->rto * 2 > TCP_MAXRTO ? TCP_MAXRTO : tp->rto * 2;
+  tcp_rexmit(tp);
+  tcp_settimer(tp, tp->rto);
+}
+
+// turn the timer wheel: run the timers due this tick.
+// called by clockintr() every tick.
+void
+tcp_timer(void)
+{
+  if(atomic_read4(&nactive) == 0)
+    return;
+
+  acquire(&tcplock);
+  uint now = ticks;
+  struct tcpcb **pp = &wheel[now % TCP_WHEEL];
+  while(*pp){
+    struct tcpcb *tp = *pp;
+    if((int)(tp->deadline - now) > 0){
+      pp = &tp->tnext; // due on a later turn
+      continue;
+    }
+    *pp = tp->tnext;
+    tp->tnext = 0;
+    tp->deadline = 0;
+    tcp_expire(tp);
+  }
+  release(&tcplock);
+  tcp_flush();
+}
+
+// give the connection tp a file, or free it if there's none.
+static int
+tcp_file(struct tcpcb *tp, struct file **f)
+{
+  if((*f = filealloc()) == 0){
+    tcpclose(tp);
+    return -NET_ENOMEM;
+  }
+  (*f)->type = FD_TCP;
+  (*f)->readable = 1;
+  (*f)->writable = 1;
+  (*f)->tcp = tp;
+  return 0;
+}
+
+// listen for connections to port.
+// returns 0, or -NET_E*.
+int
+tcplisten(struct file **f, int port)
+{
+  if(port <= 0 || port > 65535)
+    return -NET_EINVAL;
+
+  acquire(&tcplock);
+  for(struct tcpcb *tp = tcbs; tp < tcbs + NTCP; tp++){
+    if(tp->state == TCP_LISTEN && tp->lport == port){
+      release(&tcplock);
+      return -NET_EADDRINUSE;
+    }
+  }
+  struct tcpcb *tp = tcp_alloc();
+  if(tp == 0){
+    release(&tcplock);
+    return -NET_ENOMEM;
+  }
+  tp->state = TCP_LISTEN;
+  tp->owned = 1;
+  tp->lport = port;
+  release(&tcplock);
+
+  return tcp_file(tp, f);
+}
+
+// wait for a connection to the listener lf.
+// returns 0, or -NET_E*, or -1 if killed.
+int
+tcpaccept(struct file *lf, struct file **f)
+{
+  if(lf->type != FD_TCP)
+    return -NET_EINVAL;
+  struct tcpcb *lp = lf->tcp;
+
+  acquire(&tcplock);
+  if(lp->state != TCP_LISTEN){
+    release(&tcplock);
+    return -NET_EINVAL;
+  }
+  for(;;){
+    for(struct tcpcb *tp = tcbs; tp < tcbs + NTCP; tp++){
+      if(tp->state == TCP_FREE || tp->parent != lp)
+        continue;
+      if(tp->state == TCP_CLOSED){
+        // reset before it was accepted.
+        tp->owned = 0;
+        tp->parent = 0;
+        tcp_free(tp);
+      } else if(tp->state != TCP_SYN_RCVD){
+        tp->parent = 0;
+        release(&tcplock);
+        return tcp_file(tp, f);
+      }
+    }
+    if(killed(myproc())){
+      release(&tcplock);
+      return -1;
+    }
+    sleep(lp, &tcplock);
+  }
+}
+
+// connect to port on ip, and wait until the connection is
+// established. returns 0, or -NET_E*, or -1 if killed.
+int
+tcpconnect(struct file **f, uint32 ip, int port)
+{
+  if(port <= 0 || port > 65535)
+    return -NET_EINVAL;
+
+  acquire(&tcplock);
+  int lport = tcp_port();
+  struct tcpcb *tp = lport ? tcp_alloc() : 0;
+  if(tp == 0){
+    release(&tcplock);
+    return lport ? -NET_ENOMEM : -NET_EADDRINUSE;
+  }
+  tp->state = TCP_SYN_SENT;
+  tp->owned = 1;
+  tp->lport = lport;
+  tp->rport = port;
+  // replies from 127.0.0.0/8 come from xv6's own address,
+  // the source of everything it sends.
+  tp->rip = (ip >> 24) == 127 ? local_ip() : ip;
+  tp->snd_una = tp->iss;
+  tp->snd_nxt = tp->iss + 1;
+  tcp_send(tp, TCP_SYN, tp->iss, 0, 0);
+  tcp_track(tp, tp->iss, 1, TCP_SYN);
+  release(&tcplock);
+  tcp_flush();
+
+  acquire(&tcplock);
+  while(tp->state == TCP_SYN_SENT && !killed(myproc()))
+    sleep(tp, &tcplock);
+  if(tp->state != TCP_ESTABLISHED && tp->state != TCP_CLOSE_WAIT){
+    int err = tp->state == TCP_SYN_SENT ? -1 : tp->err;
+    release(&tcplock);
+    tcpclose(tp);
+    return err;
+  }
+  release(&tcplock);
+
+  return tcp_file(tp, f);
+}
+
+// the file's last reference is gone. send what's left and a
+// FIN; the tcpcb lives on until the peer acknowledges them,
+// then waits 2*TCP_MSL at most for the peer's FIN. data the
+// peer sends meanwhile is answered with a reset.
+void
+tcpclose(struct tcpcb *tp)
+{
+  acquire(&tcplock);
+  tp->owned = 0;
+  switch(tp->state){
+  case TCP_LISTEN:
+    // reset the connections no one accepted.
+    for(struct tcpcb *c = tcbs; c < tcbs + NTCP; c++){
+      if(c->state == TCP_FREE || c->parent != tp)
+        continue;
+      if(c->state != TCP_CLOSED)
+        tcp_send(c, TCP_RST | TCP_ACK, c->snd_nxt, 0, 0);
+      c->parent = 0;
+      c->owned = 0;
+      tcp_free(c);
+    }
+    tcp_free(tp);
+    break;
+  case TCP_ESTABLISHED:
+    tp->closing = 1;
+    tp->state = TCP_FIN_WAIT_1;
+    tcp_output(tp);
+    break;
+  case TCP_CLOSE_WAIT:
+    tp->closing = 1;
+    tp->state = TCP_LAST_ACK;
+    tcp_output(tp);
+    break;
+  case TCP_SYN_SENT:
+    tp->state = TCP_CLOSED;
+    // fall through
+  case TCP_CLOSED:
+    tcp_free(tp);
+    break;
+  }
+  release(&tcplock);
+  tcp_flush();
+}
+
+// read what has arrived, waiting if there's nothing yet.
+// returns the number of bytes read, 0 once the peer has
+// closed its side, -NET_E* if the connection failed, or -1.
+int
+tcpread(struct tcpcb *tp, uint64 addr, int n)
+{
+  struct proc *pr = myproc();
+
+  acquire(&tcplock);
+  while(tp->rlen == 0 && (tp->state == TCP_ESTABLISHED ||
+                          tp->state == TCP_FIN_WAIT_1 ||
+                          tp->state == TCP_FIN_WAIT_2)){
+    if(killed(pr)){
+      release(&tcplock);
+      return -1;
+    }
+    sleep(tp, &tcplock);
+  }
+  if(tp->rlen == 0){
+    int err = tp->state == TCP_LISTEN ? -NET_EINVAL : tp->err;
+    release(&tcplock);
+    return err;
+  }
+
+  if(n > tp->rlen)
+    n = tp->rlen;
+  int wasclosed = tcp_window(tp) < TCP_MSS;
+  for(int done = 0; done < n; ){
+    int m = n - done;
+    if(m > TCP_BUFSZ - tp->rhead)
+      m = TCP_BUFSZ - tp->rhead;
+    if(copyout(pr->pagetable, addr + done, tp->rbuf + tp->rhead, m) < 0){
+      // a bad address; 0 would look like the peer closing.
+      n = done > 0 ? done : -1;
+      break;
+    }
+    tp->rhead = (tp->rhead + m) % TCP_BUFSZ;
+    tp->rlen -= m;
+    done += m;
+  }
+  // tell a peer that stopped on a full window that it's open.
+  if(wasclosed && tcp_window(tp) >= TCP_MSS && tp->state != TCP_CLOSED)
+    tcp_send(tp, TCP_ACK, tp->snd_nxt, 0, 0);
+  release(&tcplock);
+  tcp_flush();
+  return n;
+}
+
+// queue n bytes to send, waiting for room in sbuf.
+// returns n, or -NET_E* if the connection failed
+// before any were queued, or -1.
+int
+tcpwrite(struct tcpcb *tp, uint64 addr, int n)
+{
+  struct proc *pr = myproc();
+  int i = 0;
+
+  acquire(&tcplock);
+  while(i < n){
+    if(tp->state != TCP_ESTABLISHED && tp->state != TCP_CLOSE_WAIT){
+      int err = tp->err ? tp->err : -NET_EINVAL;
+      release(&tcplock);
+      return i > 0 ? i : err;
+    }
+    if(tp->slen == TCP_BUFSZ){
+      if(killed(pr)){
+        release(&tcplock);
+        return -1;
+      }
+      sleep(tp, &tcplock);
+      continue;
+    }
+    int m = n - i;
+    if(m > TCP_BUFSZ - tp->slen)
+      m = TCP_BUFSZ - tp->slen;
+    if(copyin(pr->pagetable, tp->sbuf + tp->slen, addr + i, m) < 0)
+      break;
+    tp->slen += m;
+    i += m;
+    tcp_output(tp);
+    release(&tcplock);
+    tcp_flush();
+    acquire(&tcplock);
+  }
+  release(&tcplock);
+  return i;
+}
+
+// describe up to n connections, listeners included, in ns,
+// for netstat(). returns how many.
+int
+tcp_netstat(struct netstat *ns, int n)
+{
+  int i = 0;
+
+  acquire(&tcplock);
+  for(struct tcpcb *tp = tcbs; tp < tcbs + NTCP && i < n; tp++){
+    if(tp->state == TCP_FREE)
+      continue;
+    struct netstat *s = &ns[i++];
+    memset(s, 0, sizeof(*s));
+    s->proto = IPPROTO_TCP;
+    s->lport = tp->lport;
+    s->rport = tp->rport;
+    s->raddr = tp->rip;
+    s->state = tp->state;
+    s->recvq = tp->rlen;
+    s->sendq = tp->slen;
+  }
+  release(&tcplock);
+  return i;
+}
+
+// check the retransmission timeout on a scratch tcpcb: steady
+// round trips bring it down near the round trip time, it stays
+// within its bounds, and, by Karn's rule, the ack for a
+// retransmitted segment doesn't count as a round trip.
+// returns 0 if all is well.
+int
+tcp_selftest(void)
+{
+  struct tcpcb t;
+  char sbuf[16];
+  int rc = 0;
+
+  memset(&t, 0, sizeof(t));
+  t.sbuf = sbuf;
+  t.rto = TCP_INITRTO;
+
+  acquire(&tcplock);
+  for(int i = 0; i < 20; i++)
+    tcp_rtt(&t, 4);
+  if((t.srtt >> 3) != 4 || t.rto <= 4 || t.rto >= TCP_INITRTO)
+    rc = -1;
+  tcp_rtt(&t, 100000);
+  if(t.rto != TCP_MAXRTO)
+    rc = -1;
+
+  int srtt = t.srtt;
+  t.snd_una = 100;
+  t.snd_nxt = 110;
+  t.slen = 10;
+  t.rtq[0] = (struct tcpseg){ .seq = 100, .len = 10, .sent = ticks - 50, .rexmit = 1 };
+  t.rtqlen = 1;
+  // the partial ack sets t's timer; the full one cancels it,
+  // all before tcp_timer() can see it.
+  tcp_acked(&t, 105);
+  if(t.rtqlen != 1 || t.rtq[0].seq != 105 || t.rtq[0].len != 5 || t.slen != 5)
+    rc = -1;
+  tcp_acked(&t, 110);
+  if(t.rtqlen != 0 || t.slen != 0 || t.snd_una != 110 || t.srtt != srtt)
+    rc = -1;
+  release(&tcplock);
+
+  return rc;
+}
@@ -170,7 +170,11 @@ clockintr()
     acquire(&tickslock);
     ticks++;
     wakeup(&ticks);
+    timeoutwakeup();
     release(&tickslock);
+#ifdef LAB_NET
+    tcp_timer();
+#endif
   }
 
   // ask for the next timer interrupt. this also clears
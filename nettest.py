@@ -26,6 +26,7 @@ def usage():
     sys.stderr.write("       nettest.py rx2\n")
     sys.stderr.write("       nettest.py rxburst\n")
     sys.stderr.write("       nettest.py tx\n")
+    sys.stderr.write("       nettest.py txbig\n")
     sys.stderr.write("       nettest.py ping\n")
     sys.stderr.write("       nettest.py grade\n")
     sys.exit(1)
@@ -128,6 +129,20 @@ elif sys.argv[1] == "tx":
         print("tx: OK")
     else:
         print("tx: unexpected packets %s and %s" % (buf0, buf1))
+elif sys.argv[1] == "txbig":
+    #
+    # listen for one large UDP datagram sent by xv6's nettest txbig.
+    # it arrives as several IP fragments, which the host reassembles.
+    #
+    sock = socket.socket(socket.AF_INET, socket.SOCK_DGRAM)
+    sock.bind(('127.0.0.1', SERVERPORT))
+    print("txbig: listening for a UDP datagram")
+    buf, raddr = sock.recvfrom(65536)
+    want = bytes((ord('a') + i % 26) for i in range(3000))
+    if buf == want:
+        print("txbig: OK")
+    else:
+        print("txbig: unexpected payload of length %d" % (len(buf)))
 elif sys.argv[1] == "ping":
     #
     # listen for UDP packets sent by xv6's nettest ping,
@@ -0,0 +1,94 @@
+//
+// show or change the network configuration.
+// ifconfig
+// ifconfig ip|mask|gateway|dns a.b.c.d
+// ifconfig mtu n
+//
+
+#include "kernel/types.h"
+#include "kernel/net.h"
+#include "kernel/stat.h"
+#include "user/user.h"
+
+// parse a.b.c.d into *ip. returns 0, or -1 if s isn't one.
+int
+parseip(char *s, uint32 *ip)
+{
+  uint32 a = 0;
+
+  for(int i = 0; i < 4; i++){
+    if(*s < '0' || *s > '9')
+      return -1;
+    int n = 0;
+    while(*s >= '0' && *s <= '9')
+      n = n * 10 + *s++ - '0';
+    if(n > 255 || *s != (i < 3 ? '.' : 0))
+      return -1;
+    if(i < 3)
+      s++;
+    a = a << 8 | n;
+  }
+  *ip = a;
+  return 0;
+}
+
+void
+printip(char *name, uint32 ip)
+{
+  printf("%s %d.%d.%d.%d\n", name,
+         ip >> 24, (ip >> 16) & 0xff, (ip >> 8) & 0xff, ip & 0xff);
+}
+
+int
+main(int argc, char *argv[])
+{
+  struct netconf c;
+
+  if(argc != 1 && argc != 3){
+    fprintf(2, "usage: ifconfig [ip|mask|gateway|dns a.b.c.d | mtu n]\n");
+    exit(1);
+  }
+
+  int r;
+  if((r = netconf(&c, 0)) != 0){
+    fprintf(2, "ifconfig: netconf() failed (%d)\n", r);
+    exit(1);
+  }
+
+  if(argc == 1){
+    printip("ip", c.ip);
+    printip("mask", c.mask);
+    printip("gateway", c.gateway);
+    printip("dns", c.dns);
+    printf("mtu %d\n", c.mtu);
+    printf("mac %x:%x:%x:%x:%x:%x\n",
+           c.mac[0], c.mac[1], c.mac[2], c.mac[3], c.mac[4], c.mac[5]);
+    exit(0);
+  }
+
+  uint32 *field = 0;
+  if(strcmp(argv[1], "ip") == 0)
+    field = &c.ip;
+  else if(strcmp(argv[1], "mask") == 0)
+    field = &c.mask;
+  else if(strcmp(argv[1], "gateway") == 0)
+    field = &c.gateway;
+  else if(strcmp(argv[1], "dns") == 0)
+    field = &c.dns;
+  else if(strcmp(argv[1], "mtu") == 0)
+    c.mtu = atoi(argv[2]);
+  else {
+    fprintf(2, "ifconfig: unknown setting %s\n", argv[1]);
+    exit(1);
+  }
+  if(field && parseip(argv[2], field) != 0){
+    fprintf(2, "ifconfig: bad address %s\n", argv[2]);
+    exit(1);
+  }
+
+  if((r = netconf(0, &c)) != 0){
+    fprintf(2, "ifconfig: netconf() failed (%d)\n", r);
+    exit(1);
+  }
+  exit(0);
+}
@@ -23,6 +23,15 @@ main(void)
   dup(0);  // stdout
   dup(0);  // stderr
 
+#ifdef LAB_NET
+  // lease an address in the background; until it's done, or if
+  // no DHCP server answers, the static configuration stands.
+  if(fork() == 0){
+    dhcp();
+    exit(0);
+  }
+#endif
+
   for(;;){
     printf("init: starting sh\n");
     pid = fork();
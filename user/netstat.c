@@ -0,0 +1,70 @@
+//
+// show the bound UDP ports and the TCP connections, one line each.
+// netstat
+//
+
+#include "kernel/types.h"
+#include "kernel/net.h"
+#include "kernel/stat.h"
+#include "user/user.h"
+
+char *states[] = {
+[TCP_FREE]        "FREE",
+[TCP_CLOSED]      "CLOSED",
+[TCP_LISTEN]      "LISTEN",
+[TCP_SYN_SENT]    "SYN_SENT",
+[TCP_SYN_RCVD]    "SYN_RCVD",
+[TCP_ESTABLISHED] "ESTABLISHED",
+[TCP_FIN_WAIT_1]  "FIN_WAIT_1",
+[TCP_FIN_WAIT_2]  "FIN_WAIT_2",
+[TCP_CLOSING]     "CLOSING",
+[TCP_TIME_WAIT]   "TIME_WAIT",
+[TCP_CLOSE_WAIT]  "CLOSE_WAIT",
+[TCP_LAST_ACK]    "LAST_ACK",
+};
+
+// print the remote end, a.b.c.d:port, with * for any.
+void
+printpeer(uint32 ip, int port)
+{
+  if(ip == 0)
+    printf("*");
+  else
+    printf("%d.%d.%d.%d", ip >> 24, (ip >> 16) & 0xff, (ip >> 8) & 0xff, ip & 0xff);
+  if(port == 0)
+    printf(":*");
+  else
+    printf(":%d", port);
+}
+
+int
+main(int argc, char *argv[])
+{
+  static struct netstat ns[128];
+
+  if(argc != 1){
+    fprintf(2, "usage: netstat\n");
+    exit(1);
+  }
+
+  int n = netstat(ns, 128);
+  if(n < 0){
+    fprintf(2, "netstat: netstat() failed (%d)\n", n);
+    exit(1);
+  }
+  for(int i = 0; i < n; i++){
+    struct netstat *s = &ns[i];
+    if(s->proto == IPPROTO_UDP){
+      printf("udp %d ", s->lport);
+      printpeer(s->raddr, s->rport);
+      printf(" recvq %d dropped %d pid %d\n", s->recvq, (int)s->dropped, s->pid);
+    } else {
+      printf("tcp %d ", s->lport);
+      printpeer(s->raddr, s->rport);
+      printf(" %s recvq %d sendq %d\n",
+             s->state < sizeof(states) / sizeof(states[0]) ? states[s->state] : "?",
+             s->recvq, s->sendq);
+    }
+  }
+  exit(0);
+}
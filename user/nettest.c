@@ -246,6 +246,70 @@ tx()
   return 1;
 }
 
+//
+// send one UDP datagram too big for a single ethernet frame,
+// so that it goes out as IP fragments.
+// nettest.py txbig must be started first.
+//
+int
+txbig()
+{
+  static char buf[3000];
+
+  printf("txbig: sending a %ld-byte datagram\n", sizeof(buf));
+  for(int i = 0; i < sizeof(buf); i++)
+    buf[i] = 'a' + i % 26;
+
+  uint32 dst = 0x0A000202; // 10.0.2.2
+  if(send(2011, dst, NET_TESTS_PORT, buf, sizeof(buf)) < 0){
+    printf("txbig: send() failed\n");
+    return 0;
+  }
+
+  // nettest.py checks the reassembled payload.
+  return 1;
+}
+
+//
+// send a datagram as big as IP allows. it takes more fragments
+// than the e1000's transmit ring holds, so send() must wait for
+// descriptors to come free rather than give up partway.
+// doesn't need nettest.py.
+//
+int
+txmax()
+{
+  static char buf[IP_MAXPACKET - sizeof(struct ip) - sizeof(struct udp)];
+  struct nicstats before, after;
+  struct netconf c;
+
+  printf("txmax: starting\n");
+
+  if(netconf(&c, 0) != 0 || nicstats(&before) != 0){
+    printf("txmax: couldn't read the configuration\n");
+    return 0;
+  }
+  int maxfrag = (c.mtu - sizeof(struct ip)) & ~7;
+  int nfrags = (sizeof(buf) + sizeof(struct udp) + maxfrag - 1) / maxfrag;
+
+  uint32 dst = 0x0A000202; // 10.0.2.2
+  int cc = send(2054, dst, 2054, buf, sizeof(buf));
+  nicstats(&after);
+  if(cc != sizeof(buf)){
+    printf("txmax: send() returned %d\n", cc);
+    return 0;
+  }
+  if(after.tx_packets - before.tx_packets < nfrags){
+    printf("txmax: %d of %d fragments went out\n",
+           (int)(after.tx_packets - before.tx_packets), nfrags);
+    return 0;
+  }
+
+  printf("txmax: OK\n");
+
+  return 1;
+}
+
 //
 // send just one UDP packets to nettest.py ping,
 // expect a reply.
@@ -362,6 +426,106 @@ ping1()
   return 1;
 }
 
+//
+// recv() with a maxlen of 0 should consume the queued packet,
+// copy out its source, and return 0.
+// nettest.py ping must be started first.
+//
+int
+recv0()
+{
+  printf("recv0: starting\n");
+
+  bind(2012);
+
+  uint32 dst = 0x0A000202; // 10.0.2.2
+  if(send(2012, dst, NET_TESTS_PORT, "first", 5) < 0 ||
+     send(2012, dst, NET_TESTS_PORT, "second", 6) < 0){
+    printf("recv0: send() failed\n");
+    return 0;
+  }
+
+  char ibuf[128];
+  uint32 src = 0;
+  uint16 sport = 0;
+  int cc = recv(2012, &src, &sport, ibuf, 0);
+  if(cc != 0){
+    printf("recv0: recv() with maxlen 0 returned %d\n", cc);
+    return 0;
+  }
+
+  if(src != 0x0A000202 || sport != NET_TESTS_PORT){
+    printf("recv0: wrong source %x:%d\n", src, sport);
+    return 0;
+  }
+
+  // the first packet was consumed, so this must be the second.
+  memset(ibuf, 0, sizeof(ibuf));
+  cc = recv(2012, &src, &sport, ibuf, sizeof(ibuf)-1);
+  if(cc != 6 || memcmp(ibuf, "second", 6) != 0){
+    printf("recv0: expected \"second\", got %d bytes\n", cc);
+    return 0;
+  }
+
+  printf("recv0: OK\n");
+
+  return 1;
+}
+
+//
+// send three packets to nettest.py ping, and receive all
+// three replies with a single recvmmsg().
+// nettest.py ping must be started first.
+//
+int
+mmsg()
+{
+  printf("recvmmsg: starting\n");
+
+  bind(2016);
+
+  uint32 dst = 0x0A000202; // 10.0.2.2
+  char *payloads[3] = { "m 0", "m 1", "m 2" };
+  for(int i = 0; i < 3; i++){
+    if(send(2016, dst, NET_TESTS_PORT, payloads[i], 3) < 0){
+      printf("recvmmsg: send() failed\n");
+      return 0;
+    }
+  }
+
+  // give all three replies time to arrive.
+  sleep(10);
+
+  char bufs[4][16];
+  struct netmsg msgs[4];
+  for(int i = 0; i < 4; i++){
+    memset(&msgs[i], 0, sizeof(msgs[i]));
+    msgs[i].buf = bufs[i];
+    msgs[i].maxlen = sizeof(bufs[i]);
+  }
+
+  int n = recvmmsg(2016, msgs, 4, 0);
+  if(n != 3){
+    printf("recvmmsg: received %d packets, expecting 3\n", n);
+    return 0;
+  }
+
+  for(int i = 0; i < 3; i++){
+    if(msgs[i].len != 3 || memcmp(bufs[i], payloads[i], 3) != 0){
+      printf("recvmmsg: wrong content in packet %d\n", i);
+      return 0;
+    }
+    if(msgs[i].src != dst || msgs[i].sport != NET_TESTS_PORT){
+      printf("recvmmsg: wrong source %x:%d\n", msgs[i].src, msgs[i].sport);
+      return 0;
+    }
+  }
+
+  printf("recvmmsg: OK\n");
+
+  return 1;
+}
+
 //
 // send UDP packets from two different ports to nettest.py ping,
 // expect a reply to each to appear on the correct port.
@@ -599,6 +763,175 @@ ping3()
   return 1;
 }
 
+//
+// check that send() and recv() report distinct errors
+// for distinct failures. doesn't need nettest.py.
+//
+int
+errors()
+{
+  printf("errors: starting\n");
+
+  char buf[8];
+  uint32 src;
+  uint16 sport;
+  int cc = recv(2100, &src, &sport, buf, sizeof(buf));
+  if(cc != -NET_ENOTBOUND){
+    printf("errors: recv() on an unbound port returned %d, expecting %d\n", cc, -NET_ENOTBOUND);
+    return 0;
+  }
+
+  cc = send(2100, 0x0A000202, NET_TESTS_PORT, buf, 70000);
+  if(cc != -NET_EINVAL){
+    printf("errors: oversized send() returned %d, expecting %d\n", cc, -NET_EINVAL);
+    return 0;
+  }
+
+  printf("errors: OK\n");
+
+  return 1;
+}
+
+//
+// exercise send() and recv() over the loopback path,
+// both from user space and with the kernel's own self-test.
+// doesn't need nettest.py.
+//
+int
+loopback()
+{
+  printf("loopback: starting\n");
+
+  if(netselftest() != 0){
+    printf("loopback: netselftest() failed\n");
+    return 0;
+  }
+
+  bind(2013);
+
+  uint32 dst = 0x0A00020F; // 10.0.2.15, ourselves
+  if(send(2014, dst, 2013, "loop", 4) < 0){
+    printf("loopback: send() failed\n");
+    return 0;
+  }
+
+  char ibuf[128];
+  uint32 src = 0;
+  uint16 sport = 0;
+  int cc = recv(2013, &src, &sport, ibuf, sizeof(ibuf)-1);
+  if(cc != 4 || memcmp(ibuf, "loop", 4) != 0){
+    printf("loopback: wrong payload, length %d\n", cc);
+    return 0;
+  }
+
+  if(src != dst || sport != 2014){
+    printf("loopback: wrong source %x:%d\n", src, sport);
+    return 0;
+  }
+
+  printf("loopback: OK\n");
+
+  return 1;
+}
+
+//
+// unbind() should release a port's queue, so that binding and
+// unbinding over and over never runs out of queues, and only
+// the process that bound a port should be able to unbind it.
+// doesn't need nettest.py.
+//
+int
+unbindtest()
+{
+  printf("unbind: starting\n");
+
+  for(int i = 0; i < 200; i++){
+    if(bind(2015) < 0){
+      printf("unbind: bind() failed on iteration %d\n", i);
+      return 0;
+    }
+    if(unbind(2015) != 0){
+      printf("unbind: unbind() failed on iteration %d\n", i);
+      return 0;
+    }
+  }
+
+  // a process can only unbind its own ports.
+  bind(2015);
+  int pid = fork();
+  if(pid == 0)
+    exit(unbind(2015) == -NET_ENOTBOUND ? 0 : 1);
+  int xst;
+  wait(&xst);
+  if(xst != 0 || unbind(2015) != 0){
+    printf("unbind: a child unbound its parent's port\n");
+    return 0;
+  }
+
+  int r = unbind(2015);
+  if(r != -NET_ENOTBOUND){
+    printf("unbind: unbind() of an unbound port returned %d\n", r);
+    return 0;
+  }
+
+  char buf[8];
+  uint32 src;
+  uint16 sport;
+  r = recv(2015, &src, &sport, buf, sizeof(buf));
+  if(r != -NET_ENOTBOUND){
+    printf("unbind: recv() on an unbound port returned %d\n", r);
+    return 0;
+  }
+
+  printf("unbind: OK\n");
+
+  return 1;
+}
+
+//
+// bind(0) should pick distinct ephemeral ports until the
+// kernel runs out of queues, and then fail.
+// doesn't need nettest.py.
+//
+int
+ephemeral()
+{
+  static int ports[1000];
+  int n, ok = 1;
+
+  printf("ephemeral: starting\n");
+
+  for(n = 0; n < 1000; n++){
+    int port = bind(0);
+    if(port < 0)
+      break;
+    if(port < 49152 || port > 65535){
+      printf("ephemeral: port %d out of range\n", port);
+      ok = 0;
+    }
+    for(int i = 0; i < n; i++){
+      if(ports[i] == port){
+        printf("ephemeral: port %d handed out twice\n", port);
+        ok = 0;
+      }
+    }
+    ports[n] = port;
+  }
+
+  if(n == 0 || n == 1000){
+    printf("ephemeral: bound %d ports\n", n);
+    ok = 0;
+  }
+
+  for(int i = 0; i < n; i++)
+    unbind(ports[i]);
+
+  if(ok)
+    printf("ephemeral: OK\n");
+
+  return ok;
+}
+
 // Encode a DNS name
 void
 encode_qname(char *qn, char *host)
@@ -814,18 +1147,1756 @@ dns()
   }
 }  
 
+//
+// look up a name with the kernel's resolver.
+//
+int
+resolvetest()
+{
+  uint32 ip = 0;
+
+  printf("resolve: starting\n");
+
+  int r = resolve("pdos.csail.mit.edu", &ip);
+  if(r < 0){
+    printf("resolve: resolve() failed: %d\n", r);
+    return 0;
+  }
+
+  if(ip != ((128 << 24) | (52 << 16) | (129 << 8) | 126)){
+    printf("resolve: wrong ip address %x\n", ip);
+    return 0;
+  }
+
+  printf("resolve: OK\n");
+
+  return 1;
+}
+
+//
+// two processes bound to the same port with BIND_REUSEPORT
+// should split the packets sent to it between them,
+// and a plain bind() of that port should fail.
+// doesn't need nettest.py.
+//
+// send() from port 0 should pick an ephemeral port once and keep
+// using it, and recv(0, ...) should get the replies sent to it.
+// doesn't need nettest.py.
+//
+int
+autoport()
+{
+  char ibuf[16];
+  uint32 src;
+  uint16 sport, first;
+  int ok = 1;
+
+  printf("autoport: starting\n");
+
+  if(recvtimeout(0, &src, &sport, ibuf, sizeof(ibuf), 1) != -NET_ENOTBOUND){
+    printf("autoport: recv(0) worked before any send(0)\n");
+    return 0;
+  }
+
+  bind(2036);
+  uint32 dst = 0x0A00020F; // 10.0.2.15, ourselves
+  for(int i = 0; i < 2; i++){
+    if(send(0, dst, 2036, "auto", 4) != 4){
+      printf("autoport: send() from port 0 failed\n");
+      ok = 0;
+    }
+  }
+  recvtimeout(2036, &src, &sport, ibuf, sizeof(ibuf), 5);
+  first = sport;
+  recvtimeout(2036, &src, &sport, ibuf, sizeof(ibuf), 5);
+  if(first < 49152 || sport != first){
+    printf("autoport: sent from ports %d and %d\n", first, sport);
+    ok = 0;
+  }
+
+  // the port is taken now, for bind() as much as for bind(0).
+  if(bind(first) != -NET_EADDRINUSE){
+    printf("autoport: bind() of the picked port didn't fail\n");
+    ok = 0;
+  }
+
+  send(2036, dst, first, "reply", 5);
+  int cc = recvtimeout(0, &src, &sport, ibuf, sizeof(ibuf), 5);
+  if(cc != 5 || sport != 2036){
+    printf("autoport: recv(0) got %d bytes from port %d\n", cc, sport);
+    ok = 0;
+  }
+
+  // a child's port is unbound when it exits.
+  int pid = fork();
+  if(pid == 0){
+    send(0, dst, 2036, "x", 1);
+    exit(0);
+  }
+  wait(0);
+  if(recvtimeout(2036, &src, &sport, ibuf, sizeof(ibuf), 5) != 1){
+    printf("autoport: didn't hear from the child\n");
+    ok = 0;
+  } else if(bind(sport) != sport){
+    printf("autoport: child's port %d still bound after it exited\n", sport);
+    ok = 0;
+  } else {
+    unbind(sport);
+  }
+  unbind(2036);
+
+  if(ok)
+    printf("autoport: OK\n");
+
+  return ok;
+}
+
+//
+int
+reuseport()
+{
+  int fds[2];
+  char c;
+  int n = 10;
+
+  printf("reuseport: starting\n");
+
+  if(pipe(fds) < 0){
+    printf("reuseport: pipe() failed\n");
+    return 0;
+  }
+
+  if(bindflags(2017, BIND_REUSEPORT) != 2017){
+    printf("reuseport: bindflags() failed\n");
+    return 0;
+  }
+
+  int pid = fork();
+  if(pid < 0){
+    printf("reuseport: fork() failed\n");
+    return 0;
+  }
+  if(pid == 0){
+    if(bindflags(2017, BIND_REUSEPORT) != 2017){
+      printf("reuseport: second bindflags() failed\n");
+      exit(1);
+    }
+    write(fds[1], "x", 1);
+    char ibuf[16];
+    uint32 src;
+    uint16 sport;
+    for(int i = 0; i < n/2; i++){
+      if(recv(2017, &src, &sport, ibuf, sizeof(ibuf)) != 1){
+        printf("reuseport: child recv() failed\n");
+        exit(1);
+      }
+    }
+    unbind(2017);
+    exit(0);
+  }
+
+  int ok = 1;
+  read(fds[0], &c, 1);
+  close(fds[0]);
+  close(fds[1]);
+
+  int r = bind(2017);
+  if(r != -NET_EADDRINUSE){
+    printf("reuseport: bind() of a shared port returned %d\n", r);
+    ok = 0;
+  }
+
+  uint32 dst = 0x0A00020F; // 10.0.2.15, ourselves
+  for(int i = 0; i < n; i++){
+    if(send(2018, dst, 2017, "r", 1) < 0){
+      printf("reuseport: send() failed\n");
+      ok = 0;
+    }
+  }
+
+  char ibuf[16];
+  uint32 src;
+  uint16 sport;
+  for(int i = 0; i < n/2; i++){
+    if(recv(2017, &src, &sport, ibuf, sizeof(ibuf)) != 1){
+      printf("reuseport: parent recv() failed\n");
+      ok = 0;
+      break;
+    }
+  }
+
+  int xst;
+  wait(&xst);
+  if(xst != 0)
+    ok = 0;
+  unbind(2017);
+
+  // a port bound without BIND_REUSEPORT can't be shared.
+  bind(2017);
+  r = bindflags(2017, BIND_REUSEPORT);
+  if(r != -NET_EADDRINUSE){
+    printf("reuseport: bindflags() of a taken port returned %d\n", r);
+    ok = 0;
+  }
+  unbind(2017);
+
+  // no more than MAXREUSE bindings can share a port.
+  int i;
+  for(i = 0; i < MAXREUSE && bindflags(2017, BIND_REUSEPORT) == 2017; i++)
+    ;
+  r = bindflags(2017, BIND_REUSEPORT);
+  if(i != MAXREUSE || r != -NET_EADDRINUSE){
+    printf("reuseport: %d bindings shared a port, then bindflags() returned %d\n", i, r);
+    ok = 0;
+  }
+  while(i-- > 0)
+    unbind(2017);
+
+  if(ok)
+    printf("reuseport: OK\n");
+
+  return ok;
+}
+
+//
+// nicstats() should count a frame sent out the e1000.
+// doesn't need nettest.py.
+//
+int
+nicstatstest()
+{
+  struct nicstats before, after;
+
+  printf("nicstats: starting\n");
+
+  if(nicstats(&before) != 0){
+    printf("nicstats: nicstats() failed\n");
+    return 0;
+  }
+
+  uint32 dst = 0x0A000202; // 10.0.2.2
+  if(send(2019, dst, NET_TESTS_PORT, "stats", 5) < 0){
+    printf("nicstats: send() failed\n");
+    return 0;
+  }
+
+  if(nicstats(&after) != 0){
+    printf("nicstats: nicstats() failed\n");
+    return 0;
+  }
+
+  if(after.tx_packets <= before.tx_packets){
+    printf("nicstats: tx_packets didn't go up\n");
+    return 0;
+  }
+  if(after.rx_packets < before.rx_packets || after.tx_busy < before.tx_busy){
+    printf("nicstats: a counter went down\n");
+    return 0;
+  }
+
+  if(nicstats((struct nicstats *)0xffffffffff) != -NET_EINVAL){
+    printf("nicstats: bad address accepted\n");
+    return 0;
+  }
+
+  printf("nicstats: OK\n");
+
+  return 1;
+}
+
+//
+// the e1000 qemu emulates should report its link up,
+// at one of the speeds it knows.
+// doesn't need nettest.py.
+//
+int
+linkstatustest()
+{
+  printf("linkstatus: starting\n");
+
+  int speed = linkstatus();
+  if(speed != 10 && speed != 100 && speed != 1000){
+    printf("linkstatus: linkstatus() returned %d\n", speed);
+    return 0;
+  }
+
+  printf("linkstatus: OK, %d Mb/s\n", speed);
+
+  return 1;
+}
+
+//
+// recvtimeout() should give up on an idle port after about
+// the requested number of ticks, and still return a packet
+// that's waiting.
+// doesn't need nettest.py.
+//
+int
+recvtimeouttest()
+{
+  char ibuf[16];
+  uint32 src;
+  uint16 sport;
+  int ok = 1;
+
+  printf("recvtimeout: starting\n");
+
+  bind(2020);
+
+  int t0 = uptime();
+  int r = recvtimeout(2020, &src, &sport, ibuf, sizeof(ibuf), 5);
+  int t1 = uptime();
+  if(r != -NET_ETIMEDOUT){
+    printf("recvtimeout: idle port returned %d\n", r);
+    ok = 0;
+  } else if(t1 - t0 < 4 || t1 - t0 > 50){
+    printf("recvtimeout: waited %d ticks for a 5 tick timeout\n", t1 - t0);
+    ok = 0;
+  }
+
+  uint32 dst = 0x0A00020F; // 10.0.2.15, ourselves
+  if(send(2021, dst, 2020, "tick", 4) < 0){
+    printf("recvtimeout: send() failed\n");
+    ok = 0;
+  } else if((r = recvtimeout(2020, &src, &sport, ibuf, sizeof(ibuf), 5)) != 4){
+    printf("recvtimeout: queued packet returned %d\n", r);
+    ok = 0;
+  }
+
+  unbind(2020);
+
+  if(ok)
+    printf("recvtimeout: OK\n");
+
+  return ok;
+}
+
+//
+// portstats() should count what a port's queue took and what
+// it dropped once full.
+// doesn't need nettest.py.
+//
+int
+portstatstest()
+{
+  struct portstats st;
+  int n = 40;
+  int ok = 1;
+
+  printf("portstats: starting\n");
+
+  if(portstats(2022, &st) != -NET_ENOTBOUND){
+    printf("portstats: unbound port didn't fail\n");
+    return 0;
+  }
+
+  bind(2022);
+
+  // the counters are the binding's own, not another process's.
+  int pid = fork();
+  if(pid == 0)
+    exit(portstats(2022, &st) == -NET_ENOTBOUND ? 0 : 1);
+  int xst;
+  wait(&xst);
+  if(xst != 0){
+    printf("portstats: a child read its parent's counters\n");
+    unbind(2022);
+    return 0;
+  }
+
+  // nobody receives, so the queue fills and the rest are dropped.
+  uint32 dst = 0x0A00020F; // 10.0.2.15, ourselves
+  for(int i = 0; i < n; i++)
+    send(2023, dst, 2022, "s", 1);
+
+  if(portstats(2022, &st) != 0){
+    printf("portstats: portstats() failed\n");
+    ok = 0;
+  } else if(st.received + st.dropped != n || st.dropped == 0 || st.depth != st.received ||
+            st.wakeups != st.received || st.bytes != st.received || st.dropbytes != st.dropped){
+    printf("portstats: received %d dropped %d depth %d\n",
+           (int)st.received, (int)st.dropped, st.depth);
+    ok = 0;
+  }
+
+  char ibuf[8];
+  uint32 src;
+  uint16 sport;
+  recv(2022, &src, &sport, ibuf, sizeof(ibuf));
+  if(ok && (portstats(2022, &st) != 0 || st.depth != st.received - 1)){
+    printf("portstats: depth %d after one recv()\n", st.depth);
+    ok = 0;
+  }
+
+  unbind(2022);
+
+  if(ok)
+    printf("portstats: OK\n");
+
+  return ok;
+}
+
+//
+// send a datagram too big for one frame to nettest.py ping.
+// the echo comes back as IP fragments, which xv6 must reassemble.
+// nettest.py ping must be started first.
+//
+int
+pingbig()
+{
+  static char buf[3000], ibuf[sizeof(buf) + 1];
+
+  printf("pingbig: starting\n");
+
+  for(int i = 0; i < sizeof(buf); i++)
+    buf[i] = 'A' + i % 26;
+
+  bind(2024);
+
+  uint32 dst = 0x0A000202; // 10.0.2.2
+  if(send(2024, dst, NET_TESTS_PORT, buf, sizeof(buf)) < 0){
+    printf("pingbig: send() failed\n");
+    unbind(2024);
+    return 0;
+  }
+
+  uint32 src;
+  uint16 sport;
+  int cc = recvtimeout(2024, &src, &sport, ibuf, sizeof(ibuf), 50);
+  unbind(2024);
+  if(cc != sizeof(buf) || memcmp(buf, ibuf, sizeof(buf)) != 0){
+    printf("pingbig: got %d bytes back, expecting %ld\n", cc, sizeof(buf));
+    return 0;
+  }
+
+  printf("pingbig: OK\n");
+
+  return 1;
+}
+
+//
+// after connect(), a port should only get packets from the
+// connected peer; connect(port, 0, 0) should undo that.
+// doesn't need nettest.py.
+//
+int
+connecttest()
+{
+  char ibuf[16];
+  uint32 src;
+  uint16 sport;
+  int ok = 1;
+
+  printf("connect: starting\n");
+
+  if(connect(2025, 0, 0) != -NET_ENOTBOUND){
+    printf("connect: connect() of an unbound port didn't fail\n");
+    return 0;
+  }
+
+  bind(2025);
+  uint32 dst = 0x0A00020F; // 10.0.2.15, ourselves
+
+  // another process can't connect our binding.
+  int pid = fork();
+  if(pid == 0)
+    exit(connect(2025, dst, 2027) == -NET_ENOTBOUND ? 0 : 1);
+  int xst;
+  wait(&xst);
+  if(xst != 0){
+    printf("connect: a child connected its parent's port\n");
+    ok = 0;
+  }
+
+  if(connect(2025, dst, 2026) != 0){
+    printf("connect: connect() failed\n");
+    ok = 0;
+  }
+
+  send(2027, dst, 2025, "other", 5);
+  send(2026, dst, 2025, "peer", 4);
+  int cc = recvtimeout(2025, &src, &sport, ibuf, sizeof(ibuf), 5);
+  if(cc != 4 || sport != 2026){
+    printf("connect: got %d bytes from port %d, expecting the peer's\n", cc, sport);
+    ok = 0;
+  }
+  if((cc = recvtimeout(2025, &src, &sport, ibuf, sizeof(ibuf), 5)) != -NET_ETIMEDOUT){
+    printf("connect: other port's packet wasn't filtered (%d)\n", cc);
+    ok = 0;
+  }
+
+  connect(2025, 0, 0);
+  send(2027, dst, 2025, "other", 5);
+  if((cc = recvtimeout(2025, &src, &sport, ibuf, sizeof(ibuf), 5)) != 5 || sport != 2027){
+    printf("connect: disconnected port didn't get a packet (%d)\n", cc);
+    ok = 0;
+  }
+
+  unbind(2025);
+
+  if(ok)
+    printf("connect: OK\n");
+
+  return ok;
+}
+
+//
+// on a shared port, a binding connected to a peer should get all
+// of that peer's packets, and an unconnected binding the rest.
+// doesn't need nettest.py.
+//
+int
+connectshare()
+{
+  char ibuf[16];
+  uint32 src;
+  uint16 sport;
+  int fds[2];
+  char c;
+  int n = 4;
+  int ok = 1;
+
+  printf("connectshare: starting\n");
+
+  if(pipe(fds) < 0){
+    printf("connectshare: pipe() failed\n");
+    return 0;
+  }
+
+  if(bindflags(2033, BIND_REUSEPORT) != 2033){
+    printf("connectshare: bindflags() failed\n");
+    return 0;
+  }
+
+  uint32 dst = 0x0A00020F; // 10.0.2.15, ourselves
+  int pid = fork();
+  if(pid < 0){
+    printf("connectshare: fork() failed\n");
+    return 0;
+  }
+  if(pid == 0){
+    if(bindflags(2033, BIND_REUSEPORT) != 2033 || connect(2033, dst, 2034) != 0){
+      printf("connectshare: child bind or connect failed\n");
+      exit(1);
+    }
+    write(fds[1], "x", 1);
+    int xst = 0;
+    for(int i = 0; i < n; i++){
+      int cc = recvtimeout(2033, &src, &sport, ibuf, sizeof(ibuf), 10);
+      if(cc != 4 || sport != 2034){
+        printf("connectshare: child got %d bytes from port %d, expecting the peer's\n", cc, sport);
+        xst = 1;
+        break;
+      }
+    }
+    unbind(2033);
+    exit(xst);
+  }
+
+  read(fds[0], &c, 1);
+  close(fds[0]);
+  close(fds[1]);
+
+  for(int i = 0; i < n; i++){
+    send(2034, dst, 2033, "peer", 4);
+    send(2035, dst, 2033, "other", 5);
+  }
+  for(int i = 0; i < n; i++){
+    int cc = recvtimeout(2033, &src, &sport, ibuf, sizeof(ibuf), 10);
+    if(cc != 5 || sport != 2035){
+      printf("connectshare: parent got %d bytes from port %d, expecting the other's\n", cc, sport);
+      ok = 0;
+      break;
+    }
+  }
+
+  int xst;
+  wait(&xst);
+  if(xst != 0)
+    ok = 0;
+  unbind(2033);
+
+  if(ok)
+    printf("connectshare: OK\n");
+
+  return ok;
+}
+
+//
+// recvmmsg() with RECV_PEEK should return queued packets in
+// order without removing them, even after the ring has wrapped.
+//
+int
+recvpeektest()
+{
+  char bufs[10][8];
+  struct netmsg msgs[10];
+  int ok = 1;
+
+  printf("recvpeek: starting\n");
+
+  bind(2028);
+  uint32 dst = 0x0A00020F; // 10.0.2.15, ourselves
+
+  // move the ring's head away from slot 0.
+  for(int i = 0; i < 10; i++)
+    send(2029, dst, 2028, "x", 1);
+  for(int i = 0; i < 10; i++){
+    uint32 src;
+    uint16 sport;
+    if(recvtimeout(2028, &src, &sport, bufs[0], sizeof(bufs[0]), 5) != 1){
+      printf("recvpeek: initial recv() failed\n");
+      unbind(2028);
+      return 0;
+    }
+  }
+
+  for(int i = 0; i < 10; i++){
+    char c = 'a' + i;
+    send(2029, dst, 2028, &c, 1);
+  }
+
+  for(int pass = 0; pass < 2; pass++){
+    for(int i = 0; i < 10; i++){
+      memset(&msgs[i], 0, sizeof(msgs[i]));
+      msgs[i].buf = bufs[i];
+      msgs[i].maxlen = sizeof(bufs[i]);
+    }
+    int n = recvmmsg(2028, msgs, 10, pass == 0 ? RECV_PEEK : 0);
+    if(n != 10){
+      printf("recvpeek: pass %d got %d packets, expecting 10\n", pass, n);
+      ok = 0;
+      break;
+    }
+    for(int i = 0; i < 10; i++){
+      if(msgs[i].len != 1 || bufs[i][0] != 'a' + i || msgs[i].sport != 2029){
+        printf("recvpeek: pass %d packet %d out of order\n", pass, i);
+        ok = 0;
+        break;
+      }
+    }
+  }
+
+  unbind(2028);
+
+  if(ok)
+    printf("recvpeek: OK\n");
+
+  return ok;
+}
+
+//
+// a broadcast should reach every binding of a shared port that
+// asked for broadcasts, the sender's own included; a multicast should reach just those that
+// joined its group.
+//
+int
+broadcasttest()
+{
+  char ibuf[16];
+  uint32 src;
+  uint16 sport;
+  int fds[2];
+  char c;
+  int ok = 1;
+
+  printf("broadcast: starting\n");
+
+  if(pipe(fds) < 0){
+    printf("broadcast: pipe() failed\n");
+    return 0;
+  }
+
+  if(bindflags(2030, BIND_REUSEPORT | BIND_BROADCAST) != 2030){
+    printf("broadcast: bindflags() failed\n");
+    return 0;
+  }
+
+  int pid = fork();
+  if(pid < 0){
+    printf("broadcast: fork() failed\n");
+    return 0;
+  }
+  if(pid == 0){
+    if(bindflags(2030, BIND_REUSEPORT | BIND_BROADCAST) != 2030){
+      printf("broadcast: second bindflags() failed\n");
+      exit(1);
+    }
+    write(fds[1], "x", 1);
+    int cc = recvtimeout(2030, &src, &sport, ibuf, sizeof(ibuf), 10);
+    unbind(2030);
+    if(cc != 5 || sport != 2031){
+      printf("broadcast: child got %d, expecting the broadcast\n", cc);
+      exit(1);
+    }
+    exit(0);
+  }
+
+  read(fds[0], &c, 1);
+  close(fds[0]);
+  close(fds[1]);
+
+  if(send(2031, 0xffffffff, 2030, "bcast", 5) < 0){
+    printf("broadcast: send() failed\n");
+    ok = 0;
+  }
+  int cc = recvtimeout(2030, &src, &sport, ibuf, sizeof(ibuf), 10);
+  if(cc != 5 || sport != 2031){
+    printf("broadcast: parent got %d, expecting the broadcast\n", cc);
+    ok = 0;
+  }
+
+  int xst;
+  wait(&xst);
+  if(xst != 0)
+    ok = 0;
+  unbind(2030);
+
+  // without BIND_BROADCAST, only unicasts arrive.
+  bind(2030);
+  send(2031, 0xffffffff, 2030, "bcast", 5);
+  send(2031, 0x0A0002FF, 2030, "bcast", 5); // 10.0.2.255, our network's
+  if((cc = recvtimeout(2030, &src, &sport, ibuf, sizeof(ibuf), 5)) != -NET_ETIMEDOUT){
+    printf("broadcast: got a broadcast without BIND_BROADCAST (%d)\n", cc);
+    ok = 0;
+  }
+  unbind(2030);
+
+  uint32 group = 0xE00000FB; // 224.0.0.251
+  bind(2032);
+  send(2031, group, 2032, "mcast", 5);
+  if((cc = recvtimeout(2032, &src, &sport, ibuf, sizeof(ibuf), 5)) != -NET_ETIMEDOUT){
+    printf("broadcast: got a multicast without joining (%d)\n", cc);
+    ok = 0;
+  }
+  if(joinmulticast(2032, 0x0A00020F) != -NET_EINVAL){
+    printf("broadcast: joined a unicast address\n");
+    ok = 0;
+  }
+  // another process can't put our binding in a group.
+  if((pid = fork()) == 0)
+    exit(joinmulticast(2032, group) == -NET_ENOTBOUND ? 0 : 1);
+  wait(&xst);
+  if(xst != 0){
+    printf("broadcast: a child joined its parent's port to a group\n");
+    ok = 0;
+  }
+  if(joinmulticast(2032, group) != 0){
+    printf("broadcast: joinmulticast() failed\n");
+    ok = 0;
+  }
+  send(2031, group, 2032, "mcast", 5);
+  if((cc = recvtimeout(2032, &src, &sport, ibuf, sizeof(ibuf), 5)) != 5){
+    printf("broadcast: didn't get the multicast after joining (%d)\n", cc);
+    ok = 0;
+  }
+  unbind(2032);
+
+  if(ok)
+    printf("broadcast: OK\n");
+
+  return ok;
+}
+
+//
+// bind ports until the kernel runs out of queues; that should be
+// an error, not a panic, and unbinding one should free it up.
+//
+int
+bindexhaust()
+{
+  int first = 3000, last;
+  int ok = 1;
+
+  printf("bindexhaust: starting\n");
+
+  int r = 0;
+  for(last = first; last < first + 1000; last++){
+    if((r = bind(last)) != last)
+      break;
+  }
+  if(r != -NET_ENOMEM){
+    printf("bindexhaust: bind() returned %d, expecting -NET_ENOMEM\n", r);
+    ok = 0;
+  }
+
+  if(last > first){
+    unbind(first);
+    if(bind(last) != last){
+      printf("bindexhaust: unbind() didn't free a queue\n");
+      ok = 0;
+    } else {
+      unbind(last);
+    }
+  }
+  for(int port = first + 1; port < last; port++)
+    unbind(port);
+
+  if(ok)
+    printf("bindexhaust: OK\n");
+
+  return ok;
+}
+
+//
+// with UDP checksums turned off, datagrams should still get
+// through; udpcksum() should report the previous setting.
+//
+int
+udpcksumtest()
+{
+  char ibuf[16];
+  uint32 src;
+  uint16 sport;
+  int ok = 1;
+
+  printf("udpcksum: starting\n");
+
+  if(udpcksum(2) != -NET_EINVAL){
+    printf("udpcksum: udpcksum(2) didn't fail\n");
+    ok = 0;
+  }
+  int old = udpcksum(0);
+  if(old != 1){
+    printf("udpcksum: checksums were %d, expecting on\n", old);
+    ok = 0;
+  }
+
+  bind(2033);
+  uint32 dst = 0x0A00020F; // 10.0.2.15, ourselves
+  send(2034, dst, 2033, "nosum", 5);
+  int cc = recvtimeout(2033, &src, &sport, ibuf, sizeof(ibuf), 5);
+  if(cc != 5 || memcmp(ibuf, "nosum", 5) != 0){
+    printf("udpcksum: got %d bytes without checksums\n", cc);
+    ok = 0;
+  }
+
+  struct portstats st;
+  if(portstats(2033, &st) != 0 || st.badsum != 0){
+    printf("udpcksum: port counted a bad checksum\n");
+    ok = 0;
+  }
+  unbind(2033);
+
+  if(udpcksum(old) != 0){
+    printf("udpcksum: udpcksum(0) didn't stick\n");
+    ok = 0;
+  }
+
+  if(ok)
+    printf("udpcksum: OK\n");
+
+  return ok;
+}
+
+//
+// recvmmsg() with RECV_DONTWAIT shouldn't sleep on an
+// empty queue, and should still receive what's queued.
+//
+int
+dontwaittest()
+{
+  char buf[16];
+  struct netmsg msg;
+  int ok = 1;
+
+  printf("dontwait: starting\n");
+
+  bind(2035);
+  memset(&msg, 0, sizeof(msg));
+  msg.buf = buf;
+  msg.maxlen = sizeof(buf);
+
+  int r = recvmmsg(2035, &msg, 1, RECV_DONTWAIT);
+  if(r != -NET_EAGAIN){
+    printf("dontwait: empty queue returned %d, expecting -NET_EAGAIN\n", r);
+    ok = 0;
+  }
+
+  uint32 dst = 0x0A00020F; // 10.0.2.15, ourselves
+  send(2036, dst, 2035, "now", 3);
+  if((r = recvmmsg(2035, &msg, 1, RECV_DONTWAIT)) != 1 || msg.len != 3){
+    printf("dontwait: queued packet returned %d\n", r);
+    ok = 0;
+  }
+  unbind(2035);
+
+  if(ok)
+    printf("dontwait: OK\n");
+
+  return ok;
+}
+
+//
+// netpoll() should time out while no port has a packet, report
+// which ports do, and wake up when a packet arrives for one.
+//
+int
+netpolltest()
+{
+  uint16 ports[2] = { 2037, 2038 };
+  uint32 dst = 0x0A00020F; // 10.0.2.15, ourselves
+  char ibuf[16];
+  uint32 src;
+  uint16 sport;
+  int ok = 1;
+
+  printf("netpoll: starting\n");
+
+  bind(2037);
+  bind(2038);
+
+  int r = netpoll(ports, 2, 3);
+  if(r != -NET_ETIMEDOUT){
+    printf("netpoll: idle ports returned %d\n", r);
+    ok = 0;
+  }
+
+  send(2039, dst, 2038, "b", 1);
+  if((r = netpoll(ports, 2, 3)) != 0x2){
+    printf("netpoll: returned %x, expecting 0x2\n", r);
+    ok = 0;
+  }
+  recv(2038, &src, &sport, ibuf, sizeof(ibuf));
+
+  int pid = fork();
+  if(pid < 0){
+    printf("netpoll: fork() failed\n");
+    ok = 0;
+  } else if(pid == 0){
+    sleep(5);
+    send(2039, dst, 2037, "a", 1);
+    exit(0);
+  } else {
+    if((r = netpoll(ports, 2, -1)) != 0x1){
+      printf("netpoll: woke with %x, expecting 0x1\n", r);
+      ok = 0;
+    }
+    wait(0);
+  }
+
+  unbind(2037);
+  unbind(2038);
+
+  if(ok)
+    printf("netpoll: OK\n");
+
+  return ok;
+}
+
+//
+// a port given a longer queue with queuelen() should hold more
+// than the default 16 packets, in order, and keep them across
+// another resize.
+//
+int
+queuelentest()
+{
+  struct portstats st;
+  uint32 dst = 0x0A00020F; // 10.0.2.15, ourselves
+  int n = 60;
+  int ok = 1;
+
+  printf("queuelen: starting\n");
+
+  bind(2040);
+  if(queuelen(2040, 0) != -NET_EINVAL || queuelen(2040, 100000) != -NET_EINVAL){
+    printf("queuelen: bad lengths didn't fail\n");
+    ok = 0;
+  }
+  if(queuelen(2040, 100) != 0){
+    printf("queuelen: queuelen() failed\n");
+    ok = 0;
+  }
+
+  // another process can't resize our queue.
+  int pid = fork();
+  if(pid == 0)
+    exit(queuelen(2040, 1) == -NET_ENOTBOUND ? 0 : 1);
+  int xst;
+  wait(&xst);
+  if(xst != 0){
+    printf("queuelen: a child resized its parent's queue\n");
+    ok = 0;
+  }
+
+  for(int i = 0; i < n; i++){
+    char c = i;
+    send(2041, dst, 2040, &c, 1);
+  }
+  if(portstats(2040, &st) != 0 || st.received != n || st.dropped != 0){
+    printf("queuelen: received %d dropped %d\n", (int)st.received, (int)st.dropped);
+    ok = 0;
+  }
+
+  if(queuelen(2040, n - 1) != -NET_EINVAL){
+    printf("queuelen: shrank below what's queued\n");
+    ok = 0;
+  }
+  if(queuelen(2040, n) != 0){
+    printf("queuelen: resize with packets queued failed\n");
+    ok = 0;
+  }
+
+  for(int i = 0; i < n; i++){
+    char c;
+    uint32 src;
+    uint16 sport;
+    if(recv(2040, &src, &sport, &c, 1) != 1 || c != (char)i){
+      printf("queuelen: packet %d out of order\n", i);
+      ok = 0;
+      break;
+    }
+  }
+  unbind(2040);
+
+  if(ok)
+    printf("queuelen: OK\n");
+
+  return ok;
+}
+
+//
+// a socket's file descriptor should send and receive with write()
+// and read(), and closing it, or exiting, should unbind its port.
+// doesn't need nettest.py.
+//
+int
+socktest()
+{
+  char ibuf[16];
+  int ok = 1;
+
+  printf("socket: starting\n");
+
+  int fd = socket(2037);
+  if(fd < 0){
+    printf("socket: socket() failed (%d)\n", fd);
+    return 0;
+  }
+  if(write(fd, "sock", 4) != -NET_ENOROUTE){
+    printf("socket: write() before connect() didn't fail\n");
+    ok = 0;
+  }
+  uint32 dst = 0x0A00020F; // 10.0.2.15, ourselves
+  connect(2037, dst, 2037);
+  if(write(fd, "sock", 4) != 4){
+    printf("socket: write() failed\n");
+    ok = 0;
+  }
+  int cc = read(fd, ibuf, sizeof(ibuf));
+  if(cc != 4 || memcmp(ibuf, "sock", 4) != 0){
+    printf("socket: read() got %d bytes\n", cc);
+    ok = 0;
+  }
+  if(unbind(2037) != -NET_EINVAL){
+    printf("socket: unbind() of a socket's port didn't fail\n");
+    ok = 0;
+  }
+  close(fd);
+  if(bind(2037) != 2037){
+    printf("socket: close() didn't unbind the port\n");
+    ok = 0;
+  }
+  unbind(2037);
+
+  int pid = fork();
+  if(pid == 0){
+    if(socket(2038) < 0)
+      exit(1);
+    exit(0); // without closing it
+  }
+  int xst;
+  wait(&xst);
+  if(xst != 0 || bind(2038) != 2038){
+    printf("socket: exit didn't unbind the child's socket\n");
+    ok = 0;
+  }
+  unbind(2038);
+
+  if(ok)
+    printf("socket: OK\n");
+
+  return ok;
+}
+
+//
+// a process that exits with a port bound, and packets queued on
+// it, should have the port unbound and the packets freed.
+// doesn't need nettest.py.
+//
+int
+exitunbind()
+{
+  struct portstats st;
+  int up[2], down[2];
+  char c;
+  int ok = 1;
+
+  printf("exitunbind: starting\n");
+
+  if(pipe(up) < 0 || pipe(down) < 0){
+    printf("exitunbind: pipe() failed\n");
+    return 0;
+  }
+
+  int pid = fork();
+  if(pid == 0){
+    if(bind(2039) != 2039)
+      exit(1);
+    write(up[1], "x", 1);
+    read(down[0], &c, 1);
+    if(portstats(2039, &st) != 0 || st.depth != 3)
+      exit(1);
+    exit(0); // without unbinding
+  }
+
+  read(up[0], &c, 1);
+  uint32 dst = 0x0A00020F; // 10.0.2.15, ourselves
+  for(int i = 0; i < 3; i++)
+    send(2040, dst, 2039, "q", 1);
+  write(down[1], "x", 1);
+
+  int xst;
+  wait(&xst);
+  if(xst != 0){
+    printf("exitunbind: child didn't see its packets queued\n");
+    ok = 0;
+  }
+  if(portstats(2039, &st) != -NET_ENOTBOUND || bind(2039) != 2039){
+    printf("exitunbind: port still bound after its process exited\n");
+    ok = 0;
+  }
+  unbind(2039);
+  close(up[0]);
+  close(up[1]);
+  close(down[0]);
+  close(down[1]);
+
+  if(ok)
+    printf("exitunbind: OK\n");
+
+  return ok;
+}
+
+//
+// sendto() and recvfrom() should carry addresses in a
+// struct sockaddr_in. doesn't need nettest.py.
+//
+int
+sendtotest()
+{
+  char ibuf[16];
+  struct sockaddr_in to, from;
+  int ok = 1;
+
+  printf("sendto: starting\n");
+
+  bind(2041);
+  to.family = AF_INET;
+  to.addr = 0x0A00020F; // 10.0.2.15, ourselves
+  to.port = 2041;
+  if(sendto(2042, "addr", 4, &to) != 4){
+    printf("sendto: sendto() failed\n");
+    ok = 0;
+  }
+  memset(&from, 0, sizeof(from));
+  int cc = recvfrom(2041, ibuf, sizeof(ibuf), &from);
+  if(cc != 4 || memcmp(ibuf, "addr", 4) != 0){
+    printf("sendto: recvfrom() got %d bytes\n", cc);
+    ok = 0;
+  }
+  if(from.family != AF_INET || from.addr != to.addr || from.port != 2042){
+    printf("sendto: wrong source %x:%d\n", from.addr, from.port);
+    ok = 0;
+  }
+
+  to.family = AF_INET + 1;
+  if(sendto(2042, "addr", 4, &to) != -NET_EPROTO){
+    printf("sendto: sendto() of another family didn't fail\n");
+    ok = 0;
+  }
+  unbind(2041);
+
+  if(ok)
+    printf("sendto: OK\n");
+
+  return ok;
+}
+
+//
+// netconf() should report the configuration and change it,
+// refusing bad settings. doesn't need nettest.py.
+//
+int
+netconftest()
+{
+  struct netconf c, old, bad;
+  int ok = 1;
+
+  printf("netconf: starting\n");
+
+  if(netconf(&old, 0) != 0){
+    printf("netconf: netconf() failed\n");
+    return 0;
+  }
+  if(old.ip == 0 || old.mtu != 1500 || old.mac[0] != 0x52){
+    printf("netconf: unexpected configuration, mtu %d\n", old.mtu);
+    ok = 0;
+  }
+
+  c = old;
+  c.mtu = 1400;
+  if(netconf(0, &c) != 0 || netconf(&c, 0) != 0 || c.mtu != 1400){
+    printf("netconf: setting the mtu didn't stick\n");
+    ok = 0;
+  }
+
+  bad = old;
+  bad.mask = 0xff00ff00;
+  if(netconf(0, &bad) != -NET_EINVAL){
+    printf("netconf: accepted a non-contiguous netmask\n");
+    ok = 0;
+  }
+  bad = old;
+  bad.mtu = 100;
+  if(netconf(0, &bad) != -NET_EINVAL){
+    printf("netconf: accepted a tiny mtu\n");
+    ok = 0;
+  }
+  bad = old;
+  bad.ip = 0xE0000001; // 224.0.0.1
+  if(netconf(0, &bad) != -NET_EINVAL){
+    printf("netconf: accepted a multicast address\n");
+    ok = 0;
+  }
+  bad = old;
+  bad.gateway = 0x0A000102; // 10.0.1.2, another subnet
+  if(netconf(0, &bad) != -NET_EINVAL){
+    printf("netconf: accepted a gateway outside the subnet\n");
+    ok = 0;
+  }
+
+  // only a process with network privilege can change it.
+  int pid = fork();
+  if(pid == 0){
+    dropnetpriv();
+    exit(netconf(0, &old) == -NET_EPERM && netconf(&c, 0) == 0 &&
+         loglevel(0) == -NET_EPERM ? 0 : 1);
+  }
+  int xst;
+  wait(&xst);
+  if(xst != 0){
+    printf("netconf: an unprivileged process changed the configuration or log level\n");
+    ok = 0;
+  }
+
+  // the swap returns what was there before.
+  if(netconf(&c, &old) != 0 || c.mtu != 1400){
+    printf("netconf: couldn't restore the configuration\n");
+    ok = 0;
+  }
+
+  if(ok)
+    printf("netconf: OK\n");
+
+  return ok;
+}
+
+//
+// a TCP connection over loopback should carry more than a
+// buffer's worth of bytes each way in order, and end with a
+// clean close. doesn't need nettest.py.
+//
+int
+tcptest()
+{
+  static char buf[8000];
+  char c;
+  int ok = 1;
+
+  printf("tcp: starting\n");
+
+  uint32 self = 0x0A00020F; // 10.0.2.15, ourselves
+  int r;
+  if((r = tcpconnect(self, 2044)) != -NET_ECONNREFUSED){
+    printf("tcp: tcpconnect() to a closed port returned %d\n", r);
+    ok = 0;
+  }
+
+  int lfd = tcplisten(2043);
+  if(lfd < 0){
+    printf("tcp: tcplisten() failed (%d)\n", lfd);
+    return 0;
+  }
+  if((r = tcplisten(2043)) != -NET_EADDRINUSE){
+    printf("tcp: second tcplisten() of a port returned %d\n", r);
+    ok = 0;
+  }
+
+  int pid = fork();
+  if(pid == 0){
+    close(lfd);
+    int fd = tcpconnect(self, 2043);
+    if(fd < 0)
+      exit(1);
+    for(int i = 0; i < sizeof(buf); i++)
+      buf[i] = i % 251;
+    if(write(fd, buf, sizeof(buf)) != sizeof(buf))
+      exit(2);
+    if(read(fd, &c, 1) != 1 || c != 'k')
+      exit(3);
+    if(read(fd, &c, 1) != 0)
+      exit(4);
+    close(fd);
+    exit(0);
+  }
+
+  int fd = tcpaccept(lfd);
+  if(fd < 0){
+    printf("tcp: tcpaccept() failed (%d)\n", fd);
+    kill(pid);
+    wait(0);
+    close(lfd);
+    return 0;
+  }
+  int n = 0;
+  while(n < sizeof(buf)){
+    int cc = read(fd, buf + n, sizeof(buf) - n);
+    if(cc <= 0)
+      break;
+    n += cc;
+  }
+  for(int i = 0; i < n; i++){
+    if(buf[i] != (char)(i % 251)){
+      printf("tcp: byte %d is wrong\n", i);
+      ok = 0;
+      break;
+    }
+  }
+  if(n != sizeof(buf)){
+    printf("tcp: read %d of %d bytes\n", n, (int)sizeof(buf));
+    ok = 0;
+  }
+  write(fd, "k", 1);
+  close(fd);
+
+  int xst;
+  wait(&xst);
+  if(xst != 0){
+    printf("tcp: client failed at step %d\n", xst);
+    ok = 0;
+  }
+  close(lfd);
+
+  if(ok)
+    printf("tcp: OK\n");
+
+  return ok;
+}
+
+//
+// a raw socket should send its bytes as the payload of an IP
+// packet and read packets back whole, and be refused to a
+// process that has dropped its network privilege, and to its
+// children. uses 253, a protocol number for experiments.
+// doesn't need nettest.py.
+//
+int
+rawtest()
+{
+  char ibuf[64];
+  int ok = 1;
+
+  printf("raw: starting\n");
+
+  uint32 self = 0x0A00020F; // 10.0.2.15, ourselves
+  int fd = rawsocket(253, self);
+  if(fd < 0){
+    printf("raw: rawsocket() failed (%d)\n", fd);
+    return 0;
+  }
+  if(write(fd, "raw", 3) != 3){
+    printf("raw: write() failed\n");
+    ok = 0;
+  }
+  int cc = read(fd, ibuf, sizeof(ibuf));
+  struct ip *ip = (struct ip *)ibuf;
+  if(cc != sizeof(struct ip) + 3 || ip->ip_vhl != 0x45 || ip->ip_p != 253 ||
+     memcmp(ibuf + sizeof(struct ip), "raw", 3) != 0){
+    printf("raw: read() got %d bytes, not the packet sent\n", cc);
+    ok = 0;
+  }
+  close(fd);
+
+  int pid = fork();
+  if(pid == 0){
+    dropnetpriv();
+    if(rawsocket(253, self) != -NET_EPERM)
+      exit(1);
+    if(fork() == 0)
+      exit(rawsocket(253, self) == -NET_EPERM ? 0 : 1);
+    int xst;
+    wait(&xst);
+    exit(xst);
+  }
+  int xst;
+  wait(&xst);
+  if(xst != 0){
+    printf("raw: an unprivileged process opened a raw socket\n");
+    ok = 0;
+  }
+
+  if(ok)
+    printf("raw: OK\n");
+
+  return ok;
+}
+
+//
+// pcapture() should record a looped-back datagram, and refuse
+// a process without network privilege. doesn't need nettest.py.
+//
+int
+pcapturetest()
+{
+  static struct pcaprec recs[8];
+  int ok = 1;
+
+  printf("pcapture: starting\n");
+
+  int r;
+  if((r = pcapture(PCAP_START, 0, 0)) != 0){
+    printf("pcapture: PCAP_START failed (%d)\n", r);
+    return 0;
+  }
+  uint32 dst = 0x0A00020F; // 10.0.2.15, ourselves
+  bind(2045);
+  send(2046, dst, 2045, "cap", 3);
+  pcapture(PCAP_STOP, 0, 0);
+
+  // once stopped, PCAP_READ returns what's left, then 0.
+  int found = 0, n;
+  while((n = pcapture(PCAP_READ, recs, 8)) > 0){
+    for(int i = 0; i < n; i++){
+      struct eth *eth = (struct eth *)recs[i].data;
+      struct ip *ip = (struct ip *)(eth + 1);
+      struct udp *udp = (struct udp *)(ip + 1);
+      if(recs[i].dir == PCAP_LOOP && ntohs(eth->type) == ETHTYPE_IP &&
+         ip->ip_p == IPPROTO_UDP && ntohs(udp->dport) == 2045 &&
+         recs[i].len == sizeof(*eth) + sizeof(*ip) + sizeof(*udp) + 3 &&
+         recs[i].caplen == recs[i].len && memcmp(udp + 1, "cap", 3) == 0)
+        found = 1;
+    }
+  }
+  if(n != 0 || !found){
+    printf("pcapture: the datagram wasn't captured (%d)\n", n);
+    ok = 0;
+  }
+  unbind(2045);
+
+  int pid = fork();
+  if(pid == 0){
+    dropnetpriv();
+    exit(pcapture(PCAP_START, 0, 0) == -NET_EPERM ? 0 : 1);
+  }
+  int xst;
+  wait(&xst);
+  if(xst != 0){
+    printf("pcapture: an unprivileged process started a capture\n");
+    ok = 0;
+  }
+
+  if(ok)
+    printf("pcapture: OK\n");
+
+  return ok;
+}
+
+//
+// a socket's filter should keep out the datagrams it doesn't
+// accept, a filter on the capture should narrow it down, and
+// a program that jumps outside itself should be refused.
+// doesn't need nettest.py.
+//
+int
+filtertest()
+{
+  static struct pcaprec recs[8];
+  char ibuf[8];
+  int ok = 1;
+
+  printf("filter: starting\n");
+
+  // accept datagrams from port 2048, and nothing else.
+  struct pf_insn prog[] = {
+    { PF_SPORT, 0, 1, 0xffff, 2048 },
+    { PF_RET, 0, 0, 0, 1 },
+    { PF_RET, 0, 0, 0, 0 },
+  };
+  struct pf_insn bad[] = {
+    { PF_SPORT, 0, 5, 0xffff, 2048 },
+    { PF_RET, 0, 0, 0, 1 },
+  };
+
+  int fd = socket(2047);
+  if(fd < 0){
+    printf("filter: socket() failed (%d)\n", fd);
+    return 0;
+  }
+  if(setfilter(fd, bad, 2) != -NET_EINVAL){
+    printf("filter: setfilter() took a program that jumps out of itself\n");
+    ok = 0;
+  }
+  if(setfilter(fd, prog, 3) != 0){
+    printf("filter: setfilter() failed\n");
+    ok = 0;
+  }
+  uint32 dst = 0x0A00020F; // 10.0.2.15, ourselves
+  send(2049, dst, 2047, "no", 2);
+  send(2048, dst, 2047, "yes", 3);
+  int cc = read(fd, ibuf, sizeof(ibuf));
+  if(cc != 3 || memcmp(ibuf, "yes", 3) != 0){
+    printf("filter: the socket's filter let the wrong datagram in\n");
+    ok = 0;
+  }
+  close(fd);
+
+  // capture only what goes to port 2047.
+  prog[0].op = PF_DPORT;
+  prog[0].k = 2047;
+  if(setfilter(PF_CAPTURE, prog, 3) != 0 || pcapture(PCAP_START, 0, 0) != 0){
+    printf("filter: couldn't start a filtered capture\n");
+    return 0;
+  }
+  bind(2047);
+  send(2049, dst, 2047, "in", 2);
+  send(2049, dst, 2050, "out", 3);
+  pcapture(PCAP_STOP, 0, 0);
+  setfilter(PF_CAPTURE, 0, 0);
+  unbind(2047);
+  int n, total = 0;
+  while((n = pcapture(PCAP_READ, recs, 8)) > 0){
+    for(int i = 0; i < n; i++){
+      struct ip *ip = (struct ip *)(recs[i].data + sizeof(struct eth));
+      struct udp *udp = (struct udp *)(ip + 1);
+      if(ip->ip_p != IPPROTO_UDP || ntohs(udp->dport) != 2047){
+        printf("filter: captured a frame the filter should have dropped\n");
+        ok = 0;
+      }
+    }
+    total += n;
+  }
+  if(total != 1){
+    printf("filter: captured %d frames, not 1\n", total);
+    ok = 0;
+  }
+
+  if(ok)
+    printf("filter: OK\n");
+
+  return ok;
+}
+
+//
+// netstat() should list a bound UDP port with what's queued
+// on it, and a TCP listener and both ends of a connection to
+// it, in the right states. doesn't need nettest.py.
+//
+int
+netstattest()
+{
+  static struct netstat ns[64];
+  int ok = 1;
+
+  printf("netstat: starting\n");
+
+  uint32 self = 0x0A00020F; // 10.0.2.15, ourselves
+  if(bind(2051) != 2051){
+    printf("netstat: bind() failed\n");
+    return 0;
+  }
+  send(2052, self, 2051, "a", 1);
+  send(2052, self, 2051, "b", 1);
+
+  int lfd = tcplisten(2053);
+  if(lfd < 0){
+    printf("netstat: tcplisten() failed (%d)\n", lfd);
+    unbind(2051);
+    return 0;
+  }
+  int fd = tcpconnect(self, 2053);
+  if(fd < 0){
+    printf("netstat: tcpconnect() failed (%d)\n", fd);
+    close(lfd);
+    unbind(2051);
+    return 0;
+  }
+  write(fd, "hello", 5);
+
+  int n = netstat(ns, 64);
+  int udp = 0, listener = 0, client = 0, server = 0;
+  for(int i = 0; i < n; i++){
+    struct netstat *s = &ns[i];
+    if(s->proto == IPPROTO_UDP && s->lport == 2051)
+      udp = s->recvq == 2 && s->dropped == 0 && s->pid == getpid();
+    else if(s->proto == IPPROTO_TCP && s->lport == 2053 && s->state == TCP_LISTEN)
+      listener = 1;
+    else if(s->proto == IPPROTO_TCP && s->rport == 2053 && s->raddr == self)
+      client = s->state == TCP_ESTABLISHED;
+    else if(s->proto == IPPROTO_TCP && s->lport == 2053)
+      server = s->state == TCP_ESTABLISHED && s->recvq == 5;
+  }
+  if(n < 4 || !udp || !listener || !client || !server){
+    printf("netstat: %d lines; udp %d listener %d client %d server %d\n",
+           n, udp, listener, client, server);
+    ok = 0;
+  }
+  if(netstat(ns, 1) != 1){
+    printf("netstat: didn't stop at the lines asked for\n");
+    ok = 0;
+  }
+
+  close(fd);
+  close(lfd);
+  unbind(2051);
+
+  if(ok)
+    printf("netstat: OK\n");
+
+  return ok;
+}
+
+// is there a TCP connection to port on ourselves?
+int
+tcpto(uint32 self, int port)
+{
+  static struct netstat ns[64];
+
+  int n = netstat(ns, 64);
+  for(int i = 0; i < n; i++)
+    if(ns[i].proto == IPPROTO_TCP && ns[i].raddr == self && ns[i].rport == port)
+      return 1;
+  return 0;
+}
+
+//
+// a closed connection whose peer never sends its FIN should go
+// away on its own, and data sent to it should be answered with
+// a reset.
+// doesn't need nettest.py.
+//
+int
+finwait()
+{
+  char buf[8];
+  int ok = 1;
+
+  printf("finwait: starting\n");
+
+  uint32 self = 0x0A00020F; // 10.0.2.15, ourselves
+  int lfd = tcplisten(2055);
+  if(lfd < 0){
+    printf("finwait: tcplisten() failed (%d)\n", lfd);
+    return 0;
+  }
+
+  // the server writes after the client has closed.
+  int fd = tcpconnect(self, 2055);
+  int sfd = tcpaccept(lfd);
+  if(fd < 0 || sfd < 0){
+    printf("finwait: couldn't connect (%d, %d)\n", fd, sfd);
+    close(lfd);
+    return 0;
+  }
+  close(fd);
+  sleep(2);
+  write(sfd, "late", 4);
+  sleep(2);
+  int r = read(sfd, buf, sizeof(buf));
+  if(r != -NET_ECONNRESET || tcpto(self, 2055)){
+    printf("finwait: writing to a closed connection got %d\n", r);
+    ok = 0;
+  }
+  close(sfd);
+
+  // the server never writes or closes.
+  fd = tcpconnect(self, 2055);
+  sfd = tcpaccept(lfd);
+  if(fd < 0 || sfd < 0){
+    printf("finwait: couldn't connect again (%d, %d)\n", fd, sfd);
+    close(lfd);
+    return 0;
+  }
+  close(fd);
+  sleep(2);
+  if(!tcpto(self, 2055)){
+    printf("finwait: closed connection went away before its peer's FIN\n");
+    ok = 0;
+  }
+  sleep(30); // more than 2*TCP_MSL
+  if(tcpto(self, 2055)){
+    printf("finwait: closed connection lingered without its peer's FIN\n");
+    ok = 0;
+  }
+  close(sfd);
+  close(lfd);
+
+  if(ok)
+    printf("finwait: OK\n");
+
+  return ok;
+}
+
+
+
 void
 usage()
 {
   printf("Usage: nettest txone\n");
   printf("       nettest tx\n");
+  printf("       nettest txbig\n");
   printf("       nettest rx\n");
   printf("       nettest rx2\n");
   printf("       nettest rxburst\n");
   printf("       nettest ping1\n");
   printf("       nettest ping2\n");
   printf("       nettest ping3\n");
+  printf("       nettest recv0\n");
+  printf("       nettest recvmmsg\n");
   printf("       nettest dns\n");
+  printf("       nettest errors\n");
+  printf("       nettest loopback\n");
+  printf("       nettest resolve\n");
+  printf("       nettest unbind\n");
+  printf("       nettest ephemeral\n");
+  printf("       nettest autoport\n");
+  printf("       nettest reuseport\n");
+  printf("       nettest nicstats\n");
+  printf("       nettest linkstatus\n");
+  printf("       nettest recvtimeout\n");
+  printf("       nettest portstats\n");
+  printf("       nettest pingbig\n");
+  printf("       nettest connect\n");
+  printf("       nettest connectshare\n");
+  printf("       nettest recvpeek\n");
+  printf("       nettest broadcast\n");
+  printf("       nettest bindexhaust\n");
+  printf("       nettest udpcksum\n");
+  printf("       nettest dontwait\n");
+  printf("       nettest netpoll\n");
+  printf("       nettest queuelen\n");
+  printf("       nettest socket\n");
+  printf("       nettest exitunbind\n");
+  printf("       nettest sendto\n");
+  printf("       nettest netconf\n");
+  printf("       nettest tcp\n");
+  printf("       nettest raw\n");
+  printf("       nettest pcapture\n");
+  printf("       nettest filter\n");
+  printf("       nettest netstat\n");
+  printf("       nettest txmax\n");
+  printf("       nettest finwait\n");
   printf("       nettest grade\n");
   exit(1);
 }
@@ -910,6 +2981,8 @@ main(int argc, char *argv[])
     rx2();
   } else if(strcmp(argv[1], "tx") == 0){
     tx();
+  } else if(strcmp(argv[1], "txbig") == 0){
+    txbig();
   } else if(strcmp(argv[1], "ping0") == 0){
     ping0();
   } else if(strcmp(argv[1], "ping1") == 0){
@@ -918,6 +2991,10 @@ main(int argc, char *argv[])
     ping2();
   } else if(strcmp(argv[1], "ping3") == 0){
     ping3();
+  } else if(strcmp(argv[1], "recv0") == 0){
+    recv0();
+  } else if(strcmp(argv[1], "recvmmsg") == 0){
+    mmsg();
   } else if(strcmp(argv[1], "grade") == 0){
     //
     // "python3 nettest.py grade" must already be running...
@@ -943,6 +3020,70 @@ main(int argc, char *argv[])
     }
   } else if(strcmp(argv[1], "dns") == 0){
     dns();
+  } else if(strcmp(argv[1], "errors") == 0){
+    errors();
+  } else if(strcmp(argv[1], "loopback") == 0){
+    loopback();
+  } else if(strcmp(argv[1], "resolve") == 0){
+    resolvetest();
+  } else if(strcmp(argv[1], "unbind") == 0){
+    unbindtest();
+  } else if(strcmp(argv[1], "ephemeral") == 0){
+    ephemeral();
+  } else if(strcmp(argv[1], "autoport") == 0){
+    autoport();
+  } else if(strcmp(argv[1], "reuseport") == 0){
+    reuseport();
+  } else if(strcmp(argv[1], "nicstats") == 0){
+    nicstatstest();
+  } else if(strcmp(argv[1], "linkstatus") == 0){
+    linkstatustest();
+  } else if(strcmp(argv[1], "recvtimeout") == 0){
+    recvtimeouttest();
+  } else if(strcmp(argv[1], "portstats") == 0){
+    portstatstest();
+  } else if(strcmp(argv[1], "pingbig") == 0){
+    pingbig();
+  } else if(strcmp(argv[1], "connect") == 0){
+    connecttest();
+  } else if(strcmp(argv[1], "connectshare") == 0){
+    connectshare();
+  } else if(strcmp(argv[1], "recvpeek") == 0){
+    recvpeektest();
+  } else if(strcmp(argv[1], "broadcast") == 0){
+    broadcasttest();
+  } else if(strcmp(argv[1], "bindexhaust") == 0){
+    bindexhaust();
+  } else if(strcmp(argv[1], "udpcksum") == 0){
+    udpcksumtest();
+  } else if(strcmp(argv[1], "dontwait") == 0){
+    dontwaittest();
+  } else if(strcmp(argv[1], "netpoll") == 0){
+    netpolltest();
+  } else if(strcmp(argv[1], "queuelen") == 0){
+    queuelentest();
+  } else if(strcmp(argv[1], "socket") == 0){
+    socktest();
+  } else if(strcmp(argv[1], "exitunbind") == 0){
+    exitunbind();
+  } else if(strcmp(argv[1], "sendto") == 0){
+    sendtotest();
+  } else if(strcmp(argv[1], "netconf") == 0){
+    netconftest();
+  } else if(strcmp(argv[1], "tcp") == 0){
+    tcptest();
+  } else if(strcmp(argv[1], "raw") == 0){
+    rawtest();
+  } else if(strcmp(argv[1], "pcapture") == 0){
+    pcapturetest();
+  } else if(strcmp(argv[1], "filter") == 0){
+    filtertest();
+  } else if(strcmp(argv[1], "netstat") == 0){
+    netstattest();
+  } else if(strcmp(argv[1], "txmax") == 0){
+    txmax();
+  } else if(strcmp(argv[1], "finwait") == 0){
+    finwait();
   } else {
     usage();
   }
@@ -0,0 +1,170 @@
+//
+// print the frames going in and out of xv6, one line each.
+// tcpdump [-p port] [count]
+// stops after count frames, if given. with -p, shows only UDP
+// and TCP to or from port.
+//
+
+#include "kernel/types.h"
+#include "kernel/net.h"
+#include "kernel/stat.h"
+#include "user/user.h"
+
+void
+printip(uint32 ip)
+{
+  printf("%d.%d.%d.%d", ip >> 24, (ip >> 16) & 0xff, (ip >> 8) & 0xff, ip & 0xff);
+}
+
+// print usec as seconds, to the millisecond.
+void
+printtime(uint64 usec)
+{
+  int ms = (usec / 1000) % 1000;
+  printf("%d.%s%s%d", (int)(usec / 1000000), ms < 100 ? "0" : "", ms < 10 ? "0" : "", ms);
+}
+
+void
+printarp(struct arp *arp)
+{
+  if(ntohs(arp->op) == ARP_OP_REQUEST){
+    printf("ARP who-has ");
+    printip(ntohl(arp->tip));
+    printf(" tell ");
+    printip(ntohl(arp->sip));
+  } else {
+    printf("ARP reply ");
+    printip(ntohl(arp->sip));
+    uint8 *m = (uint8 *)arp->sha;
+    printf(" is-at %x:%x:%x:%x:%x:%x", m[0], m[1], m[2], m[3], m[4], m[5]);
+  }
+}
+
+// print what's in the IP packet at ip, of which there are
+// n bytes captured.
+void
+printipv4(struct ip *ip, int n)
+{
+  int ihl = (ip->ip_vhl & 0xf) * 4;
+  char *p = (char *)ip + ihl;
+  n -= ihl;
+
+  printip(ntohl(ip->ip_src));
+  printf(" > ");
+  printip(ntohl(ip->ip_dst));
+  printf(": ");
+
+  int off = ntohs(ip->ip_off);
+  if(off & IP_OFFMASK){
+    printf("fragment of id %d at %d, proto %d", ntohs(ip->ip_id), (off & IP_OFFMASK) * 8, ip->ip_p);
+    return;
+  }
+
+  if(ip->ip_p == IPPROTO_UDP && n >= sizeof(struct udp)){
+    struct udp *udp = (struct udp *)p;
+    printf("UDP %d > %d, length %d", ntohs(udp->sport), ntohs(udp->dport),
+           ntohs(udp->ulen) - (int)sizeof(*udp));
+  } else if(ip->ip_p == IPPROTO_TCP && n >= sizeof(struct tcp)){
+    struct tcp *th = (struct tcp *)p;
+    int dlen = ntohs(ip->ip_len) - ihl - (th->off >> 4) * 4;
+    printf("TCP %d > %d [%s%s%s%s%s] seq %d", ntohs(th->sport), ntohs(th->dport),
+           th->flags & TCP_SYN ? "S" : "", th->flags & TCP_FIN ? "F" : "",
+           th->flags & TCP_RST ? "R" : "", th->flags & TCP_PSH ? "P" : "",
+           th->flags & TCP_ACK ? "." : "", ntohl(th->seq));
+    if(th->flags & TCP_ACK)
+      printf(" ack %d", ntohl(th->ack));
+    printf(" win %d, length %d", ntohs(th->win), dlen);
+  } else if(ip->ip_p == IPPROTO_ICMP && n >= sizeof(struct icmp)){
+    struct icmp *icmp = (struct icmp *)p;
+    if(icmp->type == ICMP_ECHO)
+      printf("ICMP echo request, id %d, seq %d", ntohs(icmp->id), ntohs(icmp->seq));
+    else if(icmp->type == ICMP_ECHOREPLY)
+      printf("ICMP echo reply, id %d, seq %d", ntohs(icmp->id), ntohs(icmp->seq));
+    else
+      printf("ICMP type %d, code %d", icmp->type, icmp->code);
+  } else {
+    printf("proto %d, length %d", ip->ip_p, ntohs(ip->ip_len) - ihl);
+  }
+}
+
+void
+printrec(struct pcaprec *r)
+{
+  static char *dirs[] = { "in ", "out", "lo " };
+
+  if(r->drops > 0)
+    printf("(%d frames dropped)\n", r->drops);
+  printtime(r->usec);
+  printf(" %s ", dirs[r->dir]);
+
+  struct eth *eth = (struct eth *)r->data;
+  int n = r->caplen - sizeof(*eth);
+  if(n < 0){
+    printf("runt frame, length %d\n", r->len);
+    return;
+  }
+  int type = ntohs(eth->type);
+  if(type == ETHTYPE_ARP && n >= sizeof(struct arp))
+    printarp((struct arp *)(eth + 1));
+  else if(type == ETHTYPE_IP && n >= sizeof(struct ip))
+    printipv4((struct ip *)(eth + 1), n);
+  else
+    printf("ethertype 0x%x", type);
+  printf(", frame %d bytes\n", r->len);
+}
+
+// capture only frames to or from port: a filter program
+// that accepts a match on either port, and drops the rest.
+int
+portfilter(int port)
+{
+  struct pf_insn prog[] = {
+    { PF_SPORT, 1, 0, 0xffff, port },
+    { PF_DPORT, 0, 1, 0xffff, port },
+    { PF_RET, 0, 0, 0, 1 },
+    { PF_RET, 0, 0, 0, 0 },
+  };
+  return setfilter(PF_CAPTURE, prog, 4);
+}
+
+int
+main(int argc, char *argv[])
+{
+  static struct pcaprec recs[8];
+  int port = -1;
+
+  if(argc >= 3 && strcmp(argv[1], "-p") == 0){
+    port = atoi(argv[2]);
+    argc -= 2;
+    argv += 2;
+  }
+  if(argc > 2){
+    fprintf(2, "usage: tcpdump [-p port] [count]\n");
+    exit(1);
+  }
+  int count = argc == 2 ? atoi(argv[1]) : -1;
+
+  int r;
+  if((r = setfilter(PF_CAPTURE, 0, 0)) != 0 ||
+     (port >= 0 && (r = portfilter(port)) != 0)){
+    fprintf(2, "tcpdump: setfilter() failed (%d)\n", r);
+    exit(1);
+  }
+  if((r = pcapture(PCAP_START, 0, 0)) != 0){
+    fprintf(2, "tcpdump: pcapture() failed (%d)\n", r);
+    exit(1);
+  }
+  while(count != 0){
+    int n = pcapture(PCAP_READ, recs, count > 0 && count < 8 ? count : 8);
+    if(n <= 0){
+      fprintf(2, "tcpdump: pcapture() failed (%d)\n", n);
+      break;
+    }
+    for(int i = 0; i < n; i++)
+      printrec(&recs[i]);
+    if(count > 0)
+      count -= n;
+  }
+  pcapture(PCAP_STOP, 0, 0);
+  exit(0);
+}
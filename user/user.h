@@ -3,6 +3,14 @@ typedef unsigned long size_t;
 typedef long int off_t;
 #endif
 struct stat;
+struct netmsg;
+struct nicstats;
+struct portstats;
+struct sockaddr_in;
+struct netconf;
+struct pcaprec;
+struct pf_insn;
+struct netstat;
 
 // system calls
 int fork(void);
@@ -31,6 +39,33 @@ int bind(uint16);
 int unbind(uint16);
 int send(uint16, uint32, uint16, char *, uint32);
 int recv(uint16, uint32*, uint16*, char *, uint32);
+int netselftest(void);
+int resolve(char *, uint32 *);
+int recvmmsg(uint16, struct netmsg *, int, int);
+int bindflags(uint16, int);
+int nicstats(struct nicstats *);
+int linkstatus(void);
+int recvtimeout(uint16, uint32*, uint16*, char *, uint32, int);
+int loglevel(int);
+int portstats(uint16, struct portstats *);
+int connect(uint16, uint32, uint16);
+int joinmulticast(uint16, uint32);
+int udpcksum(int);
+int netpoll(uint16*, int, int);
+int queuelen(uint16, int);
+int socket(uint16);
+int sendto(uint16, char *, uint32, struct sockaddr_in *);
+int recvfrom(uint16, char *, uint32, struct sockaddr_in *);
+int netconf(struct netconf *, struct netconf *);
+int tcplisten(uint16);
+int tcpaccept(int);
+int tcpconnect(uint32, uint16);
+int rawsocket(int, uint32);
+int dropnetpriv(void);
+int pcapture(int, struct pcaprec *, int);
+int setfilter(int, struct pf_insn *, int);
+int netstat(struct netstat *, int);
+int dhcp(void);
 #endif
 #ifdef LAB_PGTBL
 int ugetpid(void);
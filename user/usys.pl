@@ -42,3 +42,30 @@ entry("send");
 entry("recv");
 entry("pgpte");
 entry("kpgtbl");
+entry("netselftest");
+entry("resolve");
+entry("recvmmsg");
+entry("bindflags");
+entry("nicstats");
+entry("linkstatus");
+entry("recvtimeout");
+entry("loglevel");
+entry("portstats");
+entry("connect");
+entry("joinmulticast");
+entry("udpcksum");
+entry("netpoll");
+entry("queuelen");
+entry("socket");
+entry("sendto");
+entry("recvfrom");
+entry("netconf");
+entry("tcplisten");
+entry("tcpaccept");
+entry("tcpconnect");
+entry("rawsocket");
+entry("dropnetpriv");
+entry("pcapture");
+entry("setfilter");
+entry("netstat");
+entry("dhcp");